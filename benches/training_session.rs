@@ -3,17 +3,30 @@ extern crate criterion;
 
 use rand_pcg::Pcg64Mcg;
 
-use criterion::Criterion;
+use criterion::{BatchSize, Criterion};
 
 use taxi::doormax::DoorMax;
 use taxi::factoredrmax::FactoredRMax;
-use taxi::qlearner::QLearner;
+use taxi::maxq::MaxQ;
+use taxi::options::{OptionQLearner, TaskOption};
+use taxi::qlearner::{QLearner, QLearnerReplay};
 use taxi::rmax::RMax;
 use taxi::runner::{run_training_session, Probe};
 use taxi::state::State;
-use taxi::world::{Costs, World};
-
-criterion_group!(trainers, qlearner, rmax, factored_rmax, doormax);
+use taxi::world::World;
+
+criterion_group!(
+    trainers,
+    qlearner,
+    qlearner_replay,
+    rmax,
+    factored_rmax,
+    maxq,
+    maxq_training,
+    maxq_construction,
+    doormax,
+    option_qlearner
+);
 criterion_main!(trainers);
 
 struct SessionData {
@@ -23,21 +36,7 @@ struct SessionData {
 
 impl Default for SessionData {
     fn default() -> SessionData {
-        // let world_str = "\
-        //                  ┌───┬─────┐\n\
-        //                  │R .│. . G│\n\
-        //                  │   │     │\n\
-        //                  │. .│. . .│\n\
-        //                  │         │\n\
-        //                  │. . . . .│\n\
-        //                  │         │\n\
-        //                  │.│. .│. .│\n\
-        //                  │ │   │   │\n\
-        //                  │Y│. .│B .│\n\
-        //                  └─┴───┴───┘\n\
-        //                  ";
-
-        // let world = World::build_from_str(world_str, Costs::default()).unwrap();
+        // let world = World::standard("dietterich5x5").unwrap();
 
         // let probes = vec![
         //     Probe::new(State::build(&world, (2, 2), Some('Y'), 'R').unwrap(), 10),
@@ -48,17 +47,7 @@ impl Default for SessionData {
         //     Probe::new(State::build(&world, (2, 2), Some('B'), 'G').unwrap(), 13),
         // ];
 
-        let world_str = "\
-                         ┌─┬───┐\n\
-                         │R│. G│\n\
-                         │ │   │\n\
-                         │. . .│\n\
-                         │     │\n\
-                         │Y B .│\n\
-                         └─────┘\n\
-                         ";
-
-        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+        let world = World::standard("small3x3").unwrap();
 
         let probes = vec![
             Probe::new(State::build(&world, (1, 1), Some('Y'), 'R').unwrap(), 4),
@@ -87,6 +76,20 @@ fn qlearner(c: &mut Criterion) {
     });
 }
 
+fn qlearner_replay(c: &mut Criterion) {
+    let data = SessionData::default();
+    let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    c.bench_function("qmax_replay", move |b| {
+        b.iter(|| {
+            let mut qlearner = QLearnerReplay::new(&data.world, 0.1, 0.3, 0.6, 1000, 8);
+            let rng = &mut source_rng.clone();
+
+            run_training_session(&data.world, &data.probes, 1, 100, &mut qlearner, rng)
+        })
+    });
+}
+
 fn rmax(c: &mut Criterion) {
     let data = SessionData::default();
     let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
@@ -115,6 +118,46 @@ fn factored_rmax(c: &mut Criterion) {
     });
 }
 
+fn maxq(c: &mut Criterion) {
+    let data = SessionData::default();
+    let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    c.bench_function("maxq", move |b| {
+        b.iter(|| {
+            let mut maxq = MaxQ::new(&data.world, 0.1, 0.3, 0.6, false);
+            let rng = &mut source_rng.clone();
+
+            run_training_session(&data.world, &data.probes, 1, 100, &mut maxq, rng)
+        })
+    });
+}
+
+// maxq without building the solver, which is done outside the measurement.
+fn maxq_training(c: &mut Criterion) {
+    let data = SessionData::default();
+    let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    c.bench_function("maxq_training", move |b| {
+        b.iter_batched(
+            || MaxQ::new(&data.world, 0.1, 0.3, 0.6, false),
+            |mut maxq| {
+                let rng = &mut source_rng.clone();
+
+                run_training_session(&data.world, &data.probes, 1, 100, &mut maxq, rng)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn maxq_construction(c: &mut Criterion) {
+    let data = SessionData::default();
+
+    c.bench_function("maxq_construction", move |b| {
+        b.iter(|| MaxQ::new(&data.world, 0.1, 0.3, 0.6, false))
+    });
+}
+
 fn doormax(c: &mut Criterion) {
     let data = SessionData::default();
     let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
@@ -128,3 +171,18 @@ fn doormax(c: &mut Criterion) {
         })
     });
 }
+
+fn option_qlearner(c: &mut Criterion) {
+    let data = SessionData::default();
+    let source_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    c.bench_function("option_qlearner", move |b| {
+        b.iter(|| {
+            let options = TaskOption::standard_options(&data.world);
+            let mut option_qlearner = OptionQLearner::new(&data.world, options, 0.1, 0.3, 0.6);
+            let rng = &mut source_rng.clone();
+
+            run_training_session(&data.world, &data.probes, 1, 100, &mut option_qlearner, rng)
+        })
+    });
+}
@@ -2,7 +2,7 @@ use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Actions {
     North,
     South,
@@ -10,10 +10,11 @@ pub enum Actions {
     West,
     PickUp,
     DropOff,
+    Wait,
 }
 
 impl Actions {
-    pub const NUM_ELEMENTS: usize = 6;
+    pub const NUM_ELEMENTS: usize = 7;
 
     pub fn to_index(self) -> usize {
         match self {
@@ -23,6 +24,7 @@ impl Actions {
             Actions::West => 3,
             Actions::PickUp => 4,
             Actions::DropOff => 5,
+            Actions::Wait => 6,
         }
     }
 
@@ -34,6 +36,7 @@ impl Actions {
             3 => Some(Actions::West),
             4 => Some(Actions::PickUp),
             5 => Some(Actions::DropOff),
+            6 => Some(Actions::Wait),
             _ => None,
         }
     }
@@ -47,9 +50,10 @@ impl Distribution<Actions> for Standard {
             Actions::West,
             Actions::PickUp,
             Actions::DropOff,
+            Actions::Wait,
         ];
 
-        actions[rng.gen_range(0, 6)]
+        actions[rng.gen_range(0, Actions::NUM_ELEMENTS)]
     }
 }
 
@@ -62,6 +66,7 @@ impl fmt::Display for Actions {
             Actions::West => write!(f, "W"),
             Actions::PickUp => write!(f, "P"),
             Actions::DropOff => write!(f, "D"),
+            Actions::Wait => write!(f, "-"),
         }
     }
 }
@@ -71,12 +76,12 @@ mod test_actions {
 
     use super::*;
 
-    use rand;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64Mcg;
     use std::f64;
 
     fn find_action(desired_action: Actions, max_iterations: u32) -> bool {
-        let mut rng = rand::thread_rng();
+        let mut rng = Pcg64Mcg::seed_from_u64(desired_action.to_index() as u64);
 
         let mut iterations_remaining = max_iterations;
         loop {
@@ -130,13 +135,19 @@ mod test_actions {
         assert!(found_action);
     }
 
+    #[test]
+    fn random_action_wait() {
+        let found_action = find_action(Actions::Wait, 500);
+        assert!(found_action);
+    }
+
     #[test]
     fn distribution() {
         let max_iterations = 1_000_000;
 
         let mut counts = vec![0.0f64; Actions::NUM_ELEMENTS];
 
-        let mut rng = rand::thread_rng();
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
         for _ in 0..max_iterations {
             let action: Actions = rng.gen();
 
@@ -144,7 +155,7 @@ mod test_actions {
         }
 
         // chi-squared should not exceed this for 95% confidence.
-        let p_05 = 11.07;
+        let p_05 = 12.59;
 
         let expected_count = (max_iterations as f64) / (counts.len() as f64);
 
@@ -191,6 +202,12 @@ mod test_actions {
             counts[Actions::DropOff.to_index()] / expected_count
         );
 
+        println!(
+            "wait count = {}, ratio = {}",
+            counts[Actions::Wait.to_index()],
+            counts[Actions::Wait.to_index()] / expected_count
+        );
+
         println!("chi-squared = {}, 95% confidence = {}", chi_sqr, p_05);
 
         assert!(chi_sqr < p_05);
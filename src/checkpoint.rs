@@ -0,0 +1,434 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::world::World;
+
+// Bump whenever the saved form of any solver changes, older checkpoints are
+// then refused rather than read into the wrong shape.
+pub const CURRENT_CHECKPOINT_VERSION: u32 = 2;
+
+pub enum Error {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Binary(serde_cbor::Error),
+    Version { found: u32, expected: u32 },
+    NumStates { found: usize, expected: usize },
+    Abstraction,
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref io_error) => write!(f, "Failed to access checkpoint:\n{:?}", io_error),
+            Error::Format(ref format_error) => {
+                write!(f, "Failed to read checkpoint:\n{:?}", format_error)
+            }
+            Error::Binary(ref binary_error) => {
+                write!(f, "Failed to read binary checkpoint:\n{:?}", binary_error)
+            }
+            Error::Version { found, expected } => write!(
+                f,
+                "Checkpoint has version {}, but only version {} can be loaded.",
+                found, expected
+            ),
+            Error::NumStates { found, expected } => write!(
+                f,
+                "Checkpoint has {} states, but the world needs {}.",
+                found, expected
+            ),
+            Error::Abstraction => write!(
+                f,
+                "A learner with a state abstraction cannot be saved, as the \
+                 abstraction is a function."
+            ),
+        }
+    }
+}
+
+// Read first, so a checkpoint from another version is refused before its
+// solver is deserialized.  The number of states is only checked against
+// the solver, which knows how it indexes states.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    num_states: usize,
+}
+
+#[derive(Serialize)]
+struct CheckpointRef<'a, T> {
+    version: u32,
+    num_states: usize,
+    solver: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Checkpoint<T> {
+    solver: T,
+}
+
+fn check_version(header: &Header) -> Result<(), Error> {
+    if header.version != CURRENT_CHECKPOINT_VERSION {
+        return Err(Error::Version {
+            found: header.version,
+            expected: CURRENT_CHECKPOINT_VERSION,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_num_states<T: Persist>(world: &World, header: &Header, solver: T) -> Result<T, Error> {
+    let expected = solver.expected_num_states(world);
+    if header.num_states != expected {
+        return Err(Error::NumStates {
+            found: header.num_states,
+            expected,
+        });
+    }
+
+    // The header could disagree with the tables themselves.
+    let found = solver.num_states();
+    if found != expected {
+        return Err(Error::NumStates { found, expected });
+    }
+
+    Ok(solver)
+}
+
+pub trait Persist: Serialize + DeserializeOwned {
+    // The number of states the solver's tables cover.
+    fn num_states(&self) -> usize;
+
+    // The number of states the tables must cover for this world, given how
+    // the solver indexes states.
+    fn expected_num_states(&self, world: &World) -> usize;
+
+    // Whether everything the solver needs is kept in its checkpoint.
+    fn check_saveable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn to_json(&self) -> Result<String, Error> {
+        self.check_saveable()?;
+
+        let checkpoint = CheckpointRef {
+            version: CURRENT_CHECKPOINT_VERSION,
+            num_states: self.num_states(),
+            solver: self,
+        };
+
+        serde_json::to_string(&checkpoint).map_err(Error::Format)
+    }
+
+    fn from_json(world: &World, json: &str) -> Result<Self, Error> {
+        let header: Header = serde_json::from_str(json).map_err(Error::Format)?;
+        check_version(&header)?;
+
+        let checkpoint: Checkpoint<Self> = serde_json::from_str(json).map_err(Error::Format)?;
+        check_num_states(world, &header, checkpoint.solver)
+    }
+
+    // The same checkpoint as to_json in CBOR, which keeps the floats of
+    // large tables in 9 bytes each rather than as decimal text.
+    fn to_binary(&self) -> Result<Vec<u8>, Error> {
+        self.check_saveable()?;
+
+        let checkpoint = CheckpointRef {
+            version: CURRENT_CHECKPOINT_VERSION,
+            num_states: self.num_states(),
+            solver: self,
+        };
+
+        serde_cbor::to_vec(&checkpoint).map_err(Error::Binary)
+    }
+
+    fn from_binary(world: &World, bytes: &[u8]) -> Result<Self, Error> {
+        let header: Header = serde_cbor::from_slice(bytes).map_err(Error::Binary)?;
+        check_version(&header)?;
+
+        let checkpoint: Checkpoint<Self> = serde_cbor::from_slice(bytes).map_err(Error::Binary)?;
+        check_num_states(world, &header, checkpoint.solver)
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_json()?).map_err(Error::Io)
+    }
+
+    fn load<P: AsRef<Path>>(world: &World, path: P) -> Result<Self, Error> {
+        let json = fs::read_to_string(path).map_err(Error::Io)?;
+        Self::from_json(world, &json)
+    }
+
+    fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_binary()?).map_err(Error::Io)
+    }
+
+    fn load_binary<P: AsRef<Path>>(world: &World, path: P) -> Result<Self, Error> {
+        let bytes = fs::read(path).map_err(Error::Io)?;
+        Self::from_binary(world, &bytes)
+    }
+}
+
+#[cfg(test)]
+mod test_checkpoint {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    use crate::qlearner::{InitStrategy, QLearner};
+    use crate::runner::Runner;
+    use crate::state::State;
+    use crate::state_indexer::StateIndexer;
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+
+    fn round_trip<T: Persist>(world: &World, solver: &T) -> T {
+        let json = solver.to_json().unwrap();
+        T::from_json(world, &json).unwrap()
+    }
+
+    fn trained_qlearner(world: &World) -> QLearner {
+        let mut qlearner = QLearner::new(world, 0.1, 0.9, 0.1);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        for _ in 0..50 {
+            let state = State::build_random(world, &mut rng).unwrap();
+            qlearner.learn(world, state, 20, &mut rng);
+        }
+
+        qlearner
+    }
+
+    #[test]
+    fn qlearner_round_trip() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = trained_qlearner(&world);
+
+        let loaded = round_trip(&world, &qlearner);
+
+        assert_eq!(loaded.to_json().unwrap(), qlearner.to_json().unwrap());
+        for state in crate::state::StateIterator::new(&world) {
+            assert_eq!(
+                loaded.greedy_action(&world, &state),
+                qlearner.greedy_action(&world, &state)
+            );
+        }
+    }
+
+    #[test]
+    fn save_and_load_file() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = trained_qlearner(&world);
+
+        let path =
+            std::env::temp_dir().join(format!("taxi_checkpoint_{}.json", std::process::id()));
+
+        qlearner.save(&path).unwrap();
+        let loaded = QLearner::load(&world, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_json().unwrap(), qlearner.to_json().unwrap());
+
+        assert_matches!(QLearner::load(&world, &path), Err(Error::Io(_)));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = trained_qlearner(&world);
+
+        let json_path =
+            std::env::temp_dir().join(format!("taxi_checkpoint_{}_b.json", std::process::id()));
+        let binary_path =
+            std::env::temp_dir().join(format!("taxi_checkpoint_{}.cbor", std::process::id()));
+
+        qlearner.save(&json_path).unwrap();
+        qlearner.save_binary(&binary_path).unwrap();
+        let json_size = std::fs::metadata(&json_path).unwrap().len();
+        let binary_size = std::fs::metadata(&binary_path).unwrap().len();
+        let loaded = QLearner::load_binary(&world, &binary_path).unwrap();
+        std::fs::remove_file(&json_path).unwrap();
+        std::fs::remove_file(&binary_path).unwrap();
+
+        assert!(binary_size < json_size);
+        assert_eq!(loaded.to_json().unwrap(), qlearner.to_json().unwrap());
+        assert_eq!(
+            loaded.value_function(&world),
+            qlearner.value_function(&world)
+        );
+
+        assert_matches!(
+            QLearner::load_binary(&world, &binary_path),
+            Err(Error::Io(_))
+        );
+    }
+
+    #[test]
+    fn binary_checks_version_and_num_states() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = trained_qlearner(&world);
+
+        let mut checkpoint: serde_cbor::Value =
+            serde_cbor::from_slice(&qlearner.to_binary().unwrap()).unwrap();
+        let fields = match checkpoint {
+            serde_cbor::Value::Map(ref mut fields) => fields,
+            _ => panic!("checkpoint is not a map"),
+        };
+        let key = |name: &str| serde_cbor::Value::Text(name.to_string());
+
+        fields.insert(
+            key("version"),
+            serde_cbor::Value::Integer((CURRENT_CHECKPOINT_VERSION + 1).into()),
+        );
+        let bytes = serde_cbor::to_vec(&checkpoint).unwrap();
+        assert_matches!(QLearner::from_binary(&world, &bytes), Err(Error::Version { found, expected })
+            if found == CURRENT_CHECKPOINT_VERSION + 1 && expected == CURRENT_CHECKPOINT_VERSION);
+
+        let fields = match checkpoint {
+            serde_cbor::Value::Map(ref mut fields) => fields,
+            _ => unreachable!(),
+        };
+        fields.insert(
+            key("version"),
+            serde_cbor::Value::Integer(CURRENT_CHECKPOINT_VERSION.into()),
+        );
+        fields.insert(key("num_states"), serde_cbor::Value::Integer(23));
+        let bytes = serde_cbor::to_vec(&checkpoint).unwrap();
+        assert_matches!(
+            QLearner::from_binary(&world, &bytes),
+            Err(Error::NumStates {
+                found: 23,
+                expected: 24
+            })
+        );
+
+        assert_matches!(
+            QLearner::from_binary(&world, b"not cbor"),
+            Err(Error::Binary(_))
+        );
+    }
+
+    #[test]
+    fn bumped_version_refused() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+
+        let mut checkpoint: serde_json::Value =
+            serde_json::from_str(&qlearner.to_json().unwrap()).unwrap();
+        checkpoint["version"] = serde_json::Value::from(CURRENT_CHECKPOINT_VERSION + 1);
+
+        let result = QLearner::from_json(&world, &checkpoint.to_string());
+        assert_matches!(result, Err(Error::Version { found, expected })
+            if found == CURRENT_CHECKPOINT_VERSION + 1 && expected == CURRENT_CHECKPOINT_VERSION);
+
+        let message = format!("{:?}", result.err().unwrap());
+        assert!(message.contains(&format!("version {}", CURRENT_CHECKPOINT_VERSION + 1)));
+    }
+
+    #[test]
+    fn other_world_refused() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+
+        let larger_world = "\
+                            ┌─────┐\n\
+                            │R . .│\n\
+                            │     │\n\
+                            │. . G│\n\
+                            └─────┘\n\
+                            ";
+        let larger_world = World::build_from_str(larger_world, Costs::default()).unwrap();
+
+        let json = qlearner.to_json().unwrap();
+        assert_matches!(
+            QLearner::from_json(&larger_world, &json),
+            Err(Error::NumStates {
+                found: 24,
+                expected: 36
+            })
+        );
+    }
+
+    #[test]
+    fn mis_sized_table_refused() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = trained_qlearner(&world);
+
+        let mut checkpoint: serde_json::Value =
+            serde_json::from_str(&qlearner.to_json().unwrap()).unwrap();
+        checkpoint["solver"]["qtable"].as_array_mut().unwrap().pop();
+
+        assert_matches!(
+            QLearner::from_json(&world, &checkpoint.to_string()),
+            Err(Error::NumStates {
+                found: 23,
+                expected: 24
+            })
+        );
+
+        // Even with a header to match.
+        checkpoint["num_states"] = serde_json::Value::from(23);
+        assert_matches!(
+            QLearner::from_json(&world, &checkpoint.to_string()),
+            Err(Error::NumStates {
+                found: 23,
+                expected: 24
+            })
+        );
+    }
+
+    #[test]
+    fn hidden_destination_round_trip() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = QLearner::with_state_indexer(
+            &world,
+            0.1,
+            0.9,
+            0.1,
+            InitStrategy::Optimistic,
+            StateIndexer::with_hidden_destination(&world),
+        );
+        assert_eq!(qlearner.num_states(), 36);
+
+        let loaded = round_trip(&world, &qlearner);
+        assert_eq!(loaded.to_json().unwrap(), qlearner.to_json().unwrap());
+    }
+
+    #[test]
+    fn abstracted_learner_refused() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let qlearner = QLearner::with_abstraction(
+            &world,
+            |state: &State| state.get_taxi().x as usize,
+            2,
+            0.1,
+            0.9,
+            0.1,
+        );
+        assert_eq!(qlearner.num_states(), 2);
+
+        assert_matches!(qlearner.to_json(), Err(Error::Abstraction));
+
+        let path = std::env::temp_dir().join(format!(
+            "taxi_abstracted_checkpoint_{}.json",
+            std::process::id()
+        ));
+        assert_matches!(qlearner.save(&path), Err(Error::Abstraction));
+        assert!(!path.exists());
+
+        assert_matches!(qlearner.to_binary(), Err(Error::Abstraction));
+    }
+}
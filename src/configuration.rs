@@ -1,19 +1,26 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 
-use toml;
-
-use taxi::world::Costs;
+use taxi::actions::Actions;
+use taxi::options::TaskOption;
+use taxi::qlearner::{EpsilonSchedule, InitStrategy};
+use taxi::rmax::RMaxParams;
+use taxi::runner::{RewardTransform, StartStates};
+use taxi::world::{Costs, World};
 
 #[derive(Deserialize, Debug, Clone, Copy)]
 pub enum SolverChoice {
     Random,
     QLearner,
+    QLearnerReplay,
+    SparseQLearner,
     RMax,
     FactoredRMax,
     MaxQ,
     DoorMax,
+    OptionQLearner,
 }
 
 impl fmt::Display for SolverChoice {
@@ -21,10 +28,111 @@ impl fmt::Display for SolverChoice {
         match *self {
             SolverChoice::Random => write!(f, "Random"),
             SolverChoice::QLearner => write!(f, "Q-Learner"),
+            SolverChoice::QLearnerReplay => write!(f, "Q-Learner Replay"),
+            SolverChoice::SparseQLearner => write!(f, "Sparse Q-Learner"),
             SolverChoice::RMax => write!(f, "RMax"),
             SolverChoice::FactoredRMax => write!(f, "FactoredRMax"),
             SolverChoice::MaxQ => write!(f, "MaxQ"),
             SolverChoice::DoorMax => write!(f, "DoorMax"),
+            SolverChoice::OptionQLearner => write!(f, "Option Q-Learner"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum RewardTransformChoice {
+    #[default]
+    Identity,
+    Clip,
+    Normalize,
+}
+
+impl RewardTransformChoice {
+    pub fn transform(self) -> RewardTransform {
+        match self {
+            RewardTransformChoice::Identity => RewardTransform::Identity,
+            RewardTransformChoice::Clip => RewardTransform::Clip,
+            RewardTransformChoice::Normalize => RewardTransform::Normalize,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum EpsilonScheduleChoice {
+    #[default]
+    Constant,
+    Glie {
+        c: f64,
+    },
+}
+
+impl EpsilonScheduleChoice {
+    pub fn schedule(self) -> EpsilonSchedule {
+        match self {
+            EpsilonScheduleChoice::Constant => EpsilonSchedule::Constant,
+            EpsilonScheduleChoice::Glie { c } => EpsilonSchedule::Glie { c },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum InitStrategyChoice {
+    #[default]
+    Optimistic,
+    Zero,
+    Random {
+        low: f64,
+        high: f64,
+        seed: u64,
+    },
+}
+
+impl InitStrategyChoice {
+    pub fn strategy(self) -> InitStrategy {
+        match self {
+            InitStrategyChoice::Optimistic => InitStrategy::Optimistic,
+            InitStrategyChoice::Zero => InitStrategy::Zero,
+            InitStrategyChoice::Random { low, high, seed } => {
+                InitStrategy::Random { low, high, seed }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum OptionSetChoice {
+    // Every primitive action and a Navigate for each fixed position.
+    #[default]
+    Standard,
+    // Only the Navigates, PickUp and DropOff.
+    Navigate,
+    // Every primitive action and a GoTo for each fixed position, which
+    // needs goto_actions.
+    GoTo,
+}
+
+impl OptionSetChoice {
+    pub fn options(self, world: &World) -> Vec<TaskOption> {
+        match self {
+            OptionSetChoice::Standard => TaskOption::standard_options(world),
+            OptionSetChoice::Navigate => TaskOption::navigate_options(world),
+            OptionSetChoice::GoTo => TaskOption::goto_options(world),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum StartStatesChoice {
+    #[default]
+    Waiting,
+    Any,
+}
+
+impl StartStatesChoice {
+    pub fn start_states(self) -> StartStates {
+        match self {
+            StartStatesChoice::Waiting => StartStates::Waiting,
+            StartStatesChoice::Any => StartStates::Any,
         }
     }
 }
@@ -38,7 +146,10 @@ pub trait ReportConfig {
 }
 
 #[derive(Deserialize, Debug)]
-pub struct RandomSolverConfig {}
+pub struct RandomSolverConfig {
+    // Relative action weights, in North, South, East, West, PickUp, DropOff, Wait order.
+    pub weights: Option<[f64; Actions::NUM_ELEMENTS]>,
+}
 
 impl ReportConfig for RandomSolverConfig {
     fn solver_choice(&self) -> SolverChoice {
@@ -46,11 +157,59 @@ impl ReportConfig for RandomSolverConfig {
     }
 }
 
+// Defaults for the hyperparameters a solver section leaves out, the values
+// the benches use.
+fn default_alpha() -> f64 {
+    0.1
+}
+
+fn default_gamma() -> f64 {
+    0.3
+}
+
+// DoorMax's bench plans much further ahead than the others.
+fn default_doormax_gamma() -> f64 {
+    0.95
+}
+
+fn default_epsilon() -> f64 {
+    0.6
+}
+
+fn default_known_count() -> f64 {
+    1.0
+}
+
+fn default_error_delta() -> f64 {
+    1.0e-6
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_max_iterations() -> usize {
+    RMaxParams::DEFAULT_MAX_ITERATIONS
+}
+
 #[derive(Deserialize, Debug)]
 pub struct QLearnerConfig {
+    #[serde(default = "default_alpha")]
     pub alpha: f64,
+    #[serde(default = "default_gamma")]
     pub gamma: f64,
+    #[serde(default = "default_epsilon")]
     pub epsilon: f64,
+    #[serde(default)]
+    pub epsilon_schedule: EpsilonScheduleChoice,
+    #[serde(default)]
+    pub init: InitStrategyChoice,
+    // Caps max_trial_steps for this solver only.
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    // Break ties between the best actions of an attempt towards the goal.
+    #[serde(default)]
+    pub progress_ties: bool,
 
     pub report: bool,
 }
@@ -65,15 +224,83 @@ impl ReportConfig for QLearnerConfig {
     }
 }
 
+#[derive(Deserialize, Debug)]
+pub struct QLearnerReplayConfig {
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    #[serde(default = "default_gamma")]
+    pub gamma: f64,
+    #[serde(default = "default_epsilon")]
+    pub epsilon: f64,
+    pub buffer_size: usize,
+    pub batch_size: usize,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+
+    pub report: bool,
+}
+
+impl ReportConfig for QLearnerReplayConfig {
+    fn solver_choice(&self) -> SolverChoice {
+        SolverChoice::QLearnerReplay
+    }
+
+    fn report(&self) -> bool {
+        self.report
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SparseQLearnerConfig {
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    #[serde(default = "default_gamma")]
+    pub gamma: f64,
+    #[serde(default = "default_epsilon")]
+    pub epsilon: f64,
+    #[serde(default)]
+    pub epsilon_schedule: EpsilonScheduleChoice,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+
+    pub report: bool,
+}
+
+impl ReportConfig for SparseQLearnerConfig {
+    fn solver_choice(&self) -> SolverChoice {
+        SolverChoice::SparseQLearner
+    }
+
+    fn report(&self) -> bool {
+        self.report
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct RMaxConfig {
+    #[serde(default = "default_gamma")]
     pub gamma: f64,
+    #[serde(default = "default_known_count")]
     pub known_count: f64,
+    #[serde(default = "default_error_delta")]
     pub error_delta: f64,
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
 
     pub report: bool,
 }
 
+impl RMaxConfig {
+    pub fn params(&self, world: &World) -> RMaxParams {
+        RMaxParams {
+            value_iteration_tolerance: self.error_delta,
+            known_threshold: self.known_count,
+            max_iterations: self.max_iterations,
+            ..RMaxParams::new(world, self.gamma)
+        }
+    }
+}
+
 impl ReportConfig for RMaxConfig {
     fn solver_choice(&self) -> SolverChoice {
         SolverChoice::RMax
@@ -86,13 +313,29 @@ impl ReportConfig for RMaxConfig {
 
 #[derive(Deserialize, Debug)]
 pub struct FactoredRMaxConfig {
+    #[serde(default = "default_gamma")]
     pub gamma: f64,
+    #[serde(default = "default_known_count")]
     pub known_count: f64,
+    #[serde(default = "default_error_delta")]
     pub error_delta: f64,
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
 
     pub report: bool,
 }
 
+impl FactoredRMaxConfig {
+    pub fn params(&self, world: &World) -> RMaxParams {
+        RMaxParams {
+            value_iteration_tolerance: self.error_delta,
+            known_threshold: self.known_count,
+            max_iterations: self.max_iterations,
+            ..RMaxParams::new(world, self.gamma)
+        }
+    }
+}
+
 impl ReportConfig for FactoredRMaxConfig {
     fn solver_choice(&self) -> SolverChoice {
         SolverChoice::FactoredRMax
@@ -105,9 +348,14 @@ impl ReportConfig for FactoredRMaxConfig {
 
 #[derive(Deserialize, Debug)]
 pub struct MaxQConfig {
+    #[serde(default = "default_alpha")]
     pub alpha: f64,
+    #[serde(default = "default_gamma")]
     pub gamma: f64,
+    #[serde(default = "default_epsilon")]
     pub epsilon: f64,
+    #[serde(default)]
+    pub init: InitStrategyChoice,
     pub report: bool,
     pub show_learning: bool,
 }
@@ -122,12 +370,42 @@ impl ReportConfig for MaxQConfig {
     }
 }
 
+#[derive(Deserialize, Debug)]
+pub struct OptionQLearnerConfig {
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    #[serde(default = "default_gamma")]
+    pub gamma: f64,
+    #[serde(default = "default_epsilon")]
+    pub epsilon: f64,
+    #[serde(default)]
+    pub options: OptionSetChoice,
+    pub report: bool,
+}
+
+impl ReportConfig for OptionQLearnerConfig {
+    fn solver_choice(&self) -> SolverChoice {
+        SolverChoice::OptionQLearner
+    }
+
+    fn report(&self) -> bool {
+        self.report
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct DoorMaxConfig {
+    #[serde(default = "default_doormax_gamma")]
     pub gamma: f64,
     pub use_reward_learner: bool,
+    #[serde(default = "default_known_count")]
     pub known_count: f64,
+    #[serde(default = "default_error_delta")]
     pub error_delta: f64,
+    // Break ties between the best actions by trying unknown ones first,
+    // then those ending nearest the goal, rather than by chance.
+    #[serde(default)]
+    pub directed_exploration: bool,
 
     pub report: bool,
 }
@@ -148,6 +426,10 @@ pub struct Probe {
     pub passenger_loc: Option<char>,
     pub destination_loc: char,
     pub max_steps: usize,
+    pub expected_steps: Option<usize>,
+    // How much the probe counts in the weighted probe metrics.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -157,14 +439,45 @@ pub struct Replay {
     pub passenger_loc: Option<char>,
     pub destination_loc: char,
     pub max_steps: usize,
+    // Where to save the replayed attempt, for taxi --replay-file.
+    #[serde(default)]
+    pub save_to: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(default)]
 pub struct CostsConfig {
     pub movement: f64,
     pub miss_pickup: f64,
     pub miss_dropoff: f64,
     pub empty_dropoff: f64,
+    pub delivery_reward: f64,
+    // Defaults to the movement cost.
+    pub wait: Option<f64>,
+    // Defaults to the movement cost.
+    pub blocked_movement: Option<f64>,
+    pub pickup: f64,
+    // Per destination replacements for delivery_reward, keyed by the fixed
+    // position's character.
+    pub delivery_rewards: BTreeMap<char, f64>,
+}
+
+impl Default for CostsConfig {
+    fn default() -> CostsConfig {
+        let costs = Costs::default();
+
+        CostsConfig {
+            movement: costs.movement,
+            miss_pickup: costs.miss_pickup,
+            miss_dropoff: costs.miss_dropoff,
+            empty_dropoff: costs.empty_dropoff,
+            delivery_reward: costs.delivery_reward,
+            wait: None,
+            blocked_movement: None,
+            pickup: costs.pickup,
+            delivery_rewards: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -172,8 +485,18 @@ pub struct CostsConfig {
 pub struct Configuration {
     pub world: String,
     pub costs: CostsConfig,
+    pub reward_transform: RewardTransformChoice,
+    pub start_states: StartStatesChoice,
+    pub stop_on_convergence: bool,
+    pub destination_churn: f64,
+    pub passenger_wander: f64,
+    pub pickup_refusal: f64,
+    pub goto_actions: bool,
     // TOML only allows for signed 64 bit integers, not unsigned.
     pub root_seed: Option<(i64, i64)>,
+    // Seeds destination churn and a wandering passenger apart from the
+    // solvers, so every session sees the same environment.
+    pub env_seed: Option<(i64, i64)>,
     pub rerun_seeds: Vec<(i64, i64)>,
     pub probes: Vec<Probe>,
     pub max_trials: usize,
@@ -181,10 +504,13 @@ pub struct Configuration {
     pub sessions: usize,
     pub random_solver: Option<RandomSolverConfig>,
     pub q_learner: Option<QLearnerConfig>,
+    pub q_learner_replay: Option<QLearnerReplayConfig>,
+    pub sparse_q_learner: Option<SparseQLearnerConfig>,
     pub r_max: Option<RMaxConfig>,
     pub factored_r_max: Option<FactoredRMaxConfig>,
     pub max_q: Option<MaxQConfig>,
     pub door_max: Option<DoorMaxConfig>,
+    pub option_q_learner: Option<OptionQLearnerConfig>,
     pub replay: Option<Replay>,
 }
 
@@ -208,6 +534,7 @@ impl Configuration {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     OpenFailure {
         filename: String,
@@ -258,17 +585,18 @@ impl Default for Configuration {
                          └─┴───┴───┘\n\
                          ";
 
-        let costs = CostsConfig {
-            movement: Costs::default().movement,
-            miss_pickup: Costs::default().miss_pickup,
-            miss_dropoff: Costs::default().miss_dropoff,
-            empty_dropoff: Costs::default().empty_dropoff,
-        };
-
         Configuration {
             world: String::from(world_str),
-            costs,
+            costs: CostsConfig::default(),
+            reward_transform: RewardTransformChoice::default(),
+            start_states: StartStatesChoice::default(),
+            stop_on_convergence: false,
+            destination_churn: 0.0,
+            passenger_wander: 0.0,
+            pickup_refusal: 0.0,
+            goto_actions: false,
             root_seed: None,
+            env_seed: None,
             rerun_seeds: Vec::new(),
             probes: Vec::new(),
             max_trials: 1,
@@ -276,10 +604,13 @@ impl Default for Configuration {
             sessions: 0,
             random_solver: None,
             q_learner: None,
+            q_learner_replay: None,
+            sparse_q_learner: None,
             r_max: None,
             factored_r_max: None,
             max_q: None,
             door_max: None,
+            option_q_learner: None,
             replay: None,
         }
     }
@@ -39,14 +39,40 @@ impl MeasureDistribution {
         }
     }
 
+    // NaN until a value has been added.
+    pub fn mean(&self) -> f64 {
+        if self.count < 1.0 {
+            f64::NAN
+        } else {
+            self.mean
+        }
+    }
+
+    // Population variance, dividing by the count.
+    pub fn variance(&self) -> f64 {
+        if self.count < 1.0 {
+            f64::NAN
+        } else {
+            self.mean_2 / self.count
+        }
+    }
+
+    // Sample variance, dividing by one less than the count.
+    pub fn sample_variance(&self) -> f64 {
+        if self.count < 2.0 {
+            f64::NAN
+        } else {
+            self.mean_2 / (self.count - 1.0)
+        }
+    }
+
     pub fn get_distribution(&self) -> (f64, f64) {
         if self.count < 1.0 {
             (f64::NAN, f64::INFINITY)
         } else if self.count < 2.0 {
             (self.mean, f64::NAN)
         } else {
-            let variance = self.mean_2 / (self.count - 1.0);
-            (self.mean, variance.sqrt())
+            (self.mean, self.sample_variance().sqrt())
         }
     }
 
@@ -15,6 +15,7 @@ use rand_pcg::Pcg64Mcg;
 use crate::actions::Actions;
 use crate::state::{State, StateIterator};
 use crate::state_indexer::StateIndexer;
+use crate::util::argmax_available_action;
 use crate::world::World;
 
 use self::condition::Condition;
@@ -22,7 +23,8 @@ use self::mcelearner::MCELearner;
 use self::multirewardlearner::MultiRewardLearner;
 use self::reward::Rewards;
 
-use crate::runner::{Attempt, Runner};
+use crate::model::LearnedModel;
+use crate::runner::{Attempt, Runner, Trial};
 
 #[derive(Debug, Clone)]
 pub struct DoorMax {
@@ -35,12 +37,15 @@ pub struct DoorMax {
     rewardlearner: MultiRewardLearner,
 
     rewards: Rewards,
-    known_reward_count: f64,
 
     value_table: Vec<f64>,
 
     gamma: f64,
     error_delta: f64,
+
+    // Whether learning breaks ties between the best actions by trying
+    // unknown ones first, then those ending nearest the current goal.
+    directed_exploration: bool,
 }
 
 impl DoorMax {
@@ -69,18 +74,55 @@ impl DoorMax {
             mcelearner: MCELearner::new(),
 
             use_reward_learner,
-            rewardlearner: MultiRewardLearner::new(error_delta),
+            rewardlearner: MultiRewardLearner::new(),
 
             rewards,
-            known_reward_count,
 
             value_table,
 
             gamma,
             error_delta,
+
+            directed_exploration: false,
+        }
+    }
+
+    pub fn with_directed_exploration(self, directed_exploration: bool) -> DoorMax {
+        DoorMax {
+            directed_exploration,
+            ..self
         }
     }
 
+    // Fraction of the state-actions whose outcome and reward are both
+    // known.
+    pub fn coverage(&self, world: &World) -> f64 {
+        let mut num_known = 0;
+        let mut num_total = 0;
+
+        for state in StateIterator::new(world) {
+            for action_index in 0..Actions::NUM_ELEMENTS {
+                let action = Actions::from_index(action_index).unwrap();
+
+                num_total += 1;
+                if self.is_known(world, &state, action) {
+                    num_known += 1;
+                }
+            }
+        }
+
+        if num_total > 0 {
+            num_known as f64 / num_total as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn is_known(&self, world: &World, state: &State, action: Actions) -> bool {
+        self.measure_reward(world, state, action).is_some()
+            && matches!(self.mcelearner.predict(world, state, action), Ok(Some(_)))
+    }
+
     fn apply_experience(
         &mut self,
         world: &World,
@@ -148,6 +190,16 @@ impl DoorMax {
         Ok(best_value)
     }
 
+    // Every action's value, indexed by Actions::to_index.
+    fn action_values(&self, world: &World, state: &State) -> Option<Vec<f64>> {
+        self.state_indexer.get_index(world, state)?;
+
+        (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .map(|action| self.measure_value(world, state, action).ok())
+            .collect()
+    }
+
     fn select_best_action<R: Rng>(
         &self,
         world: &World,
@@ -178,6 +230,78 @@ impl DoorMax {
         Ok(best_action)
     }
 
+    // Like select_best_action, but with directed exploration the ties are
+    // broken by exploration_priority before falling back to chance.
+    fn select_learning_action<R: Rng>(
+        &self,
+        world: &World,
+        state: &State,
+        rng: &mut R,
+    ) -> Result<Option<Actions>, effect::Error> {
+        if !self.directed_exploration {
+            return self.select_best_action(world, state, rng);
+        }
+
+        let mut best_value = -f64::MAX;
+        let mut best_actions = Vec::new();
+
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            let action_value = self.measure_value(world, state, action)?;
+
+            if approx_eq!(f64, action_value, best_value, ulps = 2) {
+                best_actions.push(action);
+            } else if action_value > best_value {
+                best_value = action_value;
+                best_actions.clear();
+                best_actions.push(action);
+            }
+        }
+
+        let mut best_priority = usize::MAX;
+        let mut best_action = None;
+        let mut num_found = 0;
+
+        for action in best_actions {
+            let priority = self.exploration_priority(world, state, action)?;
+
+            if priority == best_priority {
+                num_found += 1;
+
+                if 0 == rng.gen_range(0, num_found) {
+                    best_action = Some(action);
+                }
+            } else if priority < best_priority {
+                best_priority = priority;
+                best_action = Some(action);
+                num_found = 1;
+            }
+        }
+
+        Ok(best_action)
+    }
+
+    // Lower is explored first.  Unknown state-actions come before any known
+    // one, and known ones are ordered by how far their predicted state is
+    // from the taxi's goal.
+    fn exploration_priority(
+        &self,
+        world: &World,
+        state: &State,
+        action: Actions,
+    ) -> Result<usize, effect::Error> {
+        if self.measure_reward(world, state, action).is_none() {
+            return Ok(0);
+        }
+
+        match self.mcelearner.predict(world, state, action)? {
+            None => Ok(0),
+            Some(next_state) => Ok(next_state
+                .goal_distance(world)
+                .map_or(usize::MAX, |d| d + 1)),
+        }
+    }
+
     fn rebuild_value_table(&mut self, world: &World) -> Result<(), effect::Error> {
         for _ in 0..10_000 {
             let mut error = 0.0;
@@ -211,28 +335,31 @@ impl DoorMax {
         mut state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Result<Option<usize>, effect::Error> {
+    ) -> Result<Trial, effect::Error> {
+        let mut total_reward = 0.0;
+
         for step in 0..max_steps {
             if state.at_destination() {
-                return Ok(Some(step));
+                return Ok(Trial::new(Some(step), total_reward));
             }
 
             self.rebuild_value_table(world)?;
 
-            if let Some(next_action) = self.select_best_action(world, &state, rng)? {
+            if let Some(next_action) = self.select_learning_action(world, &state, rng)? {
                 let (reward, next_state) = state.apply_action(world, next_action);
+                total_reward += reward;
 
                 self.apply_experience(world, &state, next_action, &next_state, reward);
                 state = next_state;
             } else {
-                return Ok(None);
+                return Ok(Trial::new(None, total_reward));
             }
         }
 
         if state.at_destination() {
-            Ok(Some(max_steps))
+            Ok(Trial::new(Some(max_steps), total_reward))
         } else {
-            Ok(None)
+            Ok(Trial::new(None, total_reward))
         }
     }
 
@@ -290,6 +417,16 @@ impl DoorMax {
     }
 }
 
+// A prediction whose conditions conflict counts as unknown.
+impl LearnedModel for DoorMax {
+    fn predict(&self, world: &World, state: &State, action: Actions) -> Option<(State, f64)> {
+        let reward = self.measure_reward(world, state, action)?;
+        let next_state = self.mcelearner.predict(world, state, action).ok()??;
+
+        Some((next_state, reward))
+    }
+}
+
 impl Runner for DoorMax {
     fn learn<R: Rng>(
         &mut self,
@@ -297,7 +434,7 @@ impl Runner for DoorMax {
         state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize> {
+    ) -> Trial {
         self.learn(world, state, max_steps, rng).unwrap()
     }
 
@@ -315,6 +452,29 @@ impl Runner for DoorMax {
         self.solves(world, state, max_steps, rng).unwrap()
     }
 
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        let all_actions: Vec<Actions> = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .collect();
+        self.masked_greedy_action(world, state, &all_actions)
+    }
+
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        argmax_available_action(&self.action_values(world, state)?, available)
+    }
+
+    fn reset(&mut self) {
+        self.mcelearner = MCELearner::new();
+        self.rewardlearner = MultiRewardLearner::new();
+        self.rewards.reset();
+        self.value_table.iter_mut().for_each(|value| *value = 0.0);
+    }
+
     fn report_training_result(&self, world: &World, _steps: Option<usize>) {
         let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
 
@@ -325,17 +485,17 @@ impl Runner for DoorMax {
             if !state.at_destination() {
                 if let Some(next_action) = self.select_best_action(world, &state, &mut rng).unwrap()
                 {
-                    println!("===================");
-                    println!("{}", state.display(world));
-                    println!("Condition: {}", Condition::new(world, &state));
-                    println!("Best action: {}", next_action);
+                    info!("===================");
+                    info!("{}", state.display(world));
+                    info!("Condition: {}", Condition::new(world, &state));
+                    info!("Best action: {}", next_action);
 
                     if let Some(next_state) =
                         self.mcelearner.predict(world, &state, next_action).unwrap()
                     {
-                        println!("{}", next_state.display(world));
+                        info!("{}", next_state.display(world));
                     } else {
-                        println!("Situation unknown.");
+                        info!("Situation unknown.");
                     }
 
                     for action_index in 0..Actions::NUM_ELEMENTS {
@@ -349,7 +509,7 @@ impl Runner for DoorMax {
                             };
 
                         let action_value = self.measure_value(world, &state, action).unwrap();
-                        println!(
+                        info!(
                             "{} - {} + {} = {}",
                             action,
                             reward,
@@ -361,11 +521,101 @@ impl Runner for DoorMax {
             }
         }
 
-        println!("MCELearner:");
-        println!("{}", self.mcelearner);
+        info!("MCELearner:");
+        info!("{}", self.mcelearner);
 
         if self.use_reward_learner {
-            println!("{}", self.rewardlearner);
+            info!("{}", self.rewardlearner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_doormax {
+    use super::*;
+
+    use rand::SeedableRng;
+
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌─────┐\n\
+                         │R . G│\n\
+                         │  ─  │\n\
+                         │. . .│\n\
+                         │     │\n\
+                         │Y . B│\n\
+                         └─────┘\n\
+                         ";
+
+    // Returns the learning steps taken and the coverage after every episode.
+    fn explore(world: &World, directed: bool, seed: u64) -> (usize, Vec<f64>) {
+        let mut doormax =
+            DoorMax::new(world, 0.9, false, 1.0, 1.0e-6).with_directed_exploration(directed);
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+
+        let mut total_steps = 0;
+        let mut coverage = Vec::new();
+
+        for _ in 0..6 {
+            let state = State::build_random(world, &mut rng).unwrap();
+            let trial = doormax.learn(world, state, 100, &mut rng).unwrap();
+
+            total_steps += trial.steps.unwrap_or(100);
+            coverage.push(doormax.coverage(world));
+        }
+
+        (total_steps, coverage)
+    }
+
+    #[test]
+    fn directed_exploration_delivers_sooner() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        for seed in 0..3 {
+            let (uniform_steps, uniform_coverage) = explore(&world, false, seed);
+            let (directed_steps, directed_coverage) = explore(&world, true, seed);
+
+            assert!(directed_steps < uniform_steps);
+
+            for coverage in &[uniform_coverage, directed_coverage] {
+                assert!(coverage.windows(2).all(|pair| pair[0] <= pair[1]));
+                assert!(coverage[coverage.len() - 1] > 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn masked_greedy_action_stays_available() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut doormax = DoorMax::new(&world, 0.9, false, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(11);
+
+        for _ in 0..3 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            doormax.learn(&world, state, 20, &mut rng).unwrap();
+        }
+
+        let mut num_greedy_blocked = 0;
+        for state in StateIndexer::new(&world).iter_states(&world) {
+            if state.at_destination() {
+                continue;
+            }
+
+            let available = world.available_actions(state.get_taxi());
+            let greedy = doormax.greedy_action(&world, &state).unwrap();
+            let masked = doormax
+                .masked_greedy_action(&world, &state, &available)
+                .unwrap();
+
+            assert!(available.contains(&masked));
+            if available.contains(&greedy) {
+                assert_eq!(masked, greedy);
+            } else {
+                num_greedy_blocked += 1;
+            }
         }
+
+        assert!(num_greedy_blocked > 0);
     }
 }
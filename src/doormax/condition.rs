@@ -15,15 +15,8 @@ impl Condition {
         let taxi_pos = state.get_taxi();
         let walls = world.get_wall(taxi_pos);
 
-        let on_passenger = if let Some(passenger_id) = state.get_passenger() {
-            if let Some(passenger_pos) = world.get_fixed_position(passenger_id) {
-                passenger_pos == taxi_pos
-            } else {
-                false
-            }
-        } else {
-            false
-        };
+        let on_passenger =
+            !state.passenger_in_taxi() && state.get_passenger_position(world) == Some(taxi_pos);
 
         let on_destination =
             if let Some(destination_pos) = world.get_fixed_position(state.get_destination()) {
@@ -40,7 +33,7 @@ impl Condition {
 
             Term::OnPassenger => on_passenger,
             Term::OnDestination => on_destination,
-            Term::HasPassenger => state.get_passenger().is_none(),
+            Term::HasPassenger => state.passenger_in_taxi(),
         };
 
         Condition(enum_map)
@@ -109,7 +102,7 @@ impl Condition {
 
 impl fmt::Display for Condition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let &Condition(ref cond_map) = self;
+        let Condition(cond_map) = self;
 
         fn show_bool(b: bool) -> &'static str {
             if b {
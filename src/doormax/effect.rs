@@ -61,10 +61,10 @@ impl Effect for ChangeTaxiX {
     fn apply(&self, world: &World, state: &State) -> Result<State, Error> {
         let new_taxi_x = state.get_taxi().x + self.delta;
 
-        Ok(State::build(
+        Ok(State::build_with_passenger_position(
             world,
             (new_taxi_x, state.get_taxi().y),
-            state.get_passenger(),
+            passenger_pos(world, state),
             state.get_destination(),
         )?)
     }
@@ -103,10 +103,10 @@ impl Effect for ChangeTaxiY {
     fn apply(&self, world: &World, state: &State) -> Result<State, Error> {
         let new_taxi_y = state.get_taxi().y + self.delta;
 
-        Ok(State::build(
+        Ok(State::build_with_passenger_position(
             world,
             (state.get_taxi().x, new_taxi_y),
-            state.get_passenger(),
+            passenger_pos(world, state),
             state.get_destination(),
         )?)
     }
@@ -131,13 +131,13 @@ impl ChangePassenger {
 
 impl Effect for ChangePassenger {
     fn generate_effects(old_state: &State, new_state: &State) -> Option<Self> {
-        let old_passenger = old_state.get_passenger();
-        let new_passenger = new_state.get_passenger();
+        let old_passenger = old_state.passenger_state();
+        let new_passenger = new_state.passenger_state();
 
         if old_passenger != new_passenger {
-            // Assume that a non-None value for new_passenger
-            // means that the passenger was placed on the destination.
-            Some(ChangePassenger::new(new_passenger.is_some()))
+            // Assume that a passenger out of the taxi
+            // was placed on the destination.
+            Some(ChangePassenger::new(!new_state.passenger_in_taxi()))
         } else {
             None
         }
@@ -164,3 +164,10 @@ impl fmt::Display for ChangePassenger {
         write!(f, "ChangePassenger({:#?})", self.on_destination)
     }
 }
+
+// Moving the taxi leaves the passenger wherever it is waiting.
+fn passenger_pos(world: &World, state: &State) -> Option<(i32, i32)> {
+    state
+        .get_passenger_position(world)
+        .map(|position| (position.x, position.y))
+}
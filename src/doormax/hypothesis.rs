@@ -5,19 +5,14 @@ use enum_map::EnumMap;
 use crate::doormax::condition::Condition;
 use crate::doormax::term::Term;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 enum Require {
+    #[default]
     None,
     True,
     False,
 }
 
-impl Default for Require {
-    fn default() -> Self {
-        Require::None
-    }
-}
-
 impl From<bool> for Require {
     fn from(val: bool) -> Self {
         if val {
@@ -50,8 +45,8 @@ impl Hypothesis {
     //     Hypothesis(result_map)
     // }
 
-    pub fn combine_cond(&self, &Condition(ref cond_map): &Condition) -> Hypothesis {
-        let &Hypothesis(ref self_map) = self;
+    pub fn combine_cond(&self, Condition(cond_map): &Condition) -> Hypothesis {
+        let Hypothesis(self_map) = self;
 
         let mut result_map = *self_map;
 
@@ -76,16 +71,16 @@ impl Hypothesis {
         Hypothesis(result_map)
     }
 
-    pub fn matches(&self, &Hypothesis(ref other): &Hypothesis) -> bool {
-        let &Hypothesis(ref self_map) = self;
+    pub fn matches(&self, Hypothesis(other): &Hypothesis) -> bool {
+        let Hypothesis(self_map) = self;
 
         self_map
             .iter()
             .all(|(key, &value)| value == Require::None || other[key] == value)
     }
 
-    pub fn matches_cond(&self, &Condition(ref cond_map): &Condition) -> bool {
-        let &Hypothesis(ref self_map) = self;
+    pub fn matches_cond(&self, Condition(cond_map): &Condition) -> bool {
+        let Hypothesis(self_map) = self;
 
         self_map.iter().all(|(key, &value)| match value {
             Require::None => true,
@@ -97,7 +92,7 @@ impl Hypothesis {
 
 impl fmt::Display for Hypothesis {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let &Hypothesis(ref hyp_map) = self;
+        let Hypothesis(hyp_map) = self;
 
         fn show_require(r: Require) -> &'static str {
             match r {
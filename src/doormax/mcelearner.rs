@@ -29,7 +29,7 @@ impl<E: Effect> CELearner<E> {
     ) -> Result<Option<State>, effect::Error> {
         let mut full_result = None;
 
-        for &(ref condition_learner, ref learned_effect) in &self.condition_effects {
+        for (condition_learner, learned_effect) in &self.condition_effects {
             let matches_condition = condition_learner.predict(condition);
             match matches_condition {
                 // A condition learner returns None if it does not have enough
@@ -106,7 +106,7 @@ impl<E: Effect> CELearner<E> {
                     let mut condition_learner = ConditionLearner::new();
                     condition_learner.apply_experience(condition, true);
 
-                    for &(ref other_condition_learner, _) in &self.condition_effects {
+                    for (other_condition_learner, _) in &self.condition_effects {
                         condition_learner.remove_overlap(other_condition_learner);
                     }
 
@@ -118,10 +118,10 @@ impl<E: Effect> CELearner<E> {
                         let mut has_conflict = false;
 
                         for i in 0..(self.condition_effects.len() - 1) {
-                            let &(ref condition_learner, _) = &self.condition_effects[i];
+                            let (condition_learner, _) = &self.condition_effects[i];
 
                             for j in (i + 1)..self.condition_effects.len() {
-                                let &(ref other_condition_learner, _) = &self.condition_effects[j];
+                                let (other_condition_learner, _) = &self.condition_effects[j];
 
                                 // overlaps checks if either learner's truth hypothesis
                                 // is contained in the other's
@@ -152,7 +152,7 @@ impl<E: Effect + fmt::Display> fmt::Display for CELearner<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "CL(")?;
         let mut leader = " ";
-        for &(ref condition_learner, ref learned_effect) in &self.condition_effects {
+        for (condition_learner, learned_effect) in &self.condition_effects {
             write!(f, "{}{} => {}", leader, condition_learner, learned_effect)?;
             leader = ", ";
         }
@@ -194,10 +194,14 @@ impl MCELearner {
                 if let Some(predicted_passenger) =
                     self.passenger_learners[action_index].predict(world, state, &condition)?
                 {
-                    return Ok(Some(State::build(
+                    let passenger_pos = predicted_passenger
+                        .get_passenger_position(world)
+                        .map(|position| (position.x, position.y));
+
+                    return Ok(Some(State::build_with_passenger_position(
                         world,
                         (predicted_taxi_x.get_taxi().x, predicted_taxi_y.get_taxi().y),
-                        predicted_passenger.get_passenger(),
+                        passenger_pos,
                         state.get_destination(),
                     )?));
                 }
@@ -10,14 +10,12 @@ use crate::world::World;
 #[derive(Debug, Clone)]
 pub struct RewardLearner {
     condition_rewards: Vec<(ConditionLearner, f64)>,
-    error_delta: f64,
 }
 
 impl RewardLearner {
-    pub fn new(error_delta: f64) -> Self {
+    pub fn new() -> Self {
         RewardLearner {
             condition_rewards: Vec::new(),
-            error_delta,
         }
     }
 
@@ -65,7 +63,7 @@ impl RewardLearner {
             let mut condition_learner = ConditionLearner::new();
             condition_learner.apply_experience(condition, true);
 
-            for &(ref other_condition_learner, _) in &self.condition_rewards {
+            for (other_condition_learner, _) in &self.condition_rewards {
                 condition_learner.remove_overlap(other_condition_learner);
             }
 
@@ -95,7 +93,7 @@ impl RewardLearner {
                 let mut has_conflict = false;
 
                 for i in 0..(self.condition_rewards.len() - 1) {
-                    let &(ref condition_learner, _) = &self.condition_rewards[i];
+                    let (condition_learner, _) = &self.condition_rewards[i];
 
                     for j in (i + 1)..self.condition_rewards.len() {
                         let &(ref other_condition_learner, _other_reward) =
@@ -138,14 +136,15 @@ pub struct MultiRewardLearner {
 }
 
 impl MultiRewardLearner {
-    pub fn new(error_delta: f64) -> Self {
+    pub fn new() -> Self {
         let reward_learners = [
-            RewardLearner::new(error_delta),
-            RewardLearner::new(error_delta),
-            RewardLearner::new(error_delta),
-            RewardLearner::new(error_delta),
-            RewardLearner::new(error_delta),
-            RewardLearner::new(error_delta),
+            RewardLearner::new(),
+            RewardLearner::new(),
+            RewardLearner::new(),
+            RewardLearner::new(),
+            RewardLearner::new(),
+            RewardLearner::new(),
+            RewardLearner::new(),
         ];
 
         MultiRewardLearner { reward_learners }
@@ -219,7 +218,7 @@ mod multirewardlearner_test {
             ulps = 1
         ));
 
-        let mut learner = MultiRewardLearner::new(1.0e-6);
+        let mut learner = MultiRewardLearner::new();
 
         assert_eq!(learner.predict(&w, &off_passenger, Actions::PickUp), None);
 
@@ -280,7 +279,7 @@ mod multirewardlearner_test {
             ulps = 1
         ));
 
-        let mut learner = MultiRewardLearner::new(1.0e-6);
+        let mut learner = MultiRewardLearner::new();
 
         assert_eq!(learner.predict(&w, &no_passenger, Actions::DropOff), None);
 
@@ -1,5 +1,5 @@
 use crate::actions::Actions;
-use crate::state::State;
+use crate::state::{PassengerState, State};
 use crate::world::World;
 
 #[derive(Debug, Clone)]
@@ -38,6 +38,11 @@ impl Rewards {
         }
     }
 
+    pub fn reset(&mut self) {
+        self.occurences.iter_mut().for_each(|count| *count = 0.0);
+        self.rewards.iter_mut().for_each(|reward| *reward = 0.0);
+    }
+
     pub fn apply_experience(&mut self, reward: f64, world: &World, state: &State, action: Actions) {
         let x_index = state.get_taxi().x as usize;
         let y_index = state.get_taxi().y as usize;
@@ -107,6 +112,9 @@ fn num_reward_parents(world: &World, action: Actions) -> usize {
         Actions::PickUp => num_passenger_values * num_taxi_values,
 
         Actions::DropOff => num_destination_values * num_passenger_values * num_taxi_values,
+
+        // Waiting always has the same reward.
+        Actions::Wait => 1,
     }
 }
 
@@ -118,6 +126,7 @@ fn total_reward_parents(world: &World) -> usize {
     num_taxi_values * 4
         + num_passenger_values * num_taxi_values
         + num_destination_values * num_passenger_values * num_taxi_values
+        + 1
 }
 
 fn generate_reward_parent_index(
@@ -159,13 +168,19 @@ fn generate_reward_parent_index(
 
             result
         }
+
+        Actions::Wait => 0,
     }
 }
 
 fn generate_passenger_index(world: &World, state: &State) -> Option<usize> {
-    match state.get_passenger() {
-        None => Some(0),
-        Some(passenger_id) => world.get_fixed_index(passenger_id).map(|i| i + 1),
+    match state.passenger_state() {
+        PassengerState::InTaxi => Some(0),
+        PassengerState::Waiting(passenger_id) => world.get_fixed_index(passenger_id).map(|i| i + 1),
+        PassengerState::Delivered => world
+            .get_fixed_index(state.get_destination())
+            .map(|i| i + 1),
+        PassengerState::WaitingAt(_) => None,
     }
 }
 
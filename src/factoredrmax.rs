@@ -4,11 +4,14 @@ use rand::Rng;
 use rand_pcg::Pcg64Mcg;
 
 use crate::actions::Actions;
-use crate::state::{State, StateIterator};
+use crate::state::{PassengerState, State, StateIterator};
 use crate::world::World;
 
-use crate::runner::{Attempt, Runner};
+use crate::model::LearnedModel;
+use crate::rmax::{RMaxParams, SettledRebuilds};
+use crate::runner::{Attempt, Runner, Trial};
 use crate::state_indexer::StateIndexer;
+use crate::util::argmax_available_action;
 
 #[derive(Debug, Clone)]
 struct Transitions {
@@ -109,6 +112,11 @@ impl Transitions {
         }
     }
 
+    fn reset(&mut self) {
+        self.occurences.iter_mut().for_each(|count| *count = 0.0);
+        self.transitions.iter_mut().for_each(|count| *count = 0.0);
+    }
+
     fn generate_x_parent_index(
         &self,
         world: &World,
@@ -198,6 +206,42 @@ impl Transitions {
         }
     }
 
+    // "next" for a deterministic factor, otherwise "next:probability" pairs.
+    fn describe_transition<F>(
+        &self,
+        parent_index: usize,
+        num_values: usize,
+        value_name: F,
+    ) -> Option<String>
+    where
+        F: Fn(usize) -> String,
+    {
+        let outcomes: Vec<(usize, f64)> = (0..num_values)
+            .filter_map(|next_index| {
+                let probability = self.get_transition(parent_index, next_index)?;
+                if probability > 0.0 {
+                    Some((next_index, probability))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        match outcomes.as_slice() {
+            [] => None,
+            [(next_index, _)] => Some(value_name(*next_index)),
+            _ => Some(
+                outcomes
+                    .iter()
+                    .map(|(next_index, probability)| {
+                        format!("{}:{:.2}", value_name(*next_index), probability)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        }
+    }
+
     fn apply_experience(&mut self, parent_index: usize, next_index: usize) {
         self.transitions[self.transition_starts[parent_index] + next_index] += 1.0;
         self.occurences[parent_index] += 1.0;
@@ -240,6 +284,11 @@ impl Rewards {
         }
     }
 
+    fn reset(&mut self) {
+        self.occurences.iter_mut().for_each(|count| *count = 0.0);
+        self.rewards.iter_mut().for_each(|reward| *reward = 0.0);
+    }
+
     fn apply_experience(&mut self, reward: f64, action: Actions, reward_parent_index: usize) {
         let action_index = action.to_index();
 
@@ -297,21 +346,35 @@ pub struct FactoredRMax {
 
     gamma: f64,
     error_delta: f64,
+    max_iterations: usize,
+
+    settled_rebuilds: SettledRebuilds,
 }
 
 impl FactoredRMax {
     pub fn new(world: &World, gamma: f64, known_count: f64, error_delta: f64) -> FactoredRMax {
+        let params = RMaxParams {
+            value_iteration_tolerance: error_delta,
+            known_threshold: known_count,
+            ..RMaxParams::new(world, gamma)
+        };
+
+        FactoredRMax::with_params(world, params)
+    }
+
+    pub fn with_params(world: &World, params: RMaxParams) -> FactoredRMax {
         let state_indexer = StateIndexer::new(world);
         let num_states = state_indexer.num_states();
         let value_table = vec![0.0; num_states];
 
-        let transitions = Transitions::new(world, known_count);
-        let rewards = Rewards::new(world, known_count);
+        let transitions = Transitions::new(world, params.known_threshold);
+        let rewards = Rewards::new(world, params.known_threshold);
 
+        let gamma = params.gamma;
         let rmax = if gamma < 1.0 {
-            world.max_reward() / (1.0 - gamma)
+            params.r_max / (1.0 - gamma)
         } else {
-            world.max_reward()
+            params.r_max
         };
 
         FactoredRMax {
@@ -324,7 +387,10 @@ impl FactoredRMax {
             value_table,
 
             gamma,
-            error_delta,
+            error_delta: params.value_iteration_tolerance,
+            max_iterations: params.max_iterations,
+
+            settled_rebuilds: SettledRebuilds::default(),
         }
     }
 
@@ -406,6 +472,146 @@ impl FactoredRMax {
         }
     }
 
+    // Lists, per factor and action, the learned next value for every known
+    // parent.  Parents that are not yet known are left out.
+    pub fn display_factors(&self, world: &World) -> String {
+        let width = world.width as usize;
+        let height = world.height as usize;
+        let num_destination_states = world.num_fixed_positions();
+        let num_passenger_states = num_destination_states + 1;
+
+        let passenger_name = |passenger_index: usize| {
+            if passenger_index == 0 {
+                String::from("in_taxi")
+            } else {
+                world
+                    .get_fixed_id_from_index(passenger_index - 1)
+                    .unwrap()
+                    .to_string()
+            }
+        };
+        let destination_name =
+            |destination_index: usize| world.get_fixed_id_from_index(destination_index).unwrap();
+
+        let mut result = String::new();
+
+        result += "taxi_x:\n";
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            let mut entries = Vec::new();
+
+            let num_parent_ys = match action {
+                Actions::East | Actions::West => height,
+                _ => 1,
+            };
+
+            for y_index in 0..num_parent_ys {
+                for x_index in 0..width {
+                    let parent_index = self
+                        .transitions
+                        .generate_x_parent_index(world, action, x_index, y_index);
+
+                    if let Some(next) =
+                        self.transitions
+                            .describe_transition(parent_index, width, |x| x.to_string())
+                    {
+                        let parent = match action {
+                            Actions::East | Actions::West => {
+                                format!("x={},y={}", x_index, y_index)
+                            }
+                            _ => format!("x={}", x_index),
+                        };
+                        entries.push(format!("{} -> {}", parent, next));
+                    }
+                }
+            }
+
+            result += &format!("{} - {}\n", action, entries.join(", "));
+        }
+        result += "\n";
+
+        result += "taxi_y:\n";
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            let mut entries = Vec::new();
+
+            for y_index in 0..height {
+                let parent_index = self
+                    .transitions
+                    .generate_y_parent_index(world, action, y_index);
+
+                if let Some(next) =
+                    self.transitions
+                        .describe_transition(parent_index, height, |y| y.to_string())
+                {
+                    entries.push(format!("y={} -> {}", y_index, next));
+                }
+            }
+
+            result += &format!("{} - {}\n", action, entries.join(", "));
+        }
+        result += "\n";
+
+        result += "passenger:\n";
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            let mut entries = Vec::new();
+
+            // Only pick up and drop off depend on more than the passenger.
+            let (num_taxi_states, num_parent_destinations) = match action {
+                Actions::DropOff => (width * height, num_destination_states),
+                Actions::PickUp => (width * height, 1),
+                _ => (1, 1),
+            };
+
+            for taxi_index in 0..num_taxi_states {
+                let x_index = taxi_index % width;
+                let y_index = taxi_index / width;
+
+                for destination_index in 0..num_parent_destinations {
+                    for passenger_index in 0..num_passenger_states {
+                        let parent_index = self.transitions.generate_passenger_parent_index(
+                            world,
+                            action,
+                            x_index,
+                            y_index,
+                            passenger_index,
+                            destination_index,
+                        );
+
+                        if let Some(next) = self.transitions.describe_transition(
+                            parent_index,
+                            num_passenger_states,
+                            passenger_name,
+                        ) {
+                            let parent = match action {
+                                Actions::DropOff => format!(
+                                    "x={},y={},dest={},pass={}",
+                                    x_index,
+                                    y_index,
+                                    destination_name(destination_index),
+                                    passenger_name(passenger_index)
+                                ),
+                                Actions::PickUp => format!(
+                                    "x={},y={},pass={}",
+                                    x_index,
+                                    y_index,
+                                    passenger_name(passenger_index)
+                                ),
+                                _ => format!("pass={}", passenger_name(passenger_index)),
+                            };
+                            entries.push(format!("{} -> {}", parent, next));
+                        }
+                    }
+                }
+            }
+
+            result += &format!("{} - {}\n", action, entries.join(", "));
+        }
+
+        result
+    }
+
     fn predict_transition(
         &self,
         world: &World,
@@ -525,6 +731,20 @@ impl FactoredRMax {
         best_value
     }
 
+    // Every action's value, indexed by Actions::to_index, or None when the
+    // state has no factored index.
+    fn action_values(&self, world: &World, state: &State) -> Option<Vec<f64>> {
+        generate_passenger_index(world, state)?;
+        generate_destination_index(world, state)?;
+
+        Some(
+            (0..Actions::NUM_ELEMENTS)
+                .filter_map(Actions::from_index)
+                .map(|action| self.measure_value(world, state, action))
+                .collect(),
+        )
+    }
+
     fn determine_best_action_index<R: Rng>(
         &self,
         world: &World,
@@ -557,7 +777,12 @@ impl FactoredRMax {
     }
 
     fn rebuild_value_table(&mut self, world: &World) {
-        for _ in 0..10_000 {
+        let mut sweeps = 0;
+        let mut converged = false;
+
+        for _ in 0..self.max_iterations {
+            sweeps += 1;
+
             let mut error = 0.0;
 
             for state in StateIterator::new(world) {
@@ -576,9 +801,12 @@ impl FactoredRMax {
             }
 
             if error < self.error_delta {
+                converged = true;
                 break;
             }
         }
+
+        self.settled_rebuilds.record(converged, sweeps);
     }
 
     fn select_best_action<R: Rng>(
@@ -592,6 +820,69 @@ impl FactoredRMax {
     }
 }
 
+// The most likely next state, which is the only one in a deterministic
+// world.
+impl LearnedModel for FactoredRMax {
+    fn predict(&self, world: &World, state: &State, action: Actions) -> Option<(State, f64)> {
+        let passenger_index = generate_passenger_index(world, state)?;
+        let destination_index = generate_destination_index(world, state)?;
+
+        let x_index = state.get_taxi().x as usize;
+        let y_index = state.get_taxi().y as usize;
+
+        let reward = self.rewards.get_reward(
+            world,
+            action,
+            x_index,
+            y_index,
+            passenger_index,
+            destination_index,
+        )?;
+
+        let x_parent_index = self
+            .transitions
+            .generate_x_parent_index(world, action, x_index, y_index);
+
+        let y_parent_index = self
+            .transitions
+            .generate_y_parent_index(world, action, y_index);
+
+        let passenger_parent_index = self.transitions.generate_passenger_parent_index(
+            world,
+            action,
+            x_index,
+            y_index,
+            passenger_index,
+            destination_index,
+        );
+
+        let destination_parent_index =
+            self.transitions
+                .generate_destination_parent_index(world, action, destination_index);
+
+        let mut best = None;
+        let mut best_transition = 0.0;
+
+        for next_state in StateIterator::new(world) {
+            if let Some(transition) = self.predict_transition(
+                world,
+                x_parent_index,
+                y_parent_index,
+                passenger_parent_index,
+                destination_parent_index,
+                &next_state,
+            ) {
+                if transition > best_transition {
+                    best_transition = transition;
+                    best = Some(next_state);
+                }
+            }
+        }
+
+        best.map(|next_state| (next_state, reward))
+    }
+}
+
 impl Runner for FactoredRMax {
     fn learn<R: Rng>(
         &mut self,
@@ -599,28 +890,37 @@ impl Runner for FactoredRMax {
         mut state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize> {
+    ) -> Trial {
+        let mut total_reward = 0.0;
+
         for step in 0..max_steps {
             if state.at_destination() {
-                return Some(step);
+                return Trial::new(Some(step), total_reward);
+            }
+
+            // The passenger factor only covers the fixed positions, so a
+            // passenger that wandered off them ends the episode.
+            if let PassengerState::WaitingAt(_) = state.passenger_state() {
+                return Trial::new(None, total_reward);
             }
 
             self.rebuild_value_table(world);
 
             if let Some(next_action) = self.select_best_action(world, &state, rng) {
-                let (reward, next_state) = state.apply_action(world, next_action);
+                let (reward, next_state) = state.apply_action_stochastic(world, next_action, rng);
+                total_reward += reward;
 
                 self.apply_experience(world, &state, next_action, &next_state, reward);
                 state = next_state;
             } else {
-                return None;
+                return Trial::new(None, total_reward);
             }
         }
 
         if state.at_destination() {
-            Some(max_steps)
+            Trial::new(Some(max_steps), total_reward)
         } else {
-            None
+            Trial::new(None, total_reward)
         }
     }
 
@@ -677,6 +977,36 @@ impl Runner for FactoredRMax {
         state.at_destination()
     }
 
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        let all_actions: Vec<Actions> = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .collect();
+        self.masked_greedy_action(world, state, &all_actions)
+    }
+
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        argmax_available_action(&self.action_values(world, state)?, available)
+    }
+
+    // See SettledRebuilds.
+    fn has_converged(&self) -> bool {
+        self.settled_rebuilds
+            .has_converged(self.state_indexer.num_states())
+    }
+
+    fn reset(&mut self) {
+        self.transitions.reset();
+        self.rewards.reset();
+        self.value_table.iter_mut().for_each(|value| *value = 0.0);
+
+        self.settled_rebuilds = SettledRebuilds::default();
+    }
+
     fn report_training_result(&self, world: &World, _steps: Option<usize>) {
         let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
 
@@ -686,20 +1016,23 @@ impl Runner for FactoredRMax {
 
             if !state.at_destination() {
                 if let Some(next_action) = self.select_best_action(world, &state, &mut rng) {
-                    println!("===================");
-                    println!("{}", state.display(world));
-                    println!("Best action: {}", next_action);
+                    info!("===================");
+                    info!("{}", state.display(world));
+                    info!("Best action: {}", next_action);
 
                     for action_index in 0..Actions::NUM_ELEMENTS {
                         let action = Actions::from_index(action_index).unwrap();
 
                         let action_value = self.measure_value(world, &state, action);
 
-                        println!("{} - {}", action, action_value);
+                        info!("{} - {}", action, action_value);
                     }
                 }
             }
         }
+
+        info!("===================");
+        info!("{}", self.display_factors(world));
     }
 }
 
@@ -723,15 +1056,19 @@ fn total_y_parents(world: &World) -> usize {
 }
 
 fn generate_passenger_index(world: &World, state: &State) -> Option<usize> {
-    match state.get_passenger() {
-        None => Some(0),
-        Some(passenger_id) => world.get_fixed_index(passenger_id).map(|i| i + 1),
+    match state.passenger_state() {
+        PassengerState::InTaxi => Some(0),
+        PassengerState::Waiting(passenger_id) => world.get_fixed_index(passenger_id).map(|i| i + 1),
+        PassengerState::Delivered => world
+            .get_fixed_index(state.get_destination())
+            .map(|i| i + 1),
+        PassengerState::WaitingAt(_) => None,
     }
 }
 
 fn num_passenger_parents(world: &World, action: Actions) -> usize {
-    let num_destination_states = world.num_fixed_positions() as usize;
-    let num_passenger_states = (num_destination_states + 1) as usize;
+    let num_destination_states = world.num_fixed_positions();
+    let num_passenger_states = num_destination_states + 1;
 
     let num_taxi_states = (world.height * world.width) as usize;
 
@@ -743,8 +1080,8 @@ fn num_passenger_parents(world: &World, action: Actions) -> usize {
 }
 
 fn total_passenger_parents(world: &World) -> usize {
-    let num_destination_states = world.num_fixed_positions() as usize;
-    let num_passenger_states = (num_destination_states + 1) as usize;
+    let num_destination_states = world.num_fixed_positions();
+    let num_passenger_states = num_destination_states + 1;
 
     let num_taxi_states = (world.height * world.width) as usize;
 
@@ -794,6 +1131,9 @@ fn num_reward_parents(world: &World, action: Actions) -> usize {
         Actions::PickUp => num_passenger_values * num_taxi_values,
 
         Actions::DropOff => num_destination_values * num_passenger_values * num_taxi_values,
+
+        // Waiting always has the same reward.
+        Actions::Wait => 1,
     }
 }
 
@@ -805,6 +1145,7 @@ fn total_reward_parents(world: &World) -> usize {
     num_taxi_values * 4
         + num_passenger_values * num_taxi_values
         + num_destination_values * num_passenger_values * num_taxi_values
+        + 1
 }
 
 fn generate_reward_parent_index(
@@ -846,6 +1187,8 @@ fn generate_reward_parent_index(
 
             result
         }
+
+        Actions::Wait => 0,
     }
 }
 
@@ -854,6 +1197,7 @@ mod test_factoredrmax {
 
     use super::*;
     use crate::world::Costs;
+    use rand::SeedableRng;
 
     #[test]
     fn learn_simple() {
@@ -885,6 +1229,142 @@ mod test_factoredrmax {
         let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
 
         let result = factoredrmax.learn(&world, state, 100, &mut rng);
-        assert!(result.is_some());
+        assert!(result.steps.is_some());
+    }
+
+    #[test]
+    fn converges_once_values_settle() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(world_str, costs).unwrap();
+
+        let mut factoredrmax = FactoredRMax::new(&world, 0.3, 1.0, 1.0e-6);
+        assert!(!factoredrmax.has_converged());
+
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+        // The first rebuild starts from nothing, so takes several sweeps.
+        factoredrmax.rebuild_value_table(&world);
+        assert_eq!(factoredrmax.settled_rebuilds.count(), 0);
+
+        let num_states = factoredrmax.state_indexer.num_states();
+        for _ in 0..num_states {
+            factoredrmax.rebuild_value_table(&world);
+        }
+        assert!(factoredrmax.has_converged());
+
+        // New experience moves the values again.
+        let state = State::build(&world, (0, 1), Some('R'), 'G').unwrap();
+        factoredrmax.learn(&world, state, 100, &mut rng);
+        assert!(!factoredrmax.has_converged());
+    }
+
+    #[test]
+    fn display_learned_factors() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+
+        let mut factoredrmax = FactoredRMax::new(&world, 0.3, 1.0, 1.0e-6);
+
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+
+        let (reward, next_state) = state.apply_action(&world, Actions::East);
+        factoredrmax.apply_experience(&world, &state, Actions::East, &next_state, reward);
+
+        let (reward, picked_up) = next_state.apply_action(&world, Actions::PickUp);
+        factoredrmax.apply_experience(&world, &next_state, Actions::PickUp, &picked_up, reward);
+
+        let display = factoredrmax.display_factors(&world);
+
+        assert!(display.contains("E - x=0,y=0 -> 1\n"));
+        assert!(display.contains("P - x=1 -> 1\n"));
+        assert!(display.contains("E - y=0 -> 0\n"));
+        assert!(display.contains("E - pass=R -> R\n"));
+        assert!(display.contains("P - x=1,y=0,pass=R -> R\n"));
+        assert!(display.contains("N - \n"));
+    }
+
+    #[test]
+    fn masked_greedy_action_stays_available() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+        let mut factoredrmax = FactoredRMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(11);
+
+        let state = State::build_random(&world, &mut rng).unwrap();
+        factoredrmax.learn(&world, state, 5, &mut rng);
+
+        let mut num_greedy_blocked = 0;
+        for state in StateIndexer::new(&world).iter_states(&world) {
+            if state.at_destination() {
+                continue;
+            }
+
+            let available = world.available_actions(state.get_taxi());
+            let greedy = factoredrmax.greedy_action(&world, &state).unwrap();
+            let masked = factoredrmax
+                .masked_greedy_action(&world, &state, &available)
+                .unwrap();
+
+            assert!(available.contains(&masked));
+            if available.contains(&greedy) {
+                assert_eq!(masked, greedy);
+            } else {
+                num_greedy_blocked += 1;
+            }
+        }
+
+        assert!(num_greedy_blocked > 0);
+    }
+
+    #[test]
+    fn wandered_passenger_ends_episode() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let mut world = World::build_from_str(world_str, Costs::default()).unwrap();
+        world.passenger_wander = 1.0;
+
+        let mut factoredrmax = FactoredRMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        // R has two free neighbours, so the passenger leaves it on the first
+        // step and the episode ends with that step's reward.
+        let state = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+        let trial = factoredrmax.learn(&world, state, 50, &mut rng);
+        assert_eq!(trial, Trial::new(None, world.costs.movement));
+
+        let wandered =
+            State::build_with_passenger_position(&world, (1, 1), Some((1, 0)), 'G').unwrap();
+        assert_eq!(factoredrmax.greedy_action(&world, &wandered), None);
+        assert_eq!(
+            factoredrmax.learn(&world, wandered, 50, &mut rng),
+            Trial::new(None, 0.0)
+        );
     }
 }
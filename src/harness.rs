@@ -0,0 +1,356 @@
+use std::time;
+
+use rand::Rng;
+use rand_pcg::Pcg64Mcg;
+use rayon::prelude::*;
+
+use crate::distribution::MeasureDistribution;
+use crate::runner::{
+    run_training_session_with_options, Error, Probe, ProbeOutcome, Runner, SessionResult,
+    TrainingOptions,
+};
+use crate::world::World;
+
+// Everything needed to train and measure a batch of sessions.  Each session
+// gets its own seed, drawn in order from root_seed, so a run can be
+// repeated exactly.
+#[derive(Debug, Clone)]
+pub struct HarnessConfig {
+    pub world: World,
+    pub probes: Vec<Probe>,
+    pub sessions: usize,
+    pub max_trials: usize,
+    pub max_trial_steps: usize,
+    pub root_seed: u128,
+    pub options: TrainingOptions,
+}
+
+impl HarnessConfig {
+    pub fn new(
+        world: World,
+        probes: Vec<Probe>,
+        sessions: usize,
+        max_trials: usize,
+        max_trial_steps: usize,
+        root_seed: u128,
+    ) -> HarnessConfig {
+        HarnessConfig {
+            world,
+            probes,
+            sessions,
+            max_trials,
+            max_trial_steps,
+            root_seed,
+            options: TrainingOptions::default(),
+        }
+    }
+
+    pub fn with_options(self, options: TrainingOptions) -> HarnessConfig {
+        HarnessConfig { options, ..self }
+    }
+
+    // The seed of every session, in session order.
+    pub fn session_seeds(&self) -> Vec<u128> {
+        let mut seed_generator = Pcg64Mcg::new(self.root_seed);
+        (0..self.sessions).map(|_| seed_generator.gen()).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionReport {
+    pub session: usize,
+    pub seed: u128,
+    pub result: SessionResult,
+    pub duration: time::Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct HarnessReport {
+    // Steps to pass the probes, only of the sessions that did.
+    pub distribution: MeasureDistribution,
+    // Average return of every session.
+    pub returns: MeasureDistribution,
+    // Fraction of the final probe checks that were solved, over every
+    // session, with each probe counted by its weight.
+    pub weighted_success_rate: f64,
+    // Average steps of the solved probe checks, weighted the same way.
+    pub weighted_steps: f64,
+    // Summed over the sessions, which may have run in parallel.
+    pub duration: time::Duration,
+    // In session order.
+    pub sessions: Vec<SessionReport>,
+}
+
+impl HarnessReport {
+    pub fn num_solved(&self) -> usize {
+        self.distribution.get_count() as usize
+    }
+
+    pub fn summary(&self) -> String {
+        let (avg_steps, stddev_steps) = self.distribution.get_distribution();
+        let (avg_return, _) = self.returns.get_distribution();
+
+        format!(
+            "finished {} sessions in {:.1} average steps with stddev of {:.2} \
+             and {:.2} average return in {:.3} secs, solving {:.1}% of weighted \
+             probes in {:.1} weighted average steps.",
+            self.num_solved(),
+            avg_steps,
+            stddev_steps,
+            avg_return,
+            self.duration.as_secs_f64(),
+            100.0 * self.weighted_success_rate,
+            self.weighted_steps,
+        )
+    }
+}
+
+pub fn run<B, Rnr>(config: &HarnessConfig, solver_factory: B) -> Result<HarnessReport, Error>
+where
+    B: Fn() -> Rnr + Sync,
+    Rnr: Runner,
+{
+    run_with_observer(config, solver_factory, |_, _| {})
+}
+
+// Sessions run in parallel, the observer sees each one with its trained
+// solver as soon as it finishes, so not necessarily in session order.
+pub fn run_with_observer<B, Rnr, O>(
+    config: &HarnessConfig,
+    solver_factory: B,
+    observer: O,
+) -> Result<HarnessReport, Error>
+where
+    B: Fn() -> Rnr + Sync,
+    Rnr: Runner,
+    O: Fn(&SessionReport, &Rnr) + Sync,
+{
+    let session_ids: Vec<(usize, u128)> = config.session_seeds().into_iter().enumerate().collect();
+
+    let sessions = session_ids
+        .par_iter()
+        .map(|&(session, seed)| -> Result<SessionReport, Error> {
+            let start_time = time::Instant::now();
+
+            // A seeded environment restarts its stream for every session.
+            let world = config.world.clone();
+
+            let mut solver = solver_factory();
+            let mut rng = Pcg64Mcg::new(seed);
+
+            let result = run_training_session_with_options(
+                &world,
+                &config.probes,
+                (config.max_trials, config.max_trial_steps),
+                &mut solver,
+                &mut rng,
+                &config.options,
+                |_, _| {},
+            )?;
+
+            let session_report = SessionReport {
+                session,
+                seed,
+                result,
+                duration: start_time.elapsed(),
+            };
+
+            observer(&session_report, &solver);
+
+            Ok(session_report)
+        })
+        .collect::<Result<Vec<SessionReport>, Error>>()?;
+
+    let mut report = HarnessReport {
+        distribution: MeasureDistribution::default(),
+        returns: MeasureDistribution::default(),
+        weighted_success_rate: 0.0,
+        weighted_steps: 0.0,
+        duration: time::Duration::default(),
+        sessions: Vec::with_capacity(sessions.len()),
+    };
+
+    let (weighted_success_rate, weighted_steps) = weighted_probe_metrics(
+        &config.probes,
+        sessions.iter().map(|session_report| &session_report.result),
+    );
+    report.weighted_success_rate = weighted_success_rate;
+    report.weighted_steps = weighted_steps;
+
+    for session_report in sessions {
+        if let Some(steps) = session_report.result.steps {
+            report.distribution.add_value(steps as f64);
+        }
+        report
+            .returns
+            .add_value(session_report.result.average_reward());
+        report.duration += session_report.duration;
+
+        report.sessions.push(session_report);
+    }
+
+    Ok(report)
+}
+
+// The weighted success rate and weighted average steps of the final probe
+// checks, both 0 when nothing carries any weight.
+fn weighted_probe_metrics<'a, I>(probes: &[Probe], results: I) -> (f64, f64)
+where
+    I: Iterator<Item = &'a SessionResult>,
+{
+    let mut total_weight = 0.0;
+    let mut solved_weight = 0.0;
+    let mut weighted_steps = 0.0;
+
+    for result in results {
+        for (probe, outcome) in probes.iter().zip(&result.probe_outcomes) {
+            total_weight += probe.weight;
+
+            let steps = match *outcome {
+                ProbeOutcome::Failed => None,
+                ProbeOutcome::Solved(steps) => Some(steps),
+                ProbeOutcome::Optimal => probe.expected_steps,
+                ProbeOutcome::Feasible { steps, .. } => Some(steps),
+                ProbeOutcome::BeatExpected { steps, .. } => Some(steps),
+            };
+
+            if let Some(steps) = steps {
+                solved_weight += probe.weight;
+                weighted_steps += probe.weight * steps as f64;
+            }
+        }
+    }
+
+    let success_rate = if total_weight > 0.0 {
+        solved_weight / total_weight
+    } else {
+        0.0
+    };
+
+    let average_steps = if solved_weight > 0.0 {
+        weighted_steps / solved_weight
+    } else {
+        0.0
+    };
+
+    (success_rate, average_steps)
+}
+
+#[cfg(test)]
+mod test_harness {
+    use super::*;
+
+    use crate::qlearner::QLearner;
+    use crate::state::State;
+    use crate::world::{Costs, EnvRng};
+
+    const WORLD: &str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+
+    fn harness_config() -> HarnessConfig {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let probes = vec![
+            Probe::new(State::build(&world, (1, 0), Some('R'), 'G').unwrap(), 10),
+            Probe::new(State::build(&world, (0, 1), Some('G'), 'R').unwrap(), 10),
+        ];
+
+        HarnessConfig::new(world, probes, 4, 500, 50, 0xcafe_f00d_d15e_a5e5)
+    }
+
+    #[test]
+    fn sessions_are_repeatable() {
+        let config = harness_config();
+        let world = config.world.clone();
+
+        let first = run(&config, || QLearner::new(&world, 0.1, 0.9, 0.1)).unwrap();
+        let second = run(&config, || QLearner::new(&world, 0.1, 0.9, 0.1)).unwrap();
+
+        assert_eq!(first.sessions.len(), 4);
+        assert_eq!(first.num_solved(), 4);
+
+        for (index, (a, b)) in first.sessions.iter().zip(&second.sessions).enumerate() {
+            assert_eq!(a.session, index);
+            assert_eq!(a.seed, config.session_seeds()[index]);
+            assert_eq!(a.result, b.result);
+        }
+
+        assert_eq!(
+            first.distribution.get_distribution(),
+            second.distribution.get_distribution()
+        );
+        assert!(first.summary().starts_with("finished 4 sessions"));
+    }
+
+    #[test]
+    fn seeded_environment_is_repeatable() {
+        let mut config = harness_config();
+        config.world.destination_churn = 0.1;
+        config.world.env_rng = EnvRng::new(11);
+        let world = config.world.clone();
+
+        let first = run(&config, || QLearner::new(&world, 0.1, 0.9, 0.1)).unwrap();
+        let second = run(&config, || QLearner::new(&world, 0.1, 0.9, 0.1)).unwrap();
+
+        for (a, b) in first.sessions.iter().zip(&second.sessions) {
+            assert_eq!(a.result, b.result);
+        }
+    }
+
+    #[test]
+    fn weighted_probes_dominate_metrics() {
+        let config = harness_config();
+        let mut probes = config.probes.clone();
+
+        let result = SessionResult {
+            steps: None,
+            trials: 1,
+            total_reward: 0.0,
+            probe_outcomes: vec![ProbeOutcome::Solved(4), ProbeOutcome::Failed],
+        };
+
+        // Equal weights are the plain averages.
+        let (rate, steps) = weighted_probe_metrics(&probes, [result.clone()].iter());
+        assert_eq!((rate, steps), (0.5, 4.0));
+
+        // A heavy failing probe drags the success rate down.
+        probes[1].weight = 5.0;
+        let (rate, steps) = weighted_probe_metrics(&probes, [result.clone()].iter());
+        assert_eq!((rate, steps), (1.0 / 6.0, 4.0));
+
+        let both_solved = SessionResult {
+            probe_outcomes: vec![ProbeOutcome::Solved(2), ProbeOutcome::Solved(8)],
+            ..result.clone()
+        };
+        let (rate, steps) = weighted_probe_metrics(&probes, [result, both_solved].iter());
+        assert_eq!(rate, 7.0 / 12.0);
+        assert_eq!(steps, (4.0 + 2.0 + 5.0 * 8.0) / 7.0);
+
+        let report = run(&config, || QLearner::new(&config.world, 0.1, 0.9, 0.1)).unwrap();
+        assert_eq!(report.weighted_success_rate, 1.0);
+        assert!(report.weighted_steps > 0.0);
+    }
+
+    #[test]
+    fn observer_sees_every_session() {
+        let config = harness_config();
+        let world = config.world.clone();
+        let observed = std::sync::Mutex::new(Vec::new());
+
+        let report = run_with_observer(
+            &config,
+            || QLearner::new(&world, 0.1, 0.9, 0.1),
+            |session_report, _| observed.lock().unwrap().push(session_report.session),
+        )
+        .unwrap();
+
+        let mut observed = observed.into_inner().unwrap();
+        observed.sort();
+        assert_eq!(observed, vec![0, 1, 2, 3]);
+        assert_eq!(report.sessions.len(), 4);
+    }
+}
@@ -8,16 +8,28 @@ extern crate enum_map;
 #[macro_use]
 extern crate float_cmp;
 
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate serde_derive;
+
 pub mod actions;
+pub mod checkpoint;
 pub mod distribution;
 pub mod doormax;
 pub mod factoredrmax;
+pub mod harness;
 pub mod maxq;
+pub mod model;
+pub mod options;
 pub mod position;
 pub mod qlearner;
 pub mod random_solver;
+pub mod replay_buffer;
 pub mod rmax;
 pub mod runner;
 pub mod state;
 pub mod state_indexer;
+pub mod util;
 pub mod world;
@@ -0,0 +1,128 @@
+use std::io;
+use std::io::Write;
+
+use crossterm::cursor::MoveTo;
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::tty::IsTty;
+
+use rand_pcg::Pcg64Mcg;
+
+use taxi::runner::{Probe, Runner};
+use taxi::world::World;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiveView {
+    every: usize,
+    is_tty: bool,
+}
+
+impl LiveView {
+    pub fn new(every: usize) -> LiveView {
+        LiveView {
+            every,
+            is_tty: io::stdout().is_tty(),
+        }
+    }
+
+    pub fn observe<Rnr: Runner>(
+        &self,
+        world: &World,
+        probes: &[Probe],
+        trials: usize,
+        runner: &Rnr,
+    ) {
+        if self.every == 0 || !trials.is_multiple_of(self.every) {
+            return;
+        }
+
+        // Fixed rng so that the view does not disturb the training rng.
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+        let solved: Vec<bool> = probes
+            .iter()
+            .map(|probe| runner.solves(world, probe.state, probe.maximum_steps, &mut rng))
+            .collect();
+
+        let num_solved = solved.iter().filter(|s| **s).count();
+
+        if self.is_tty && self.draw(world, probes, trials, &solved).is_ok() {
+            return;
+        }
+
+        println!(
+            "Trial {}: {}/{} probes solved {:?}",
+            trials,
+            num_solved,
+            probes.len(),
+            solved
+        );
+    }
+
+    fn draw(
+        &self,
+        world: &World,
+        probes: &[Probe],
+        trials: usize,
+        solved: &[bool],
+    ) -> crossterm::Result<()> {
+        let mut stdout = io::stdout();
+
+        execute!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+
+        let blocks: Vec<String> = probes
+            .iter()
+            .zip(solved)
+            .enumerate()
+            .map(|(i, (probe, probe_solved))| {
+                format!(
+                    "{}#{} {}",
+                    probe.state.display(world),
+                    i,
+                    if *probe_solved { "solved" } else { "unsolved" }
+                )
+            })
+            .collect();
+
+        let num_solved = solved.iter().filter(|s| **s).count();
+
+        // Raw mode is not enabled, so plain newlines are fine here.
+        writeln!(
+            stdout,
+            "Trial {} - {}/{} probes solved\n",
+            trials,
+            num_solved,
+            probes.len()
+        )?;
+        write!(stdout, "{}", side_by_side(&blocks))?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+}
+
+fn side_by_side(blocks: &[String]) -> String {
+    let columns: Vec<Vec<&str>> = blocks.iter().map(|b| b.lines().collect()).collect();
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .map(|lines| lines.iter().map(|l| l.chars().count()).max().unwrap_or(0))
+        .collect();
+
+    let height = columns.iter().map(|lines| lines.len()).max().unwrap_or(0);
+
+    let mut result = String::new();
+
+    for row in 0..height {
+        for (lines, width) in columns.iter().zip(&widths) {
+            let line = lines.get(row).cloned().unwrap_or("");
+            result += line;
+            result += &" ".repeat(width - line.chars().count() + 2);
+        }
+
+        result.truncate(result.trim_end().len());
+        result.push('\n');
+    }
+
+    result
+}
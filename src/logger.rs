@@ -0,0 +1,35 @@
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+// Prints what the library logs: warnings and errors to stderr with their
+// level, everything else to stdout as is, so a report reads as it always
+// has.
+struct StdoutLogger;
+
+impl Log for StdoutLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() <= Level::Warn {
+            eprintln!("{}: {}", record.level(), record.args());
+        } else {
+            println!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StdoutLogger = StdoutLogger;
+
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+
+    Ok(())
+}
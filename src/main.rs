@@ -2,6 +2,9 @@
 extern crate serde_derive;
 
 mod configuration;
+mod live_view;
+mod logger;
+mod play;
 mod replay;
 
 use rand::Rng;
@@ -11,45 +14,68 @@ use std::time;
 
 use rand_pcg::Pcg64Mcg;
 
-use rayon::prelude::*;
+use log::{info, warn, LevelFilter};
 
-use crate::configuration::{Configuration, ReportConfig, SolverChoice};
+use crate::configuration::{
+    Configuration, DoorMaxConfig, OptionQLearnerConfig, QLearnerConfig, QLearnerReplayConfig,
+    RandomSolverConfig, ReportConfig, SolverChoice, SparseQLearnerConfig,
+};
 
-use taxi::distribution::MeasureDistribution;
+use taxi::harness::{self, HarnessConfig, HarnessReport};
 use taxi::state::State;
-use taxi::world::{Costs, World};
+use taxi::state_indexer::StateIndexer;
+use taxi::world::{Costs, EnvRng, World};
 
 use taxi::doormax::DoorMax;
 use taxi::factoredrmax::FactoredRMax;
 use taxi::maxq::MaxQ;
-use taxi::qlearner::QLearner;
+use taxi::options::OptionQLearner;
+use taxi::qlearner::{QLearner, QLearnerReplay, SparseQLearner};
 use taxi::random_solver::RandomSolver;
 use taxi::rmax::RMax;
-use taxi::runner::{run_training_session, Probe, Runner};
+use taxi::runner::{
+    probe_table, run_training_session_with_options, Probe, ProbeOutcome, Runner, TrainingOptions,
+};
 
 use crossterm::event;
 use crossterm::event::{Event, KeyCode};
+use crossterm::tty::IsTty;
 use std::io;
 
+use crate::live_view::LiveView;
+use crate::play::Play;
 use crate::replay::Replay;
 
 enum AppError {
     NoConfiguration,
+    InvalidArguments(String),
     Configuration(configuration::Error),
     World(taxi::world::Error),
     BuildProbes(taxi::state::Error),
+    InvalidProbe(usize, String),
+    RandomSolver(taxi::random_solver::Error),
     Runner(taxi::runner::Error),
     ReplayRunnerNotConfigured(SolverChoice),
+    PassengerWanderUnsupported(SolverChoice),
     ReplayTraining(taxi::runner::Error),
     ReplayState(taxi::state::Error),
     WaitForReplay(crossterm::ErrorKind),
     Replay(io::Error),
+    SaveAttempt(taxi::runner::Error),
+    LoadAttempt(taxi::runner::Error),
+    Logger(log::SetLoggerError),
+    PlayNotConfigured,
+    PlayState(taxi::state::Error),
+    Play(io::Error),
 }
 
 impl fmt::Debug for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             AppError::NoConfiguration => write!(f, "Configuration file not specified."),
+            AppError::InvalidArguments(ref message) => {
+                write!(f, "Invalid arguments: {}", message)
+            }
             AppError::Configuration(ref config_error) => {
                 write!(f, "Failed to read configuration:\n{:?}", config_error)
             }
@@ -59,6 +85,12 @@ impl fmt::Debug for AppError {
             AppError::BuildProbes(ref state_error) => {
                 write!(f, "Failed to build probe state:\n{:?}", state_error)
             }
+            AppError::InvalidProbe(index, ref message) => {
+                write!(f, "Probe {} is invalid: {}", index, message)
+            }
+            AppError::RandomSolver(ref random_error) => {
+                write!(f, "Failed to build random solver:\n{:?}", random_error)
+            }
             AppError::Runner(ref runner_error) => {
                 write!(f, "Failed to run trial:\n{:?}", runner_error)
             }
@@ -68,6 +100,12 @@ impl fmt::Debug for AppError {
                  for that solver.",
                 runner_type
             ),
+            AppError::PassengerWanderUnsupported(ref runner_type) => write!(
+                f,
+                "The {:?} solver only indexes passengers at the fixed positions, \
+                 so it cannot learn with passenger_wander set.",
+                runner_type
+            ),
             AppError::ReplayTraining(ref runner_error) => {
                 write!(f, "Failed to run training for replay:\n{:?}", runner_error)
             }
@@ -82,6 +120,27 @@ impl fmt::Debug for AppError {
             AppError::Replay(ref replay_error) => {
                 write!(f, "Failed to replay:\n{:?}", replay_error)
             }
+            AppError::SaveAttempt(ref runner_error) => {
+                write!(f, "Failed to save attempt:\n{:?}", runner_error)
+            }
+            AppError::LoadAttempt(ref runner_error) => {
+                write!(f, "Failed to load attempt:\n{:?}", runner_error)
+            }
+            AppError::Logger(ref logger_error) => {
+                write!(f, "Failed to set up logging:\n{:?}", logger_error)
+            }
+            AppError::PlayNotConfigured => write!(
+                f,
+                "Playing needs a replay section to give the starting state."
+            ),
+            AppError::PlayState(ref state_error) => {
+                write!(
+                    f,
+                    "Failed to build starting state to play:\n{:?}",
+                    state_error
+                )
+            }
+            AppError::Play(ref play_error) => write!(f, "Failed to play:\n{:?}", play_error),
         }
     }
 }
@@ -93,17 +152,85 @@ fn main() -> Result<(), AppError> {
         return Err(AppError::NoConfiguration);
     }
 
+    let command_line = parse_command_line(&args[2..])?;
+    logger::init(command_line.log_level).map_err(AppError::Logger)?;
+    let live_view = command_line.live_view;
+    let show_probe_table = command_line.probe_table;
+
     let config = Configuration::from_file(&args[1]).map_err(AppError::Configuration)?;
 
-    let costs = Costs::new(
-        config.costs.movement,
-        config.costs.miss_pickup,
-        config.costs.miss_dropoff,
-        config.costs.empty_dropoff,
-    );
-    let world = World::build_from_str(&config.world, costs).map_err(AppError::World)?;
+    let costs = Costs {
+        delivery_reward: config.costs.delivery_reward,
+        wait: config.costs.wait.unwrap_or(config.costs.movement),
+        blocked_movement: config
+            .costs
+            .blocked_movement
+            .unwrap_or(config.costs.movement),
+        pickup: config.costs.pickup,
+        ..Costs::new(
+            config.costs.movement,
+            config.costs.miss_pickup,
+            config.costs.miss_dropoff,
+            config.costs.empty_dropoff,
+        )
+    };
+    let mut world = World::build_from_str(&config.world, costs).map_err(AppError::World)?;
+    for (&id, &reward) in &config.costs.delivery_rewards {
+        world
+            .set_delivery_reward(id, Some(reward))
+            .map_err(AppError::World)?;
+    }
+    world.destination_churn = config.destination_churn;
+    world.passenger_wander = config.passenger_wander;
+    if world.passenger_wander > 0.0 {
+        // Only the QLearner indexes passengers away from the fixed positions.
+        let unsupported = [
+            (
+                config.q_learner_replay.is_some(),
+                SolverChoice::QLearnerReplay,
+            ),
+            (
+                config.sparse_q_learner.is_some(),
+                SolverChoice::SparseQLearner,
+            ),
+            (config.r_max.is_some(), SolverChoice::RMax),
+            (config.factored_r_max.is_some(), SolverChoice::FactoredRMax),
+            (config.max_q.is_some(), SolverChoice::MaxQ),
+            (config.door_max.is_some(), SolverChoice::DoorMax),
+            (
+                config.option_q_learner.is_some(),
+                SolverChoice::OptionQLearner,
+            ),
+        ];
+        if let Some(&(_, solver)) = unsupported.iter().find(|&&(configured, _)| configured) {
+            return Err(AppError::PassengerWanderUnsupported(solver));
+        }
+    }
+    world.pickup_refusal = config.pickup_refusal;
+    world.goto_actions = config.goto_actions;
+    if let Some((seed_high, seed_low)) = config.env_seed {
+        world.env_rng = EnvRng::new((seed_high as u128).rotate_left(64) + (seed_low as u128));
+    }
+
+    if command_line.show_world {
+        return show_world(&config, &world);
+    }
+
+    if let Some(ref path) = command_line.replay_file {
+        let replay = Replay::from_file(&world, path).map_err(AppError::LoadAttempt)?;
+        return replay.run().map_err(AppError::Replay);
+    }
+
+    if command_line.play {
+        return play(&config, &world);
+    }
+
     let probes = build_probes(&config, &world)?;
 
+    // Solvers are built with the transformed costs so that their initial
+    // values match the rewards they will see.
+    let learning_world = config.reward_transform.transform().transform_world(&world);
+
     let root_seed = if let Some((seed_high, seed_low)) = config.root_seed {
         (seed_high as u128).rotate_left(64) + (seed_low as u128)
     } else {
@@ -111,54 +238,66 @@ fn main() -> Result<(), AppError> {
     };
 
     if config.sessions > 0 {
+        let harness_config = HarnessConfig::new(
+            world.clone(),
+            probes.clone(),
+            config.sessions,
+            config.max_trials,
+            config.max_trial_steps,
+            root_seed,
+        )
+        .with_options(training_options(&config));
+
         let mut results = Vec::new();
 
         if let Some(ref random_config) = config.random_solver {
+            let random_solver = build_random_solver(random_config)?;
+
             gather_stats(
-                RandomSolver::new,
+                || random_solver.clone(),
                 random_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
 
         if let Some(ref qlearner_config) = config.q_learner {
             gather_stats(
-                || {
-                    QLearner::new(
-                        &world,
-                        qlearner_config.alpha,
-                        qlearner_config.gamma,
-                        qlearner_config.epsilon,
-                    )
-                },
+                || build_qlearner(&learning_world, qlearner_config),
                 qlearner_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
+                &mut results,
+            )?;
+        };
+
+        if let Some(ref qlearner_replay_config) = config.q_learner_replay {
+            gather_stats(
+                || build_qlearner_replay(&learning_world, qlearner_replay_config),
+                qlearner_replay_config,
+                &harness_config,
+                show_probe_table,
+                &mut results,
+            )?;
+        };
+
+        if let Some(ref sparse_qlearner_config) = config.sparse_q_learner {
+            gather_stats(
+                || build_sparse_qlearner(&learning_world, sparse_qlearner_config),
+                sparse_qlearner_config,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
 
         if let Some(ref rmax_config) = config.r_max {
             gather_stats(
-                || {
-                    RMax::new(
-                        &world,
-                        rmax_config.gamma,
-                        rmax_config.known_count,
-                        rmax_config.error_delta,
-                    )
-                },
+                || RMax::with_params(&learning_world, rmax_config.params(&learning_world)),
                 rmax_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
@@ -166,18 +305,14 @@ fn main() -> Result<(), AppError> {
         if let Some(ref factored_rmax_config) = config.factored_r_max {
             gather_stats(
                 || {
-                    FactoredRMax::new(
-                        &world,
-                        factored_rmax_config.gamma,
-                        factored_rmax_config.known_count,
-                        factored_rmax_config.error_delta,
+                    FactoredRMax::with_params(
+                        &learning_world,
+                        factored_rmax_config.params(&learning_world),
                     )
                 },
                 factored_rmax_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
@@ -185,168 +320,188 @@ fn main() -> Result<(), AppError> {
         if let Some(ref maxq_config) = config.max_q {
             gather_stats(
                 || {
-                    MaxQ::new(
-                        &world,
+                    MaxQ::with_init(
+                        &learning_world,
                         maxq_config.alpha,
                         maxq_config.gamma,
                         maxq_config.epsilon,
                         maxq_config.show_learning,
+                        maxq_config.init.strategy(),
                     )
                 },
                 maxq_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
 
         if let Some(ref doormax_config) = config.door_max {
             gather_stats(
-                || {
-                    DoorMax::new(
-                        &world,
-                        doormax_config.gamma,
-                        doormax_config.use_reward_learner,
-                        doormax_config.known_count,
-                        doormax_config.error_delta,
-                    )
-                },
+                || build_doormax(&learning_world, doormax_config),
                 doormax_config,
-                &world,
-                &probes,
-                &config,
-                root_seed,
+                &harness_config,
+                show_probe_table,
                 &mut results,
             )?;
         };
 
-        println!();
-
-        for (solver_choice, stats) in results {
-            let (avg_steps, stddev_steps) = stats.distribution.get_distribution();
+        if let Some(ref option_qlearner_config) = config.option_q_learner {
+            gather_stats(
+                || build_option_qlearner(&learning_world, option_qlearner_config),
+                option_qlearner_config,
+                &harness_config,
+                show_probe_table,
+                &mut results,
+            )?;
+        };
 
-            let elapsed_time =
-                stats.duration.as_secs() as f64 + f64::from(stats.duration.subsec_nanos()) * 1e-9;
+        println!();
 
+        for (solver_choice, report) in results {
             println!(
-                "{:?} - finished {} sessions in {:.1} average steps with stddev of {:.2} \
-                 in {:.3} secs. Using seed [{}, {}]",
+                "{:?} - {} Using seed [{}, {}]",
                 solver_choice,
-                stats.distribution.get_count() as usize,
-                avg_steps,
-                stddev_steps,
-                elapsed_time,
+                report.summary(),
                 root_seed.rotate_right(64) as i64,
                 root_seed as i64,
             );
         }
     }
 
-    for (seed_high, seed_low) in config.rerun_seeds {
+    for &(seed_high, seed_low) in &config.rerun_seeds {
         let seed = (seed_high as u128).rotate_left(64) + (seed_low as u128);
 
         if let Some(ref random_config) = config.random_solver {
+            let random_solver = build_random_solver(random_config)?;
+
             rerun_session(
-                RandomSolver::new,
+                || random_solver.clone(),
                 random_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
             )?;
         };
 
         if let Some(ref qlearner_config) = config.q_learner {
             rerun_session(
-                || {
-                    QLearner::new(
-                        &world,
-                        qlearner_config.alpha,
-                        qlearner_config.gamma,
-                        qlearner_config.epsilon,
-                    )
-                },
+                || build_qlearner(&learning_world, qlearner_config),
                 qlearner_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
+                seed,
+                live_view,
+                show_probe_table,
+            )?;
+        };
+
+        if let Some(ref qlearner_replay_config) = config.q_learner_replay {
+            rerun_session(
+                || build_qlearner_replay(&learning_world, qlearner_replay_config),
+                qlearner_replay_config,
+                &world,
+                &probes,
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
+            )?;
+        };
+
+        if let Some(ref sparse_qlearner_config) = config.sparse_q_learner {
+            rerun_session(
+                || build_sparse_qlearner(&learning_world, sparse_qlearner_config),
+                sparse_qlearner_config,
+                &world,
+                &probes,
+                &config,
+                seed,
+                live_view,
+                show_probe_table,
             )?;
         };
 
         if let Some(ref rmax_config) = config.r_max {
             rerun_session(
-                || {
-                    RMax::new(
-                        &world,
-                        rmax_config.gamma,
-                        rmax_config.known_count,
-                        rmax_config.error_delta,
-                    )
-                },
+                || RMax::with_params(&learning_world, rmax_config.params(&learning_world)),
                 rmax_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
             )?;
         };
 
         if let Some(ref factored_rmax_config) = config.factored_r_max {
             rerun_session(
                 || {
-                    FactoredRMax::new(
-                        &world,
-                        factored_rmax_config.gamma,
-                        factored_rmax_config.known_count,
-                        factored_rmax_config.error_delta,
+                    FactoredRMax::with_params(
+                        &learning_world,
+                        factored_rmax_config.params(&learning_world),
                     )
                 },
                 factored_rmax_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
             )?;
         };
 
         if let Some(ref maxq_config) = config.max_q {
             rerun_session(
                 || {
-                    MaxQ::new(
-                        &world,
+                    MaxQ::with_init(
+                        &learning_world,
                         maxq_config.alpha,
                         maxq_config.gamma,
                         maxq_config.epsilon,
                         maxq_config.show_learning,
+                        maxq_config.init.strategy(),
                     )
                 },
                 maxq_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
             )?;
         };
 
         if let Some(ref doormax_config) = config.door_max {
             rerun_session(
-                || {
-                    DoorMax::new(
-                        &world,
-                        doormax_config.gamma,
-                        doormax_config.use_reward_learner,
-                        doormax_config.known_count,
-                        doormax_config.error_delta,
-                    )
-                },
+                || build_doormax(&learning_world, doormax_config),
                 doormax_config,
                 &world,
                 &probes,
-                (config.max_trials, config.max_trial_steps),
+                &config,
                 seed,
+                live_view,
+                show_probe_table,
+            )?;
+        };
+
+        if let Some(ref option_qlearner_config) = config.option_q_learner {
+            rerun_session(
+                || build_option_qlearner(&learning_world, option_qlearner_config),
+                option_qlearner_config,
+                &world,
+                &probes,
+                &config,
+                seed,
+                live_view,
+                show_probe_table,
             )?;
         };
     }
@@ -357,15 +512,15 @@ fn main() -> Result<(), AppError> {
         if let Some(ref replay_config) = config.replay {
             match replay_config.solver {
                 SolverChoice::Random => {
-                    if config.random_solver.is_some() {
+                    if let Some(ref random_config) = config.random_solver {
                         run_replay(
-                            &mut RandomSolver::new(),
+                            &mut build_random_solver(random_config)?,
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -374,18 +529,43 @@ fn main() -> Result<(), AppError> {
                 SolverChoice::QLearner => {
                     if let Some(ref qlearner_config) = config.q_learner {
                         run_replay(
-                            &mut QLearner::new(
-                                &world,
-                                qlearner_config.alpha,
-                                qlearner_config.gamma,
-                                qlearner_config.epsilon,
-                            ),
+                            &mut build_qlearner(&learning_world, qlearner_config),
+                            replay_config,
+                            &world,
+                            &probes,
+                            &config,
+                            &mut rng,
+                            live_view,
+                        )?
+                    } else {
+                        return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
+                    }
+                }
+                SolverChoice::QLearnerReplay => {
+                    if let Some(ref qlearner_replay_config) = config.q_learner_replay {
+                        run_replay(
+                            &mut build_qlearner_replay(&learning_world, qlearner_replay_config),
+                            replay_config,
+                            &world,
+                            &probes,
+                            &config,
+                            &mut rng,
+                            live_view,
+                        )?
+                    } else {
+                        return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
+                    }
+                }
+                SolverChoice::SparseQLearner => {
+                    if let Some(ref sparse_qlearner_config) = config.sparse_q_learner {
+                        run_replay(
+                            &mut build_sparse_qlearner(&learning_world, sparse_qlearner_config),
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -394,18 +574,16 @@ fn main() -> Result<(), AppError> {
                 SolverChoice::RMax => {
                     if let Some(ref rmax_config) = config.r_max {
                         run_replay(
-                            &mut RMax::new(
-                                &world,
-                                rmax_config.gamma,
-                                rmax_config.known_count,
-                                rmax_config.error_delta,
+                            &mut RMax::with_params(
+                                &learning_world,
+                                rmax_config.params(&learning_world),
                             ),
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -414,18 +592,16 @@ fn main() -> Result<(), AppError> {
                 SolverChoice::FactoredRMax => {
                     if let Some(ref factored_rmax_config) = config.factored_r_max {
                         run_replay(
-                            &mut FactoredRMax::new(
-                                &world,
-                                factored_rmax_config.gamma,
-                                factored_rmax_config.known_count,
-                                factored_rmax_config.error_delta,
+                            &mut FactoredRMax::with_params(
+                                &learning_world,
+                                factored_rmax_config.params(&learning_world),
                             ),
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -434,19 +610,20 @@ fn main() -> Result<(), AppError> {
                 SolverChoice::MaxQ => {
                     if let Some(ref maxq_config) = config.max_q {
                         run_replay(
-                            &mut MaxQ::new(
-                                &world,
+                            &mut MaxQ::with_init(
+                                &learning_world,
                                 maxq_config.alpha,
                                 maxq_config.gamma,
                                 maxq_config.epsilon,
                                 maxq_config.show_learning,
+                                maxq_config.init.strategy(),
                             ),
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -455,19 +632,28 @@ fn main() -> Result<(), AppError> {
                 SolverChoice::DoorMax => {
                     if let Some(ref doormax_config) = config.door_max {
                         run_replay(
-                            &mut DoorMax::new(
-                                &world,
-                                doormax_config.gamma,
-                                doormax_config.use_reward_learner,
-                                doormax_config.known_count,
-                                doormax_config.error_delta,
-                            ),
+                            &mut build_doormax(&learning_world, doormax_config),
+                            replay_config,
+                            &world,
+                            &probes,
+                            &config,
+                            &mut rng,
+                            live_view,
+                        )?
+                    } else {
+                        return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
+                    }
+                }
+                SolverChoice::OptionQLearner => {
+                    if let Some(ref option_qlearner_config) = config.option_q_learner {
+                        run_replay(
+                            &mut build_option_qlearner(&learning_world, option_qlearner_config),
                             replay_config,
                             &world,
                             &probes,
-                            config.max_trials,
-                            config.max_trial_steps,
+                            &config,
                             &mut rng,
+                            live_view,
                         )?
                     } else {
                         return Err(AppError::ReplayRunnerNotConfigured(replay_config.solver));
@@ -480,10 +666,130 @@ fn main() -> Result<(), AppError> {
     Ok(())
 }
 
+struct CommandLine {
+    live_view: Option<LiveView>,
+    show_world: bool,
+    probe_table: bool,
+    replay_file: Option<String>,
+    play: bool,
+    log_level: LevelFilter,
+}
+
+// Parses the optional flags after the configuration file:
+//   `--live N` renders the probes every N trials while rerunning seeds or
+//   training for a replay.
+//   `--show-world` prints the parsed world and probes, then exits.
+//   `--probe-table` prints how the trained solver does on each probe after
+//   every session.
+//   `--replay-file PATH` replays an attempt saved by a replay section's
+//   save_to, without any training, then exits.
+//   `--play` lets you drive the taxi from the replay section's starting
+//   state, without any training, then exits.
+//   `--log LEVEL` sets how much the solvers and sessions log, one of off,
+//   error, warn, info (the default), debug or trace.
+fn parse_command_line(args: &[String]) -> Result<CommandLine, AppError> {
+    let mut command_line = CommandLine {
+        live_view: None,
+        show_world: false,
+        probe_table: false,
+        replay_file: None,
+        play: false,
+        log_level: LevelFilter::Info,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--live" {
+            let every = args
+                .next()
+                .and_then(|every| every.parse::<usize>().ok())
+                .filter(|every| *every > 0)
+                .ok_or_else(|| {
+                    AppError::InvalidArguments("--live expects a positive trial count".to_string())
+                })?;
+
+            command_line.live_view = Some(LiveView::new(every));
+        } else if arg == "--show-world" {
+            command_line.show_world = true;
+        } else if arg == "--probe-table" {
+            command_line.probe_table = true;
+        } else if arg == "--replay-file" {
+            let path = args.next().ok_or_else(|| {
+                AppError::InvalidArguments("--replay-file expects a path".to_string())
+            })?;
+
+            command_line.replay_file = Some(path.clone());
+        } else if arg == "--play" {
+            command_line.play = true;
+        } else if arg == "--log" {
+            command_line.log_level = args
+                .next()
+                .and_then(|level| level.parse::<LevelFilter>().ok())
+                .ok_or_else(|| {
+                    AppError::InvalidArguments(
+                        "--log expects off, error, warn, info, debug or trace".to_string(),
+                    )
+                })?;
+        } else {
+            return Err(AppError::InvalidArguments(format!(
+                "unknown argument '{}'",
+                arg
+            )));
+        }
+    }
+
+    Ok(command_line)
+}
+
+// The world is printed before the probes are built, so a map can be
+// checked even when a probe does not fit it.
+fn show_world(config: &Configuration, world: &World) -> Result<(), AppError> {
+    for line in world.display_strings() {
+        println!("{}", line);
+    }
+    println!("{} states", StateIndexer::new(world).num_states());
+
+    for (index, probe) in build_probes(config, world)?.iter().enumerate() {
+        println!("\nProbe {}, {} steps:", index, probe.maximum_steps);
+        print!("{}", probe.state.display_with_coords(world));
+    }
+
+    Ok(())
+}
+
+fn play(config: &Configuration, world: &World) -> Result<(), AppError> {
+    let replay_config = config.replay.as_ref().ok_or(AppError::PlayNotConfigured)?;
+
+    let start = State::build(
+        world,
+        replay_config.taxi_pos,
+        replay_config.passenger_loc,
+        replay_config.destination_loc,
+    )
+    .map_err(AppError::PlayState)?;
+
+    let result = Play::new(world, start)
+        .run(&mut rand::thread_rng())
+        .map_err(AppError::Play)?;
+
+    println!(
+        "{} after {} steps with total reward {}.",
+        if result.delivered {
+            "Delivered"
+        } else {
+            "Quit"
+        },
+        result.steps,
+        result.reward
+    );
+
+    Ok(())
+}
+
 fn build_probes(config: &Configuration, world: &World) -> Result<Vec<Probe>, AppError> {
     let mut probes = Vec::new();
 
-    for probe_config in &config.probes {
+    for (index, probe_config) in config.probes.iter().enumerate() {
         let state = State::build(
             world,
             probe_config.taxi_pos,
@@ -492,133 +798,209 @@ fn build_probes(config: &Configuration, world: &World) -> Result<Vec<Probe>, App
         )
         .map_err(AppError::BuildProbes)?;
 
-        probes.push(Probe::new(state, probe_config.max_steps));
+        let probe = Probe {
+            expected_steps: probe_config.expected_steps,
+            weight: probe_config.weight,
+            ..Probe::new(state, probe_config.max_steps)
+        };
+
+        probe
+            .validate(world)
+            .map_err(|message| AppError::InvalidProbe(index, message))?;
+
+        probes.push(probe);
     }
 
     Ok(probes)
 }
 
-#[derive(Default)]
-struct Stats {
-    distribution: MeasureDistribution,
-    duration: time::Duration,
+fn build_random_solver(random_config: &RandomSolverConfig) -> Result<RandomSolver, AppError> {
+    match random_config.weights {
+        Some(weights) => RandomSolver::with_weights(weights).map_err(AppError::RandomSolver),
+        None => Ok(RandomSolver::new()),
+    }
+}
+
+fn build_qlearner(world: &World, qlearner_config: &QLearnerConfig) -> QLearner {
+    // A wandering passenger leaves the fixed positions.
+    let state_indexer = if world.passenger_wander > 0.0 {
+        StateIndexer::with_passenger_cells(world)
+    } else {
+        StateIndexer::new(world)
+    };
+
+    let qlearner = QLearner::with_state_indexer(
+        world,
+        qlearner_config.alpha,
+        qlearner_config.gamma,
+        qlearner_config.epsilon,
+        qlearner_config.init.strategy(),
+        state_indexer,
+    )
+    .with_epsilon_schedule(qlearner_config.epsilon_schedule.schedule())
+    .with_progress_ties(qlearner_config.progress_ties);
+
+    match qlearner_config.max_steps {
+        Some(max_steps) => qlearner.with_default_max_steps(max_steps),
+        None => qlearner,
+    }
+}
+
+fn build_option_qlearner(
+    world: &World,
+    option_qlearner_config: &OptionQLearnerConfig,
+) -> OptionQLearner {
+    OptionQLearner::new(
+        world,
+        option_qlearner_config.options.options(world),
+        option_qlearner_config.alpha,
+        option_qlearner_config.gamma,
+        option_qlearner_config.epsilon,
+    )
+}
+
+fn build_qlearner_replay(
+    world: &World,
+    qlearner_replay_config: &QLearnerReplayConfig,
+) -> QLearnerReplay {
+    let qlearner_replay = QLearnerReplay::new(
+        world,
+        qlearner_replay_config.alpha,
+        qlearner_replay_config.gamma,
+        qlearner_replay_config.epsilon,
+        qlearner_replay_config.buffer_size,
+        qlearner_replay_config.batch_size,
+    );
+
+    match qlearner_replay_config.max_steps {
+        Some(max_steps) => qlearner_replay.with_default_max_steps(max_steps),
+        None => qlearner_replay,
+    }
+}
+
+fn build_sparse_qlearner(
+    world: &World,
+    sparse_qlearner_config: &SparseQLearnerConfig,
+) -> SparseQLearner {
+    let sparse_qlearner = SparseQLearner::new(
+        world,
+        sparse_qlearner_config.alpha,
+        sparse_qlearner_config.gamma,
+        sparse_qlearner_config.epsilon,
+    )
+    .with_epsilon_schedule(sparse_qlearner_config.epsilon_schedule.schedule());
+
+    match sparse_qlearner_config.max_steps {
+        Some(max_steps) => sparse_qlearner.with_default_max_steps(max_steps),
+        None => sparse_qlearner,
+    }
+}
+
+fn build_doormax(world: &World, doormax_config: &DoorMaxConfig) -> DoorMax {
+    DoorMax::new(
+        world,
+        doormax_config.gamma,
+        doormax_config.use_reward_learner,
+        doormax_config.known_count,
+        doormax_config.error_delta,
+    )
+    .with_directed_exploration(doormax_config.directed_exploration)
 }
 
 fn gather_stats<B, Rnr>(
     builder: B,
     report_config: &dyn ReportConfig,
-    world: &World,
-    probes: &[Probe],
-    config: &Configuration,
-    root_seed: u128,
-    results: &mut Vec<(SolverChoice, Stats)>,
+    harness_config: &HarnessConfig,
+    show_probe_table: bool,
+    results: &mut Vec<(SolverChoice, HarnessReport)>,
 ) -> Result<(), AppError>
 where
     B: Fn() -> Rnr + Sync,
-    Rnr: Runner + Sync,
+    Rnr: Runner,
 {
-    let mut seed_generator = Pcg64Mcg::new(root_seed);
-    let session_ids: Vec<(usize, u128)> = (0..config.sessions)
-        .map(|session_id| (session_id, seed_generator.gen()))
-        .collect();
-
     let solver_choice = report_config.solver_choice();
     let report = report_config.report();
 
-    let stats = session_ids
-        .par_iter()
-        .fold(
-            || Ok(Stats::default()),
-            |current_result, (session_number, seed)| -> Result<Stats, AppError> {
-                current_result.and_then(|mut stats| {
-                    let start_time = time::Instant::now();
-
-                    let mut solver = builder();
-
-                    let mut rng = Pcg64Mcg::new(*seed);
-
-                    let training_step_count = run_training_session(
-                        world,
-                        probes,
-                        config.max_trials,
-                        config.max_trial_steps,
-                        &mut solver,
-                        &mut rng,
+    let harness_report =
+        harness::run_with_observer(harness_config, builder, |session_report, solver| {
+            let seed = session_report.seed;
+            let session_result = &session_report.result;
+            let elapsed_time = session_report.duration.as_secs_f64();
+
+            match session_result.steps {
+                Some(num_steps) => {
+                    info!(
+                        "{:?} - Finished session {} [{}, {}] in {} steps with average \
+                         return {:.2} in {:.3} secs.",
+                        solver_choice,
+                        session_report.session,
+                        seed.rotate_right(64) as i64,
+                        seed as i64,
+                        num_steps,
+                        session_result.average_reward(),
+                        elapsed_time,
+                    );
+                }
+                None => {
+                    info!(
+                        "{:?} - Failed session {} [{},{}] with maximums {} trials of {} steps \
+                         in {:.3} secs.",
+                        solver_choice,
+                        session_report.session,
+                        seed.rotate_right(64) as i64,
+                        seed as i64,
+                        harness_config.max_trials,
+                        harness_config.max_trial_steps,
+                        elapsed_time,
+                    );
+                }
+            };
+
+            report_probe_outcomes(solver_choice, &session_result.probe_outcomes);
+
+            if show_probe_table {
+                let mut rng = Pcg64Mcg::new(seed);
+                print!(
+                    "{}",
+                    probe_table(
+                        &harness_config.world,
+                        &harness_config.probes,
+                        solver,
+                        &mut rng
                     )
-                    .map_err(AppError::Runner)?;
-
-                    let duration = start_time.elapsed();
-                    let elapsed_time =
-                        duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9;
-
-                    match training_step_count {
-                        Some(num_steps) => {
-                            println!(
-                                "{:?} - Finished session {} [{}, {}] in {} steps in {:.3} secs.",
-                                solver_choice,
-                                session_number,
-                                seed.rotate_right(64) as i64,
-                                *seed as i64,
-                                num_steps,
-                                elapsed_time,
-                            );
-                            stats.distribution.add_value(num_steps as f64);
-                        }
-                        None => {
-                            println!(
-                                "{:?} - Failed session {} [{},{}] with maximums {} trials of {} steps \
-                                 in {:.3} secs.",
-                                solver_choice,
-                                session_number,
-                                seed.rotate_right(64) as i64,
-                                *seed as i64,
-                                config.max_trials,
-                                config.max_trial_steps,
-                                elapsed_time,
-                            );
-                        }
-                    };
-
-                    stats.duration += duration;
-
-                    // This may overlap with other reports, should we guard with a mutex?
-                    if report {
-                        // println!("Report Reults #{} : BEGIN", session_number);
-                        solver.report_training_result(world, training_step_count);
-                        // println!("Report Reults #{} : END", session_number);
-                    }
+                );
+            }
 
-                    Ok(stats)
-                })
-            },
-        )
-        .reduce(
-            || Ok(Stats::default()),
-            |result_a: Result<Stats, AppError>,
-             result_b: Result<Stats, AppError>|
-             -> Result<Stats, AppError> {
-                result_a.and_then(|mut stats_a| {
-                    result_b.and_then(|stats_b| {
-                        stats_a.distribution.add_distribution(&stats_b.distribution);
-                        stats_a.duration += stats_b.duration;
-                        Ok(stats_a)
-                    })
-                })
-            },
-        )?;
-
-    results.push((solver_choice, stats));
+            // This may overlap with other reports, should we guard with a mutex?
+            if report {
+                solver.report_training_result(&harness_config.world, session_result.steps);
+            }
+        })
+        .map_err(AppError::Runner)?;
+
+    results.push((solver_choice, harness_report));
 
     Ok(())
 }
 
+fn training_options(config: &Configuration) -> TrainingOptions {
+    TrainingOptions {
+        reward_transform: config.reward_transform.transform(),
+        start_states: config.start_states.start_states(),
+        stop_on_convergence: config.stop_on_convergence,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn rerun_session<B, Rnr>(
     builder: B,
     report_config: &dyn ReportConfig,
     world: &World,
     probes: &[Probe],
-    (max_trials, max_trial_steps): (usize, usize),
+    config: &Configuration,
     seed: u128,
+    live_view: Option<LiveView>,
+    show_probe_table: bool,
 ) -> Result<(), AppError>
 where
     B: Fn() -> Rnr,
@@ -626,69 +1008,115 @@ where
 {
     let solver_choice = report_config.solver_choice();
 
+    // A fresh copy of a seeded environment, as each harness session gets.
+    let world = &world.clone();
+
     let start_time = time::Instant::now();
 
     let mut solver = builder();
     let mut rng = Pcg64Mcg::new(seed);
 
-    let training_step_count = run_training_session(
+    let session_result = run_training_session_with_options(
         world,
         probes,
-        max_trials,
-        max_trial_steps,
+        (config.max_trials, config.max_trial_steps),
         &mut solver,
         &mut rng,
+        &training_options(config),
+        |trials, solver| {
+            if let Some(ref view) = live_view {
+                view.observe(world, probes, trials, solver);
+            }
+        },
     )
     .map_err(AppError::Runner)?;
 
     let duration = start_time.elapsed();
     let elapsed_time = duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9;
 
-    match training_step_count {
+    match session_result.steps {
         Some(num_steps) => {
-            println!(
-                "{:?} - Finished seed [{}, {}] in {} steps in {:.3} secs.",
+            info!(
+                "{:?} - Finished seed [{}, {}] in {} steps with average return {:.2} \
+                 in {:.3} secs.",
                 solver_choice,
                 seed.rotate_right(64) as i64,
                 seed as i64,
                 num_steps,
+                session_result.average_reward(),
                 elapsed_time,
             );
         }
         None => {
-            println!(
+            info!(
                 "{:?} - Failed seed [{},{}] with maximums {} trials of {} steps \
                  in {:.3} secs.",
                 solver_choice,
                 seed.rotate_right(64) as i64,
                 seed as i64,
-                max_trials,
-                max_trial_steps,
+                config.max_trials,
+                config.max_trial_steps,
                 elapsed_time,
             );
         }
     };
 
-    solver.report_training_result(world, training_step_count);
+    report_probe_outcomes(solver_choice, &session_result.probe_outcomes);
+
+    if show_probe_table {
+        print!("{}", probe_table(world, probes, &solver, &mut rng));
+    }
+
+    solver.report_training_result(world, session_result.steps);
 
     Ok(())
 }
 
+// Only probes that missed their expected step count are worth a line.
+fn report_probe_outcomes(solver_choice: SolverChoice, probe_outcomes: &[ProbeOutcome]) {
+    for (probe_index, outcome) in probe_outcomes.iter().enumerate() {
+        match *outcome {
+            ProbeOutcome::Feasible { steps, expected } => warn!(
+                "{:?} - Probe {} took {} steps, expected {}.",
+                solver_choice, probe_index, steps, expected
+            ),
+            ProbeOutcome::BeatExpected { steps, expected } => warn!(
+                "{:?} - Probe {} took {} steps, fewer than the expected {}, \
+                 expected_steps should be lowered.",
+                solver_choice, probe_index, steps, expected
+            ),
+            _ => {}
+        }
+    }
+}
+
 fn run_replay<Rnr, R>(
     solver: &mut Rnr,
     replay_config: &configuration::Replay,
     world: &World,
     probes: &[Probe],
-    max_trials: usize,
-    max_trial_steps: usize,
+    config: &Configuration,
     mut rng: &mut R,
+    live_view: Option<LiveView>,
 ) -> Result<(), AppError>
 where
     Rnr: Runner,
     R: Rng,
 {
-    run_training_session(world, probes, max_trials, max_trial_steps, solver, &mut rng)
-        .map_err(AppError::ReplayTraining)?;
+    run_training_session_with_options(
+        world,
+        probes,
+        (config.max_trials, config.max_trial_steps),
+        solver,
+        &mut rng,
+        &training_options(config),
+        |trials, solver| {
+            if let Some(ref view) = live_view {
+                view.observe(world, probes, trials, solver);
+            }
+        },
+    )
+    .map_err(AppError::ReplayTraining)?;
 
     let do_replay = ask_for_replay().map_err(AppError::WaitForReplay)?;
 
@@ -703,6 +1131,10 @@ where
 
         let attempt = solver.attempt(world, replay_state, replay_config.max_steps, &mut rng);
 
+        if let Some(ref path) = replay_config.save_to {
+            attempt.save(world, path).map_err(AppError::SaveAttempt)?;
+        }
+
         let replay = Replay::new(world, attempt);
         replay.run().map_err(AppError::Replay)?;
     }
@@ -713,6 +1145,15 @@ where
 fn ask_for_replay() -> crossterm::Result<bool> {
     println!("Press Enter to see replay.  q to exit.");
 
+    // Without a terminal on stdin, read a single line instead of waiting
+    // for key events, so a closed or piped stdin cannot block forever.
+    if !io::stdin().is_tty() {
+        let mut line = String::new();
+        let num_read = io::stdin().read_line(&mut line)?;
+
+        return Ok(num_read > 0 && !line.trim().eq_ignore_ascii_case("q"));
+    }
+
     loop {
         let event = event::read()?;
 
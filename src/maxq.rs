@@ -6,11 +6,12 @@ mod qnode;
 use rand::Rng;
 
 use crate::actions::Actions;
-use crate::state::State;
+use crate::qlearner::InitStrategy;
+use crate::state::{PassengerState, State};
 use crate::state_indexer::StateIndexer;
 use crate::world::World;
 
-use crate::runner::{Attempt, Runner};
+use crate::runner::{Attempt, Runner, Trial};
 
 use self::nodestorage::NodeStorage;
 use self::qnode::QChild;
@@ -27,18 +28,34 @@ pub struct MaxQParams {
 #[derive(Debug, Clone)]
 pub struct MaxQ {
     params: MaxQParams,
+    // Kept to set the initial values again when gamma changes.
+    init: InitStrategy,
     nodes: NodeStorage,
 }
 
 impl MaxQ {
     pub fn new(world: &World, alpha: f64, gamma: f64, epsilon: f64, show_learning: bool) -> MaxQ {
-        let initial_q_value = if gamma < 1.0 {
-            world.max_reward() / (1.0 - gamma)
-        } else {
-            world.max_reward()
-        };
+        Self::with_init(
+            world,
+            alpha,
+            gamma,
+            epsilon,
+            show_learning,
+            InitStrategy::Optimistic,
+        )
+    }
 
-        let nodes = NodeStorage::new(initial_q_value, world);
+    // Only the primitive values are initialized by the strategy, the
+    // completions always start at 0.
+    pub fn with_init(
+        world: &World,
+        alpha: f64,
+        gamma: f64,
+        epsilon: f64,
+        show_learning: bool,
+        init: InitStrategy,
+    ) -> MaxQ {
+        let nodes = NodeStorage::new(world, gamma, init);
 
         let params = MaxQParams {
             alpha,
@@ -48,15 +65,94 @@ impl MaxQ {
             show_learning,
         };
 
-        MaxQ { params, nodes }
+        MaxQ {
+            params,
+            init,
+            nodes,
+        }
     }
 
-    fn evaluate(&self, world: &World, state: &State) -> Option<Actions> {
+    // Copies the learned values, for continuing training with new parameters.
+    // A reset then starts from the initial values for the new gamma.
+    pub fn with_hyperparams(&self, world: &World, alpha: f64, gamma: f64, epsilon: f64) -> MaxQ {
+        let mut nodes = self.nodes.clone();
+        nodes.set_initial_values(world, gamma, self.init);
+
+        MaxQ {
+            params: MaxQParams {
+                alpha,
+                gamma,
+                epsilon,
+                ..self.params
+            },
+            init: self.init,
+            nodes,
+        }
+    }
+
+    // Greedy action of the whole hierarchy.  Ties are broken towards the
+    // first child of each node, see MaxNode::evaluate.
+    fn evaluate_max_node_action(&self, world: &World, state: &State) -> Option<Actions> {
         self.nodes.max_nodes[0]
             .evaluate(&self.nodes, world, state)
             .map(|(_, _, action)| action)
     }
 
+    // Follows the greedy choice at each level of the hierarchy, returning
+    // the completion value of every choice followed by the value of the
+    // primitive action.  The entries sum to the value of the root, see
+    // MaxNode::evaluate.
+    pub fn decompose(&self, world: &World, state: &State) -> Vec<(String, f64)> {
+        let mut result = Vec::new();
+        let mut current_max_index = 0;
+
+        while let Some((_, child_index, _)) =
+            self.nodes.max_nodes[current_max_index].evaluate(&self.nodes, world, state)
+        {
+            let max_node = &self.nodes.max_nodes[current_max_index];
+            let qnode = &self.nodes.q_nodes[child_index];
+
+            let (value, completion, action) = match qnode.evaluate(&self.nodes, world, state) {
+                Some(evaluation) => evaluation,
+                None => break,
+            };
+
+            result.push((format!("{} -> {}", max_node, qnode), completion));
+
+            match qnode.get_child(world, state) {
+                Some(QChild::MaxNode(max_index)) => current_max_index = max_index,
+                Some(QChild::Primitive(_)) => {
+                    result.push((format!("{}", action), value));
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        result
+    }
+
+    // The value of the root for every state index of StateIndexer::new,
+    // the sum of the decomposition.  Each node's value includes its
+    // subtasks' completions, so a waiting passenger's state counts the
+    // whole trip.  Delivered states and indices with no state are 0.
+    pub fn value_function(&self, world: &World) -> Vec<f64> {
+        let state_indexer = StateIndexer::new(world);
+
+        (0..state_indexer.num_states())
+            .map(|state_index| {
+                state_indexer
+                    .get_state(world, state_index)
+                    .map_or(0.0, |state| {
+                        self.decompose(world, &state)
+                            .iter()
+                            .map(|(_, value)| value)
+                            .sum()
+                    })
+            })
+            .collect()
+    }
+
     fn maxq_apply_selection<R: Rng>(
         &mut self,
         qchild: QChild,
@@ -64,16 +160,17 @@ impl MaxQ {
         state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<(State, Vec<State>)> {
+    ) -> Result<(State, Vec<State>, f64), f64> {
         match qchild {
             QChild::Primitive(child_primitive_index) => {
                 let primitive_node = &mut self.nodes.primitive_nodes[child_primitive_index];
 
-                let (reward, next_state) = state.apply_action(world, primitive_node.get_action());
+                let (reward, next_state) =
+                    state.apply_action_stochastic(world, primitive_node.get_action(), rng);
 
                 primitive_node.apply_experience(&self.params, world, &state, reward, &next_state);
 
-                Some((next_state, vec![state]))
+                Ok((next_state, vec![state], reward))
             }
 
             QChild::MaxNode(child_max_index) => {
@@ -82,6 +179,10 @@ impl MaxQ {
         }
     }
 
+    // The state reached, the states acted from and the total reward.  When
+    // some node has no child to choose, or the passenger wanders off the
+    // fixed positions, the episode is abandoned and the error holds the
+    // reward collected until then.
     fn maxq_q<R: Rng>(
         &mut self,
         max_index: usize,
@@ -89,14 +190,15 @@ impl MaxQ {
         mut state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<(State, Vec<State>)> {
+    ) -> Result<(State, Vec<State>, f64), f64> {
         let mut seq = Vec::new();
+        let mut total_reward = 0.0;
 
         while !self.nodes.max_nodes[max_index].terminal_state(world, &state)
             && seq.len() < max_steps
         {
             if self.params.show_learning {
-                println!(
+                debug!(
                     "step {}/{} node {} - {}\n{}",
                     seq.len(),
                     max_steps,
@@ -106,18 +208,16 @@ impl MaxQ {
                 );
             }
 
-            let child_q_index = self.nodes.max_nodes[max_index].select_child_to_learn(
-                &self.nodes,
-                &self.params,
-                world,
-                &state,
-                rng,
-            )?;
+            let child_q_index = self.nodes.max_nodes[max_index]
+                .select_child_to_learn(&self.nodes, &self.params, world, &state, rng)
+                .ok_or(total_reward)?;
 
-            let qchild = self.nodes.q_nodes[child_q_index].get_child(world, &state)?;
+            let qchild = self.nodes.q_nodes[child_q_index]
+                .get_child(world, &state)
+                .ok_or(total_reward)?;
 
             if self.params.show_learning {
-                println!(
+                debug!(
                     "Node {} selected child {} - {}",
                     self.nodes.max_nodes[max_index],
                     child_q_index,
@@ -125,8 +225,22 @@ impl MaxQ {
                 );
             }
 
-            let (next_state, mut child_seq) =
-                self.maxq_apply_selection(qchild, world, state, max_steps - seq.len(), rng)?;
+            let (next_state, mut child_seq, child_reward) = self
+                .maxq_apply_selection(qchild, world, state, max_steps - seq.len(), rng)
+                .map_err(|child_reward| total_reward + child_reward)?;
+
+            // The subtasks only know the fixed positions, so a passenger that
+            // wandered off them ends the episode before anything learns from
+            // the state it left behind.
+            if let PassengerState::WaitingAt(_) = next_state.passenger_state() {
+                return Err(total_reward + child_reward);
+            }
+
+            // A child that takes no steps cannot make progress, and selecting
+            // it again would loop forever.
+            if child_seq.is_empty() {
+                break;
+            }
 
             // A terminal state check should be run for all parents here.
             // For taxi, there is no way for a parent to terminate
@@ -144,7 +258,7 @@ impl MaxQ {
                     self.nodes.max_nodes[max_index].learning_reward(world, &next_state);
 
                 if self.params.show_learning {
-                    println!(
+                    debug!(
                         "maxq_q {} - {} for state:\n{}",
                         max_index,
                         self.nodes.max_nodes[max_index],
@@ -172,11 +286,12 @@ impl MaxQ {
             }
 
             seq.append(&mut child_seq);
+            total_reward += child_reward;
             state = next_state;
         }
 
         if self.params.show_learning {
-            println!(
+            debug!(
                 "Step {}/{} terminating node {} - {}",
                 seq.len(),
                 max_steps,
@@ -184,7 +299,7 @@ impl MaxQ {
                 self.nodes.max_nodes[max_index]
             );
         }
-        Some((state, seq))
+        Ok((state, seq, total_reward))
     }
 }
 
@@ -195,15 +310,22 @@ impl Runner for MaxQ {
         state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize> {
+    ) -> Trial {
         if self.params.show_learning {
-            println!("Learning:\n{:#?}\n{}\n", state, state.display(world));
+            debug!("Learning:\n{:#?}\n{}\n", state, state.display(world));
         }
 
-        let (final_state, seq) = self.maxq_q(0, world, state, max_steps, rng)?;
+        if let PassengerState::WaitingAt(_) = state.passenger_state() {
+            return Trial::new(None, 0.0);
+        }
+
+        let (final_state, seq, reward) = match self.maxq_q(0, world, state, max_steps, rng) {
+            Ok(result) => result,
+            Err(reward) => return Trial::new(None, reward),
+        };
 
         if self.params.show_learning {
-            println!(
+            debug!(
                 "Finished {} steps:\n{:#?}\n{}\n{}",
                 seq.len(),
                 final_state,
@@ -216,9 +338,9 @@ impl Runner for MaxQ {
             );
         }
         if final_state.at_destination() {
-            Some(seq.len())
+            Trial::new(Some(seq.len()), reward)
         } else {
-            None
+            Trial::new(None, reward)
         }
     }
 
@@ -236,7 +358,7 @@ impl Runner for MaxQ {
                 break;
             }
 
-            if let Some(next_action) = self.evaluate(world, &state) {
+            if let Some(next_action) = self.evaluate_max_node_action(world, &state) {
                 attempt.step(next_action);
                 let (_, next_state) = state.apply_action(world, next_action);
                 state = next_state;
@@ -264,7 +386,7 @@ impl Runner for MaxQ {
                 return true;
             }
 
-            if let Some(next_action) = self.evaluate(world, &state) {
+            if let Some(next_action) = self.evaluate_max_node_action(world, &state) {
                 let (_, next_state) = state.apply_action(world, next_action);
                 state = next_state;
             } else {
@@ -275,15 +397,36 @@ impl Runner for MaxQ {
         state.at_destination()
     }
 
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        self.evaluate_max_node_action(world, state)
+    }
+
+    // Evaluates the hierarchy as if the masked primitives did not exist,
+    // so a blocked move gives way to the best of the others.
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        self.nodes.max_nodes[0]
+            .evaluate_available(&self.nodes, world, state, Some(available))
+            .map(|(_, _, action)| action)
+    }
+
+    fn reset(&mut self) {
+        self.nodes.reset();
+    }
+
     fn report_training_result(&self, world: &World, _steps: Option<usize>) {
         let state_indexer = StateIndexer::new(world);
 
         for si in 0..state_indexer.num_states() {
             if let Some(state) = state_indexer.get_state(world, si) {
                 if !state.at_destination() {
-                    println!("{}\n{}", si, state.display(world));
-                    if let Some(action) = self.evaluate(world, &state) {
-                        println!("Result {}", action,);
+                    info!("{}\n{}", si, state.display(world));
+                    if let Some(action) = self.evaluate_max_node_action(world, &state) {
+                        info!("Result {}", action,);
 
                         let mut current_max_index = 0;
                         loop {
@@ -291,7 +434,7 @@ impl Runner for MaxQ {
                                 [current_max_index]
                                 .evaluate(&self.nodes, world, &state)
                             {
-                                println!(
+                                info!(
                                     "{} chose {}",
                                     self.nodes.max_nodes[current_max_index],
                                     self.nodes.q_nodes[child_index]
@@ -306,20 +449,20 @@ impl Runner for MaxQ {
                                         }
                                     }
                                 } else {
-                                    println!("Failed to find child for index {}!", child_index);
+                                    info!("Failed to find child for index {}!", child_index);
                                     break;
                                 }
                             } else {
-                                println!("Failed to evaluate");
+                                info!("Failed to evaluate");
                                 break;
                             }
                         }
                     } else {
-                        println!("Failed to evaluate!");
+                        info!("Failed to evaluate!");
                     }
 
                     for (max_node_index, max_node) in self.nodes.max_nodes.iter().enumerate() {
-                        println!("{} {} :", max_node_index, max_node);
+                        info!("{} {} :", max_node_index, max_node);
 
                         for q_index in max_node.qnode_index_iter() {
                             let qnode = &self.nodes.q_nodes[*q_index];
@@ -329,7 +472,7 @@ impl Runner for MaxQ {
                             {
                                 let completion_index = qnode.get_completion_index(world, &state);
 
-                                println!(
+                                info!(
                                     "  {} {} {:?} => {} + {} = {}",
                                     q_index,
                                     qnode,
@@ -339,15 +482,474 @@ impl Runner for MaxQ {
                                     value + completion
                                 );
                             } else {
-                                println!("  {} {} => does not evaluate", q_index, qnode,);
+                                info!("  {} {} => does not evaluate", q_index, qnode,);
                             }
                         }
                     }
 
-                    println!("\n");
+                    info!("\n");
                 }
             }
         }
         // println!("{:#?}", self.max_nodes);
     }
 }
+
+#[cfg(test)]
+mod test_maxq {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    use crate::maxq::maxnode::{MaxNode, MaxNodeType};
+    use crate::state::StateIterator;
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+
+    #[test]
+    fn saved_attempt_replays_identically() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+
+        let state = State::build(&world, (1, 0), Some('R'), 'G').unwrap();
+        for _ in 0..200 {
+            maxq.learn(&world, state, 50, &mut rng);
+        }
+
+        let attempt = maxq.attempt(&world, state, 20, &mut rng);
+
+        let path = std::env::temp_dir().join(format!("taxi_attempt_{}.json", std::process::id()));
+        attempt.save(&world, &path).unwrap();
+        let loaded = Attempt::load(&world, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.initial_state, attempt.initial_state);
+        assert_eq!(loaded.actions, attempt.actions);
+        assert_eq!(loaded.success, attempt.success);
+
+        let rendered = |attempt: &Attempt| -> Vec<String> {
+            attempt
+                .states(&world)
+                .iter()
+                .map(|state| state.display(&world))
+                .collect()
+        };
+        assert_eq!(rendered(&loaded), rendered(&attempt));
+
+        // The taxi starts off this narrower map.
+        let small_world = World::build_from_str(
+            "\
+             ┌─┐\n\
+             │R│\n\
+             │ │\n\
+             │G│\n\
+             └─┘\n\
+             ",
+            Costs::default(),
+        )
+        .unwrap();
+        let json = attempt.to_json(&world).unwrap();
+        assert_matches!(
+            Attempt::from_json(&small_world, &json),
+            Err(crate::runner::Error::AttemptState(_))
+        );
+    }
+
+    #[test]
+    fn navigate_at_target_terminates() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut maxq = MaxQ::new(&world, 0.1, 0.9, 1.0, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+        let navigate_index = MaxNode::get_index(MaxNodeType::Navigate('R'), &world);
+
+        let (final_state, seq, reward) = maxq
+            .maxq_q(navigate_index, &world, state, 10, &mut rng)
+            .unwrap();
+
+        assert_eq!(final_state, state);
+        assert!(seq.is_empty());
+        assert_eq!(reward, 0.0);
+    }
+
+    #[test]
+    fn get_at_passenger_picks_up() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+        let get_index = MaxNode::get_index(MaxNodeType::Get, &world);
+
+        for seed in 0..20 {
+            let mut maxq = MaxQ::new(&world, 0.1, 0.9, 1.0, false);
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+
+            let (final_state, seq, reward) =
+                maxq.maxq_q(get_index, &world, state, 10, &mut rng).unwrap();
+
+            assert!(final_state.passenger_in_taxi());
+            assert_eq!(seq, vec![state]);
+            assert_eq!(reward, 0.0);
+        }
+    }
+
+    #[test]
+    fn decompose_follows_hierarchy() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+
+        let waiting = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+        let decomposition = maxq.decompose(&world, &waiting);
+        assert_eq!(decomposition[0].0, "Root -> Get");
+        assert!(decomposition[1].0.starts_with("Get -> "));
+
+        let (value, _, _) = maxq.nodes.max_nodes[0]
+            .evaluate(&maxq.nodes, &world, &waiting)
+            .unwrap();
+        let total: f64 = decomposition.iter().map(|(_, v)| v).sum();
+        assert!(approx_eq!(f64, total, value, ulps = 2));
+
+        let carrying = State::build(&world, (0, 0), None, 'G').unwrap();
+        let decomposition = maxq.decompose(&world, &carrying);
+        assert_eq!(decomposition[0].0, "Root -> Put");
+        assert!(decomposition[1].0.starts_with("Put -> "));
+
+        let at_destination = State::build(&world, (1, 1), None, 'G').unwrap();
+        let decomposition = maxq.decompose(&world, &at_destination);
+        assert_eq!(decomposition[0].0, "Root -> Put");
+        assert_eq!(decomposition[1], ("Put -> DropOff".to_string(), 0.0));
+        assert_eq!(decomposition.len(), 3);
+    }
+
+    #[test]
+    fn terminal_states_match_passenger_ids() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+
+        let get = &maxq.nodes.max_nodes[MaxNode::get_index(MaxNodeType::Get, &world)];
+        let put = &maxq.nodes.max_nodes[MaxNode::get_index(MaxNodeType::Put, &world)];
+
+        for state in StateIterator::new(&world) {
+            assert_eq!(
+                get.terminal_state(&world, &state),
+                state.passenger_in_taxi()
+            );
+            assert_eq!(
+                put.terminal_state(&world, &state),
+                !state.passenger_in_taxi()
+            );
+        }
+    }
+
+    #[test]
+    fn initial_values_scale_with_delivery_reward() {
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+        let maxq = MaxQ::new(&world, 0.1, 0.5, 0.1, false);
+
+        let state = State::build(&world, (1, 1), None, 'G').unwrap();
+        let (value, action) =
+            maxq.nodes.primitive_nodes[Actions::DropOff.to_index()].evaluate(&world, &state);
+
+        assert_eq!(action, Actions::DropOff);
+        assert!(approx_eq!(f64, value, 40.0, ulps = 2));
+    }
+
+    #[test]
+    fn init_strategy_sets_primitive_values() {
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+        let state = State::build(&world, (1, 1), None, 'G').unwrap();
+
+        let primitive_values = |maxq: &MaxQ| -> Vec<f64> {
+            maxq.nodes
+                .primitive_nodes
+                .iter()
+                .map(|node| node.evaluate(&world, &state).0)
+                .collect()
+        };
+
+        let zero = MaxQ::with_init(&world, 0.1, 0.5, 0.1, false, InitStrategy::Zero);
+        assert!(primitive_values(&zero).iter().all(|value| *value == 0.0));
+
+        let random = InitStrategy::Random {
+            low: 0.0,
+            high: 1.0,
+            seed: 3,
+        };
+        let first = MaxQ::with_init(&world, 0.1, 0.5, 0.1, false, random);
+        let second = MaxQ::with_init(&world, 0.1, 0.5, 0.1, false, random);
+        assert_eq!(primitive_values(&first), primitive_values(&second));
+        assert!(primitive_values(&first)
+            .iter()
+            .all(|value| *value >= 0.0 && *value < 1.0));
+    }
+
+    #[test]
+    fn with_hyperparams_resets_for_new_gamma() {
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+        let state = State::build(&world, (1, 1), None, 'G').unwrap();
+
+        let primitive_values = |maxq: &MaxQ| -> Vec<f64> {
+            maxq.nodes
+                .primitive_nodes
+                .iter()
+                .map(|node| node.evaluate(&world, &state).0)
+                .collect()
+        };
+
+        let maxq = MaxQ::new(&world, 0.1, 0.5, 0.1, false);
+        assert!(primitive_values(&maxq).iter().all(|value| *value == 40.0));
+
+        let mut tuned = maxq.with_hyperparams(&world, 0.1, 0.9, 0.1);
+        assert_eq!(primitive_values(&tuned), primitive_values(&maxq));
+
+        tuned.reset();
+        assert_eq!(
+            primitive_values(&tuned),
+            primitive_values(&MaxQ::new(&world, 0.1, 0.9, 0.1, false))
+        );
+        assert!(primitive_values(&tuned).iter().all(|value| approx_eq!(
+            f64,
+            *value,
+            200.0,
+            ulps = 2
+        )));
+    }
+
+    #[test]
+    fn greedy_learning_covers_tied_children() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let maxq = MaxQ::new(&world, 0.1, 0.9, 0.0, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(5);
+
+        let state = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+        let navigate_index = MaxNode::get_index(MaxNodeType::Navigate('R'), &world);
+        let navigate = &maxq.nodes.max_nodes[navigate_index];
+
+        let mut selected = Vec::new();
+        for _ in 0..100 {
+            let child = navigate
+                .select_child_to_learn(&maxq.nodes, &maxq.params, &world, &state, &mut rng)
+                .unwrap();
+            if !selected.contains(&child) {
+                selected.push(child);
+            }
+        }
+        assert_eq!(selected.len(), navigate.qnode_index_iter().count());
+
+        // Without learning, the greedy attempt always makes the same choice.
+        let (_, first_child, _) = navigate.evaluate(&maxq.nodes, &world, &state).unwrap();
+        for _ in 0..10 {
+            let (_, child, _) = navigate.evaluate(&maxq.nodes, &world, &state).unwrap();
+            assert_eq!(child, first_child);
+        }
+    }
+
+    #[test]
+    fn greedy_action_matches_attempt() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        for _ in 0..20 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            maxq.learn(&world, state, 50, &mut rng);
+        }
+
+        let state_indexer = StateIndexer::new(&world);
+        for state_index in 0..state_indexer.num_states() {
+            let state = state_indexer.get_state(&world, state_index).unwrap();
+            if state.at_destination() {
+                continue;
+            }
+
+            let attempt = maxq.attempt(&world, state, 1, &mut rng);
+            assert_eq!(
+                maxq.greedy_action(&world, &state),
+                attempt.actions.first().cloned()
+            );
+        }
+    }
+
+    #[test]
+    fn learns_blocked_movement_cost() {
+        let costs = Costs {
+            blocked_movement: -5.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+        let mut maxq = MaxQ::new(&world, 1.0, 0.9, 0.3, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(5);
+
+        for _ in 0..50 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            maxq.learn(&world, state, 50, &mut rng);
+        }
+
+        let top_left = State::build(&world, (0, 0), Some('G'), 'R').unwrap();
+        let bottom_right = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+
+        for (action, blocked, open) in &[
+            (Actions::North, top_left, bottom_right),
+            (Actions::South, bottom_right, top_left),
+            (Actions::East, bottom_right, top_left),
+            (Actions::West, top_left, bottom_right),
+        ] {
+            let node = &maxq.nodes.primitive_nodes[action.to_index()];
+            assert_eq!(node.evaluate(&world, blocked), (-5.0, *action));
+            assert_eq!(node.evaluate(&world, open), (-1.0, *action));
+        }
+    }
+
+    #[test]
+    fn learns_each_delivery_reward() {
+        let mut world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        world.set_delivery_reward('R', Some(10.0)).unwrap();
+        world.set_delivery_reward('G', Some(30.0)).unwrap();
+
+        let mut maxq = MaxQ::new(&world, 1.0, 0.9, 0.3, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(5);
+
+        for _ in 0..50 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            maxq.learn(&world, state, 50, &mut rng);
+        }
+
+        let at_r = State::build(&world, (0, 0), None, 'R').unwrap();
+        let at_g = State::build(&world, (1, 1), None, 'G').unwrap();
+        let missed = State::build(&world, (1, 0), None, 'G').unwrap();
+
+        let node = &maxq.nodes.primitive_nodes[Actions::DropOff.to_index()];
+        assert_eq!(node.evaluate(&world, &at_r), (10.0, Actions::DropOff));
+        assert_eq!(node.evaluate(&world, &at_g), (30.0, Actions::DropOff));
+        assert_eq!(
+            node.evaluate(&world, &missed),
+            (world.costs.miss_dropoff, Actions::DropOff)
+        );
+    }
+
+    #[test]
+    fn masked_greedy_action_skips_blocked_moves() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        for _ in 0..20 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            maxq.learn(&world, state, 50, &mut rng);
+        }
+
+        let moves = [Actions::North, Actions::South, Actions::East, Actions::West];
+
+        let mut num_greedy_masked = 0;
+        for state in StateIndexer::new(&world).iter_states(&world) {
+            if state.at_destination() {
+                continue;
+            }
+
+            let available = world.available_actions(state.get_taxi());
+            let greedy = maxq.greedy_action(&world, &state).unwrap();
+            let masked = maxq
+                .masked_greedy_action(&world, &state, &available)
+                .unwrap();
+            assert!(available.contains(&masked));
+            if available.contains(&greedy) {
+                assert_eq!(masked, greedy);
+            }
+
+            // Without the greedy move, another action is taken instead.
+            if moves.contains(&greedy) {
+                num_greedy_masked += 1;
+
+                let others: Vec<Actions> = available
+                    .iter()
+                    .cloned()
+                    .filter(|action| *action != greedy)
+                    .collect();
+                let fallback = maxq.masked_greedy_action(&world, &state, &others).unwrap();
+                assert!(others.contains(&fallback));
+            }
+        }
+
+        assert!(num_greedy_masked > 0);
+    }
+
+    #[test]
+    fn wandered_passenger_ends_episode() {
+        let mut world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        world.passenger_wander = 1.0;
+
+        let mut maxq = MaxQ::new(&world, 0.1, 0.9, 0.1, false);
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        // R has two free neighbours, so the passenger leaves it on the first
+        // step and the episode ends with that step's reward.
+        let state = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+        let trial = maxq.learn(&world, state, 50, &mut rng);
+        assert_eq!(trial, Trial::new(None, world.costs.movement));
+
+        let wandered =
+            State::build_with_passenger_position(&world, (1, 1), Some((1, 0)), 'G').unwrap();
+        assert_eq!(
+            maxq.learn(&world, wandered, 50, &mut rng),
+            Trial::new(None, 0.0)
+        );
+    }
+
+    #[test]
+    fn value_function_sums_decomposition() {
+        let mut world = World::standard("small3x3").unwrap();
+        world.costs.delivery_reward = 20.0;
+
+        let mut maxq = MaxQ::with_init(&world, 0.5, 1.0, 0.3, false, InitStrategy::Zero);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        for _ in 0..5000 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            maxq.learn(&world, state, 100, &mut rng);
+        }
+
+        let values = maxq.value_function(&world);
+        let optimal_values = world.optimal_values(1.0, 1e-9, 10_000).unwrap();
+        let state_indexer = StateIndexer::new(&world);
+
+        for state in StateIterator::new(&world) {
+            let state_index = state_indexer.get_index(&world, &state).unwrap();
+            let decomposed: f64 = maxq
+                .decompose(&world, &state)
+                .iter()
+                .map(|(_, value)| value)
+                .sum();
+            assert!(approx_eq!(f64, values[state_index], decomposed, ulps = 2));
+
+            if !state.at_destination() {
+                assert!(
+                    (values[state_index] - optimal_values[state_index]).abs() < 0.01,
+                    "{:?}: {} != {}",
+                    state,
+                    values[state_index],
+                    optimal_values[state_index]
+                );
+            }
+        }
+    }
+}
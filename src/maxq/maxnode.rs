@@ -1,3 +1,4 @@
+use std::f64;
 use std::fmt;
 use std::slice::Iter;
 
@@ -5,7 +6,8 @@ use rand::seq::SliceRandom;
 use rand::Rng;
 
 use crate::actions::Actions;
-use crate::state::State;
+use crate::state::{PassengerState, State};
+use crate::util::argmax_action;
 use crate::world::World;
 
 use crate::maxq::nodestorage::NodeStorage;
@@ -27,33 +29,53 @@ pub struct MaxNode {
 }
 
 impl MaxNode {
+    // The node's value, the child chosen and the primitive action it leads
+    // to.  The value is the best child's value plus its completion, so it
+    // covers the rest of this subtask.  Ties go to the first child in this
+    // node's order, so repeated evaluations of the same state always agree.
     pub fn evaluate(
         &self,
         nodes: &NodeStorage,
         world: &World,
         state: &State,
     ) -> Option<(f64, usize, Actions)> {
-        let mut result = None;
-
-        if !self.terminal_state(world, state) {
-            let mut highest_q = None;
-
-            for qnode_index in &self.qnodes {
-                if let Some((value, completion, action)) =
-                    nodes.q_nodes[*qnode_index].evaluate(nodes, world, state)
-                {
-                    let q = Some(value + completion);
-
-                    if q > highest_q {
-                        highest_q = q;
+        self.evaluate_available(nodes, world, state, None)
+    }
 
-                        result = Some((value, *qnode_index, action));
-                    }
-                }
-            }
+    // Like evaluate, but when available is given only children that lead
+    // to one of those primitive actions are chosen.
+    pub fn evaluate_available(
+        &self,
+        nodes: &NodeStorage,
+        world: &World,
+        state: &State,
+        available: Option<&[Actions]>,
+    ) -> Option<(f64, usize, Actions)> {
+        if self.terminal_state(world, state) {
+            return None;
         }
 
-        result
+        let evaluations: Vec<_> = self
+            .qnodes
+            .iter()
+            .map(|qnode_index| {
+                nodes.q_nodes[*qnode_index].evaluate_available(nodes, world, state, available)
+            })
+            .collect();
+
+        // Children that cannot act are NaN so the argmax skips them.
+        let q_values: Vec<f64> = evaluations
+            .iter()
+            .map(|evaluation| match evaluation {
+                Some((value, completion, _)) => value + completion,
+                None => f64::NAN,
+            })
+            .collect();
+
+        let (child, q_value) = argmax_action(&q_values)?;
+        let (_, _, action) = evaluations[child]?;
+
+        Some((q_value, self.qnodes[child], action))
     }
 
     pub fn result_state_values(
@@ -62,27 +84,28 @@ impl MaxNode {
         world: &World,
         state: &State,
     ) -> Option<(f64, f64)> {
-        let mut result = None;
+        if self.terminal_state(world, state) {
+            return None;
+        }
 
-        if !self.terminal_state(world, state) {
-            let mut highest_q = None;
+        let evaluations: Vec<_> = self
+            .qnodes
+            .iter()
+            .map(|qnode_index| nodes.q_nodes[*qnode_index].evaluate_learning(nodes, world, state))
+            .collect();
 
-            for qnode_index in &self.qnodes {
-                if let Some((value, learning_completion, completion)) =
-                    nodes.q_nodes[*qnode_index].evaluate_learning(nodes, world, state)
-                {
-                    let q = Some(value + learning_completion);
+        let q_values: Vec<f64> = evaluations
+            .iter()
+            .map(|evaluation| match evaluation {
+                Some((value, learning_completion, _)) => value + learning_completion,
+                None => f64::NAN,
+            })
+            .collect();
 
-                    if q > highest_q {
-                        highest_q = q;
+        let (child, _) = argmax_action(&q_values)?;
+        let (value, learning_completion, completion) = evaluations[child]?;
 
-                        result = Some((value + learning_completion, value + completion));
-                    }
-                }
-            }
-        }
-
-        result
+        Some((value + learning_completion, value + completion))
     }
 
     pub fn learning_reward(&self, _world: &World, state: &State) -> f64 {
@@ -109,18 +132,71 @@ impl MaxNode {
         let nongreedy_roll = rng.gen_range(0.0f64, 1.0f64);
 
         if nongreedy_roll < params.epsilon {
-            self.qnodes.choose(rng).cloned()
+            // Only explore children that can act in this state, a child
+            // that is already terminal would not take any steps.
+            let candidates: Vec<usize> = self
+                .qnodes
+                .iter()
+                .filter(|qnode_index| {
+                    nodes.q_nodes[**qnode_index]
+                        .evaluate(nodes, world, state)
+                        .is_some()
+                })
+                .cloned()
+                .collect();
+
+            candidates.choose(rng).cloned()
         } else {
-            self.evaluate(nodes, world, state)
-                .map(|(_, child_index, _)| child_index)
+            self.select_greedy_child(nodes, world, state, rng)
         }
     }
 
+    // Like evaluate, but ties are broken uniformly at random.
+    fn select_greedy_child<R: Rng>(
+        &self,
+        nodes: &NodeStorage,
+        world: &World,
+        state: &State,
+        rng: &mut R,
+    ) -> Option<usize> {
+        let mut result = None;
+
+        if !self.terminal_state(world, state) {
+            let mut highest_q = 0.0;
+            let mut num_found = 0;
+
+            for qnode_index in &self.qnodes {
+                if let Some((value, completion, _)) =
+                    nodes.q_nodes[*qnode_index].evaluate(nodes, world, state)
+                {
+                    let q = value + completion;
+
+                    if result.is_none() {
+                        result = Some(*qnode_index);
+                        highest_q = q;
+                        num_found = 1;
+                    } else if approx_eq!(f64, q, highest_q, ulps = 2) {
+                        num_found += 1;
+                        if rng.gen_range(0, num_found) == 0 {
+                            result = Some(*qnode_index);
+                        }
+                    } else if q > highest_q {
+                        result = Some(*qnode_index);
+                        highest_q = q;
+                        num_found = 1;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
     pub fn terminal_state(&self, world: &World, state: &State) -> bool {
         match self.node_type {
             MaxNodeType::Root => state.at_destination(),
-            MaxNodeType::Get => state.get_passenger() == None,
-            MaxNodeType::Put => state.get_passenger() != None,
+            MaxNodeType::Get => state.passenger_state() == PassengerState::InTaxi,
+            MaxNodeType::Put => state.passenger_state() != PassengerState::InTaxi,
             MaxNodeType::Navigate(id) => Some(state.get_taxi()) == world.get_fixed_position(id),
         }
     }
@@ -1,3 +1,4 @@
+use crate::qlearner::InitStrategy;
 use crate::world::World;
 
 use crate::maxq::maxnode::MaxNode;
@@ -12,10 +13,10 @@ pub struct NodeStorage {
 }
 
 impl NodeStorage {
-    pub fn new(initial_q_value: f64, world: &World) -> NodeStorage {
+    pub fn new(world: &World, gamma: f64, init: InitStrategy) -> NodeStorage {
         let max_nodes = MaxNode::build_nodes(world);
         let q_nodes = QNode::build_nodes(world);
-        let primitive_nodes = PrimitiveNode::build_nodes(initial_q_value);
+        let primitive_nodes = PrimitiveNode::build_nodes(world, gamma, init);
 
         NodeStorage {
             max_nodes,
@@ -23,4 +24,20 @@ impl NodeStorage {
             primitive_nodes,
         }
     }
+
+    // Replaces the values the primitives reset to, keeping the learned ones.
+    pub fn set_initial_values(&mut self, world: &World, gamma: f64, init: InitStrategy) {
+        let fresh_nodes = PrimitiveNode::build_nodes(world, gamma, init);
+
+        for (node, fresh_node) in self.primitive_nodes.iter_mut().zip(fresh_nodes) {
+            node.set_initial_values(fresh_node);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.q_nodes.iter_mut().for_each(QNode::reset);
+        self.primitive_nodes
+            .iter_mut()
+            .for_each(PrimitiveNode::reset);
+    }
 }
@@ -1,6 +1,7 @@
 use crate::actions::Actions;
-use crate::state::State;
-use crate::world::World;
+use crate::qlearner::InitStrategy;
+use crate::state::{PassengerState, State};
+use crate::world::{ActionAffect, World};
 
 use crate::maxq::MaxQParams;
 
@@ -8,37 +9,67 @@ use crate::maxq::MaxQParams;
 pub struct PrimitiveNode {
     action: Actions,
     values: Vec<f64>,
+    // Kept to restore the values on reset.
+    initial_values: Vec<f64>,
 }
 
 impl PrimitiveNode {
-    pub fn new(action: Actions, initial_q_value: f64) -> PrimitiveNode {
-        let num_values = match action {
-            Actions::PickUp | Actions::DropOff => 2,
-            Actions::North | Actions::South | Actions::East | Actions::West => 1,
-        };
-
+    pub fn new(action: Actions, values: Vec<f64>) -> PrimitiveNode {
         PrimitiveNode {
             action,
-            values: vec![initial_q_value; num_values],
+            initial_values: values.clone(),
+            values,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.values.copy_from_slice(&self.initial_values);
+    }
+
+    // Takes the values to reset to from a freshly built node.
+    pub fn set_initial_values(&mut self, fresh_node: PrimitiveNode) {
+        self.initial_values = fresh_node.initial_values;
+    }
+
+    fn num_values(world: &World, action: Actions) -> usize {
+        match action {
+            Actions::PickUp => 2,
+            Actions::DropOff => world.num_fixed_positions() + 1,
+            Actions::North | Actions::South | Actions::East | Actions::West => 2,
+            Actions::Wait => 1,
         }
     }
 
     fn get_value_index(&self, world: &World, state: &State) -> usize {
         match self.action {
             // Pick-up has only 2 results, taxi is at passenger or not.
-            Actions::PickUp => match state.get_passenger() {
-                Some(id) if world.get_fixed_position(id) == Some(state.get_taxi()) => 0,
+            Actions::PickUp => match state.passenger_state() {
+                PassengerState::Waiting(id)
+                    if world.get_fixed_position(id) == Some(state.get_taxi()) =>
+                {
+                    0
+                }
                 _ => 1,
             },
 
-            // Drop-off has only 2 results, passenger is in taxi and at destination or not.
+            // Drop-off succeeds at each destination, which may pay its own
+            // reward, or fails in the last slot.
             Actions::DropOff => match world.get_fixed_id(state.get_taxi()) {
-                Some(id) if state.get_passenger() == None && id == state.get_destination() => 0,
-                _ => 1,
+                Some(id) if state.passenger_in_taxi() && id == state.get_destination() => {
+                    world.get_fixed_index(id).unwrap()
+                }
+                _ => world.num_fixed_positions(),
             },
 
-            // reward for directional movement is independent of taxi position
-            Actions::North | Actions::South | Actions::East | Actions::West => 0,
+            // Directional movement has 2 results, the taxi moves or runs into a wall.
+            Actions::North | Actions::South | Actions::East | Actions::West => {
+                match world.determine_affect(state.get_taxi(), self.action) {
+                    ActionAffect::Invalid => 1,
+                    _ => 0,
+                }
+            }
+
+            Actions::Wait => 0,
         }
     }
 
@@ -65,12 +96,25 @@ impl PrimitiveNode {
         self.action
     }
 
-    pub fn build_nodes(initial_q_value: f64) -> Vec<PrimitiveNode> {
+    pub fn build_nodes(world: &World, gamma: f64, init: InitStrategy) -> Vec<PrimitiveNode> {
+        let actions: Vec<Actions> = (0..Actions::NUM_ELEMENTS)
+            .map(|action_index| Actions::from_index(action_index).unwrap())
+            .collect();
+
+        let num_values = actions
+            .iter()
+            .map(|action| Self::num_values(world, *action))
+            .sum();
+        let mut initial_values = init.initial_values(world, gamma, num_values).into_iter();
+
         let mut result = Vec::with_capacity(Actions::NUM_ELEMENTS);
 
-        for action_index in 0..Actions::NUM_ELEMENTS {
-            let action = Actions::from_index(action_index).unwrap();
-            result.push(Self::new(action, initial_q_value));
+        for action in actions {
+            let values = initial_values
+                .by_ref()
+                .take(Self::num_values(world, action))
+                .collect();
+            result.push(Self::new(action, values));
         }
 
         result
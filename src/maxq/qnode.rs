@@ -1,7 +1,7 @@
 use std::fmt;
 
 use crate::actions::Actions;
-use crate::state::State;
+use crate::state::{PassengerState, State};
 use crate::world::World;
 
 use crate::maxq::maxnode::{MaxNode, MaxNodeType};
@@ -58,12 +58,31 @@ impl QNode {
         }
     }
 
-    // returns (value, completion, leaf-action)
+    pub fn reset(&mut self) {
+        self.completions.iter_mut().for_each(|value| *value = 0.0);
+        self.learning_completions
+            .iter_mut()
+            .for_each(|value| *value = 0.0);
+    }
+
+    // returns (child value, completion, leaf-action)
     pub fn evaluate(
         &self,
         nodes: &NodeStorage,
         world: &World,
         state: &State,
+    ) -> Option<(f64, f64, Actions)> {
+        self.evaluate_available(nodes, world, state, None)
+    }
+
+    // Like evaluate, but None when available is given and the child's
+    // primitive action is not among them, see MaxNode::evaluate_available.
+    pub fn evaluate_available(
+        &self,
+        nodes: &NodeStorage,
+        world: &World,
+        state: &State,
+        available: Option<&[Actions]>,
     ) -> Option<(f64, f64, Actions)> {
         let completion = if let Some(completion_index) = self.get_completion_index(world, state) {
             self.completions[completion_index]
@@ -78,14 +97,17 @@ impl QNode {
                 let primitive_node = &nodes.primitive_nodes[index];
                 let (value, action) = primitive_node.evaluate(world, state);
 
-                Some((value, completion, action))
+                match available {
+                    Some(available) if !available.contains(&action) => None,
+                    _ => Some((value, completion, action)),
+                }
             }
 
             QChild::MaxNode(index) => {
                 let max_node = &nodes.max_nodes[index];
 
                 max_node
-                    .evaluate(nodes, world, state)
+                    .evaluate_available(nodes, world, state, available)
                     .map(|(value, _, action)| (value, completion, action))
             }
         }
@@ -137,7 +159,7 @@ impl QNode {
         state: &State,
     ) {
         if params.show_learning {
-            println!("Updating completion for {}", self);
+            debug!("Updating completion for {}", self);
         }
 
         if let Some(completion_index) = self.get_completion_index(world, state) {
@@ -151,7 +173,7 @@ impl QNode {
             self.completions[completion_index] += params.alpha * gamma * result_completion;
 
             if params.show_learning {
-                println!(
+                debug!(
                     "{} completion {} - was {} applied {} -> {}",
                     self,
                     completion_index,
@@ -190,7 +212,11 @@ impl QNode {
         match self.node_type {
             QNodeType::Get => Some(QChild::MaxNode(MaxNode::get_index(MaxNodeType::Get, world))),
             QNodeType::NavigateForGet => {
-                let id = state.get_passenger()?;
+                let id = match state.passenger_state() {
+                    PassengerState::Waiting(id) => id,
+                    PassengerState::Delivered => state.get_destination(),
+                    PassengerState::InTaxi | PassengerState::WaitingAt(_) => return None,
+                };
                 Some(QChild::MaxNode(MaxNode::get_index(
                     MaxNodeType::Navigate(id),
                     world,
@@ -326,9 +352,11 @@ impl fmt::Display for QNode {
 }
 
 fn passenger_state_index(world: &World, state: &State) -> Option<usize> {
-    state
-        .get_passenger()
-        .and_then(|passenger_id| world.get_fixed_index(passenger_id))
+    match state.passenger_state() {
+        PassengerState::Waiting(passenger_id) => world.get_fixed_index(passenger_id),
+        PassengerState::Delivered => world.get_fixed_index(state.get_destination()),
+        PassengerState::InTaxi | PassengerState::WaitingAt(_) => None,
+    }
 }
 
 // fn add_passenger_state_index(mut index: usize, world: &World, state: &State) -> Option<usize> {
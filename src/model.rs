@@ -0,0 +1,170 @@
+use crate::actions::Actions;
+use crate::state::State;
+use crate::state_indexer::StateIndexer;
+use crate::world::World;
+
+// A solver that learns the world's transitions and rewards, so its model
+// can be checked apart from how well it plans with it.
+pub trait LearnedModel {
+    // The next state and reward the learner expects, None while it does
+    // not know one or the other.
+    fn predict(&self, world: &World, state: &State, action: Actions) -> Option<(State, f64)>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ModelAccuracy {
+    pub num_correct: usize,
+    pub num_wrong: usize,
+    pub num_unknown: usize,
+}
+
+impl ModelAccuracy {
+    pub fn num_checked(&self) -> usize {
+        self.num_correct + self.num_wrong + self.num_unknown
+    }
+
+    // Unknown predictions count as incorrect.
+    pub fn accuracy(&self) -> f64 {
+        let num_checked = self.num_checked();
+
+        if num_checked > 0 {
+            self.num_correct as f64 / num_checked as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+// Compares the learner's predictions with State::apply_action for every
+// action in every state the taxi can reach that is not yet delivered.
+pub fn validate_model<M: LearnedModel>(learner: &M, world: &World) -> ModelAccuracy {
+    let mut result = ModelAccuracy::default();
+
+    for state in StateIndexer::new(world).iter_states(world) {
+        if state.at_destination() || !is_reachable(world, &state) {
+            continue;
+        }
+
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            let (reward, next_state) = state.apply_action(world, action);
+
+            match learner.predict(world, &state, action) {
+                None => result.num_unknown += 1,
+                Some((predicted_state, predicted_reward))
+                    if predicted_state == next_state
+                        && approx_eq!(f64, predicted_reward, reward, ulps = 2) =>
+                {
+                    result.num_correct += 1
+                }
+                Some(_) => result.num_wrong += 1,
+            }
+        }
+    }
+
+    result
+}
+
+// Walls can shut cells off from the fixed positions, a taxi never gets
+// there.
+fn is_reachable(world: &World, state: &State) -> bool {
+    world
+        .get_fixed_position(state.get_destination())
+        .and_then(|destination| world.distance(state.get_taxi(), destination))
+        .is_some()
+}
+
+#[cfg(test)]
+mod test_model {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    use crate::doormax::DoorMax;
+    use crate::factoredrmax::FactoredRMax;
+    use crate::position::Position;
+    use crate::runner::Runner;
+    use crate::world::{Costs, Side};
+
+    const WORLD: &str = "\
+                         ┌───┬─┐\n\
+                         │R .│G│\n\
+                         │   │ │\n\
+                         │Y . .│\n\
+                         └─────┘\n\
+                         ";
+
+    fn train<Rnr: Runner>(world: &World, runner: &mut Rnr, episodes: usize) {
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        for _ in 0..episodes {
+            let state = State::build_random(world, &mut rng).unwrap();
+            runner.learn(world, state, 100, &mut rng);
+        }
+    }
+
+    #[test]
+    fn doormax_model_becomes_exact() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut doormax = DoorMax::new(&world, 0.9, false, 1.0, 1.0e-6);
+
+        // Six cells, three passenger states and three destinations.
+        let fresh = validate_model(&doormax, &world);
+        assert_eq!(fresh.num_checked(), 6 * 3 * 3 * Actions::NUM_ELEMENTS);
+        assert_eq!(fresh.num_unknown, fresh.num_checked());
+        assert_eq!(fresh.accuracy(), 0.0);
+
+        train(&world, &mut doormax, 50);
+
+        let trained = validate_model(&doormax, &world);
+        assert_eq!(trained.num_wrong, 0);
+        assert_eq!(trained.num_unknown, 0);
+        assert_eq!(trained.accuracy(), 1.0);
+    }
+
+    #[test]
+    fn factored_model_is_never_wrong() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut factored = FactoredRMax::new(&world, 0.9, 1.0, 1.0e-6);
+
+        train(&world, &mut factored, 25);
+
+        let trained = validate_model(&factored, &world);
+        assert_eq!(trained.num_wrong, 0);
+        assert!(trained.accuracy() > 0.5);
+        assert_eq!(
+            trained.accuracy(),
+            trained.num_correct as f64 / trained.num_checked() as f64
+        );
+    }
+
+    // Predicts with another world's walls.
+    struct OtherWorld(World);
+
+    impl LearnedModel for OtherWorld {
+        fn predict(&self, _world: &World, state: &State, action: Actions) -> Option<(State, f64)> {
+            let (reward, next_state) = state.apply_action(&self.0, action);
+            Some((next_state, reward))
+        }
+    }
+
+    #[test]
+    fn wrong_predictions_are_counted() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        let exact = validate_model(&OtherWorld(world.clone()), &world);
+        assert_eq!(exact.accuracy(), 1.0);
+
+        // Only moves through the removed wall differ: east from (1,0) and
+        // west from (2,0), in each of the nine passenger and destination
+        // combinations.
+        let mut open = world.clone();
+        open.toggle_wall(Position::new(1, 0), Side::East).unwrap();
+
+        let result = validate_model(&OtherWorld(open), &world);
+        assert_eq!(result.num_wrong, 2 * 9);
+        assert_eq!(result.num_unknown, 0);
+        assert_eq!(result.num_correct, result.num_checked() - 2 * 9);
+    }
+}
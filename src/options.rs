@@ -0,0 +1,584 @@
+use std::fmt;
+
+use rand::Rng;
+
+use crate::actions::Actions;
+use crate::qlearner::InitStrategy;
+use crate::runner::{Attempt, Runner, Trial};
+use crate::state::State;
+use crate::state_indexer::StateIndexer;
+use crate::util::argmax_action;
+use crate::world::{ActionAffect, World};
+
+// A temporally extended action.  It may be started in the states of its
+// initiation set, then follows its internal policy until its termination
+// condition holds.  Not called Option, which would hide the prelude's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskOption {
+    // A single action, which always terminates after one step.
+    Primitive(Actions),
+    // Drives the taxi along a shortest path to the fixed position, as
+    // MaxQ's Navigate subtask learns to.
+    Navigate(char),
+    // Teleports the taxi to the fixed position in a single step with
+    // State::apply_goto, so only startable in worlds with goto_actions.
+    // Attempts record the moves of the shortest path instead.
+    GoTo(char),
+}
+
+impl TaskOption {
+    // Every primitive action, followed by a Navigate for each fixed
+    // position.
+    pub fn standard_options(world: &World) -> Vec<TaskOption> {
+        let primitives = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .map(TaskOption::Primitive);
+        let navigates = (0..world.num_fixed_positions())
+            .filter_map(|index| world.get_fixed_id_from_index(index))
+            .map(TaskOption::Navigate);
+
+        primitives.chain(navigates).collect()
+    }
+
+    // MaxQ's Get and Put without their own learning: a Navigate for each
+    // fixed position, and the PickUp and DropOff actions.  Passengers
+    // waiting away from the fixed positions cannot be reached.
+    pub fn navigate_options(world: &World) -> Vec<TaskOption> {
+        let navigates = (0..world.num_fixed_positions())
+            .filter_map(|index| world.get_fixed_id_from_index(index))
+            .map(TaskOption::Navigate);
+
+        navigates
+            .chain(vec![
+                TaskOption::Primitive(Actions::PickUp),
+                TaskOption::Primitive(Actions::DropOff),
+            ])
+            .collect()
+    }
+
+    // Every primitive action, followed by a GoTo for each fixed position:
+    // a flat learner with macro-actions, to set against MaxQ's hierarchy.
+    pub fn goto_options(world: &World) -> Vec<TaskOption> {
+        let primitives = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .map(TaskOption::Primitive);
+        let gotos = (0..world.num_fixed_positions())
+            .filter_map(|index| world.get_fixed_id_from_index(index))
+            .map(TaskOption::GoTo);
+
+        primitives.chain(gotos).collect()
+    }
+
+    // The initiation set.
+    pub fn can_start(self, world: &World, state: &State) -> bool {
+        match self {
+            TaskOption::Primitive(_) => true,
+            TaskOption::GoTo(_) if !world.goto_actions => false,
+            TaskOption::Navigate(id) | TaskOption::GoTo(id) => world
+                .get_fixed_position(id)
+                .and_then(|target| world.distance(state.get_taxi(), target))
+                .is_some_and(|distance| distance > 0),
+        }
+    }
+
+    // The internal policy, None once the option cannot act any further.
+    pub fn action(self, world: &World, state: &State) -> Option<Actions> {
+        match self {
+            TaskOption::Primitive(action) => Some(action),
+            TaskOption::Navigate(id) | TaskOption::GoTo(id) => {
+                let target = world.get_fixed_position(id)?;
+                let taxi = state.get_taxi();
+                let distance = world.distance(taxi, target)?;
+
+                if distance == 0 {
+                    return None;
+                }
+
+                // The first move that gets one step closer.
+                [Actions::North, Actions::South, Actions::East, Actions::West]
+                    .iter()
+                    .cloned()
+                    .find(|action| match world.determine_affect(taxi, *action) {
+                        ActionAffect::Move(delta) => {
+                            world.distance(taxi + delta, target) == Some(distance - 1)
+                        }
+                        _ => false,
+                    })
+            }
+        }
+    }
+
+    // The termination condition, checked after every step.
+    pub fn terminates(self, world: &World, state: &State) -> bool {
+        match self {
+            TaskOption::Primitive(_) => true,
+            TaskOption::Navigate(id) | TaskOption::GoTo(id) => {
+                world.get_fixed_position(id) == Some(state.get_taxi())
+            }
+        }
+    }
+}
+
+impl fmt::Display for TaskOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TaskOption::Primitive(action) => write!(f, "{}", action),
+            TaskOption::Navigate(id) => write!(f, "Navigate({})", id),
+            TaskOption::GoTo(id) => write!(f, "GoTo({})", id),
+        }
+    }
+}
+
+// Ties between the best startable options are broken at random, skipping
+// the NaN of those that cannot start.
+fn random_greedy_option<R: Rng>(values: &[f64], rng: &mut R) -> Option<usize> {
+    let (_, best_value) = argmax_action(values)?;
+    let best: Vec<usize> = (0..values.len())
+        .filter(|index| approx_eq!(f64, values[*index], best_value, ulps = 2))
+        .collect();
+
+    Some(best[rng.gen_range(0, best.len())])
+}
+
+// What running one option to termination did.
+struct OptionOutcome {
+    next_state: State,
+    steps: usize,
+    // Discounted by the step each reward arrived on.
+    discounted_reward: f64,
+    total_reward: f64,
+}
+
+// Q-learning over options rather than single actions, so an option is
+// valued by the discounted reward it collects plus gamma^k of the value
+// where it ends, k being the steps it took (SMDP Q-learning).  Unlike MaxQ,
+// the options' own policies are given rather than learned.
+#[derive(Debug, Clone)]
+pub struct OptionQLearner {
+    alpha: f64,
+    gamma: f64,
+    epsilon: f64,
+    optimistic_value: f64,
+
+    options: Vec<TaskOption>,
+    state_indexer: StateIndexer,
+    qtable: Vec<Vec<f64>>,
+}
+
+impl OptionQLearner {
+    pub fn new(
+        world: &World,
+        options: Vec<TaskOption>,
+        alpha: f64,
+        gamma: f64,
+        epsilon: f64,
+    ) -> OptionQLearner {
+        let state_indexer = StateIndexer::new(world);
+        let optimistic_value = InitStrategy::Optimistic.initial_values(world, gamma, 1)[0];
+        let qtable = vec![vec![optimistic_value; options.len()]; state_indexer.num_states()];
+
+        OptionQLearner {
+            alpha,
+            gamma,
+            epsilon,
+            optimistic_value,
+
+            options,
+            state_indexer,
+            qtable,
+        }
+    }
+
+    pub fn options(&self) -> &[TaskOption] {
+        &self.options
+    }
+
+    // The value of each option from this state, NaN where it cannot start.
+    fn option_values(&self, world: &World, state: &State, state_index: usize) -> Vec<f64> {
+        self.options
+            .iter()
+            .zip(&self.qtable[state_index])
+            .map(|(option, value)| {
+                if option.can_start(world, state) {
+                    *value
+                } else {
+                    f64::NAN
+                }
+            })
+            .collect()
+    }
+
+    // Ties go to the earliest option, so primitives come first.
+    fn determine_best_option(&self, world: &World, state: &State) -> Option<usize> {
+        let state_index = self.state_indexer.get_index(world, state)?;
+        argmax_action(&self.option_values(world, state, state_index)).map(|(index, _)| index)
+    }
+
+    fn determine_learning_option<R: Rng>(
+        &self,
+        world: &World,
+        state: &State,
+        state_index: usize,
+        rng: &mut R,
+    ) -> Option<usize> {
+        let values = self.option_values(world, state, state_index);
+
+        if rng.gen_range(0.0f64, 1.0f64) < self.epsilon {
+            let startable: Vec<usize> = (0..values.len())
+                .filter(|index| !values[*index].is_nan())
+                .collect();
+
+            if startable.is_empty() {
+                None
+            } else {
+                Some(startable[rng.gen_range(0, startable.len())])
+            }
+        } else {
+            random_greedy_option(&values, rng)
+        }
+    }
+
+    // Runs the option until it terminates, the passenger is delivered or
+    // max_steps run out.  Each action is taken with apply, so learning can
+    // be stochastic while attempts stay repeatable.
+    fn run_option<F>(
+        &self,
+        world: &World,
+        mut state: State,
+        option: TaskOption,
+        max_steps: usize,
+        mut apply: F,
+    ) -> OptionOutcome
+    where
+        F: FnMut(&State, Actions) -> (f64, State),
+    {
+        let mut steps = 0;
+        let mut discounted_reward = 0.0;
+        let mut total_reward = 0.0;
+        let mut discount = 1.0;
+
+        while steps < max_steps && !state.at_destination() {
+            let action = match option.action(world, &state) {
+                Some(action) => action,
+                None => break,
+            };
+            let (reward, next_state) = apply(&state, action);
+            discounted_reward += discount * reward;
+            total_reward += reward;
+            discount *= self.gamma;
+            steps += 1;
+
+            state = next_state;
+
+            if option.terminates(world, &state) {
+                break;
+            }
+        }
+
+        OptionOutcome {
+            next_state: state,
+            steps,
+            discounted_reward,
+            total_reward,
+        }
+    }
+
+    // A GoTo while learning, the whole drive in one step.
+    fn run_goto(&self, world: &World, state: State, id: char) -> OptionOutcome {
+        let (reward, next_state) = state.apply_goto(world, id);
+
+        OptionOutcome {
+            next_state,
+            steps: 1,
+            discounted_reward: reward,
+            total_reward: reward,
+        }
+    }
+
+    fn find_maximal_value(&self, world: &World, state: &State) -> Option<f64> {
+        if state.at_destination() {
+            return Some(0.0);
+        }
+
+        let state_index = self.state_indexer.get_index(world, state)?;
+        argmax_action(&self.option_values(world, state, state_index)).map(|(_, value)| value)
+    }
+}
+
+impl Runner for OptionQLearner {
+    fn learn<R: Rng>(
+        &mut self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        rng: &mut R,
+    ) -> Trial {
+        let mut total_reward = 0.0;
+        let mut step = 0;
+
+        while step < max_steps {
+            if state.at_destination() {
+                return Trial::new(Some(step), total_reward);
+            }
+
+            let state_index = match self.state_indexer.get_index(world, &state) {
+                Some(state_index) => state_index,
+                None => break,
+            };
+            let option_index = match self.determine_learning_option(world, &state, state_index, rng)
+            {
+                Some(option_index) => option_index,
+                None => break,
+            };
+
+            let outcome = match self.options[option_index] {
+                TaskOption::GoTo(id) => self.run_goto(world, state, id),
+                option => {
+                    self.run_option(world, state, option, max_steps - step, |state, action| {
+                        state.apply_action_stochastic(world, action, rng)
+                    })
+                }
+            };
+            total_reward += outcome.total_reward;
+            step += outcome.steps;
+
+            if outcome.steps == 0 {
+                break;
+            }
+
+            if let Some(next_value) = self.find_maximal_value(world, &outcome.next_state) {
+                let target =
+                    outcome.discounted_reward + self.gamma.powi(outcome.steps as i32) * next_value;
+                let entry = &mut self.qtable[state_index][option_index];
+                *entry += self.alpha * (target - *entry);
+            }
+
+            state = outcome.next_state;
+        }
+
+        if state.at_destination() {
+            Trial::new(Some(step), total_reward)
+        } else {
+            Trial::new(None, total_reward)
+        }
+    }
+
+    fn attempt<R: Rng>(
+        &self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        _rng: &mut R,
+    ) -> Attempt {
+        let mut attempt = Attempt::new(state, max_steps);
+        let mut step = 0;
+
+        while step < max_steps && !state.at_destination() {
+            let option_index = match self.determine_best_option(world, &state) {
+                Some(option_index) => option_index,
+                None => break,
+            };
+
+            let outcome = self.run_option(
+                world,
+                state,
+                self.options[option_index],
+                max_steps - step,
+                |state, action| {
+                    attempt.step(action);
+                    state.apply_action(world, action)
+                },
+            );
+            step += outcome.steps;
+
+            if outcome.steps == 0 {
+                break;
+            }
+
+            state = outcome.next_state;
+        }
+
+        if state.at_destination() {
+            attempt.succeeded()
+        }
+
+        attempt
+    }
+
+    fn solves<R: Rng>(&self, world: &World, state: State, max_steps: usize, rng: &mut R) -> bool {
+        self.attempt(world, state, max_steps, rng).success
+    }
+
+    fn reset(&mut self) {
+        let optimistic_value = self.optimistic_value;
+        self.qtable
+            .iter_mut()
+            .flat_map(|values| values.iter_mut())
+            .for_each(|value| *value = optimistic_value);
+    }
+
+    fn report_training_result(&self, world: &World, _steps: Option<usize>) {
+        info!("");
+        for state in self.state_indexer.iter_states(world) {
+            if let Some(state_index) = self.state_indexer.get_index(world, &state) {
+                info!("{}", state.display(world));
+                for (option, value) in self.options.iter().zip(&self.qtable[state_index]) {
+                    info!("{} {}", option, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_options {
+    use super::*;
+    use crate::position::Position;
+    use crate::qlearner::QLearner;
+    use crate::runner::{run_training_session, Probe};
+    use crate::world::Costs;
+    use rand_pcg::Pcg64Mcg;
+
+    fn build_world() -> World {
+        let source = "\
+                      ┌───┬─────┐\n\
+                      │R .│. . G│\n\
+                      │   │     │\n\
+                      │. .│. . .│\n\
+                      │         │\n\
+                      │. . . . .│\n\
+                      │         │\n\
+                      │.│. .│. .│\n\
+                      │ │   │   │\n\
+                      │Y│. .│B .│\n\
+                      └─┴───┴───┘\n\
+                      ";
+
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        World::build_from_str(source, costs).unwrap()
+    }
+
+    // The probes start at fixed positions, where every choice between
+    // options is made.
+    fn build_probes(world: &World) -> Vec<Probe> {
+        vec![
+            Probe::new(State::build(world, (0, 0), Some('Y'), 'R').unwrap(), 10),
+            Probe::new(State::build(world, (4, 0), Some('Y'), 'G').unwrap(), 18),
+            Probe::new(State::build(world, (3, 4), Some('R'), 'B').unwrap(), 18),
+            Probe::new(State::build(world, (0, 4), Some('B'), 'G').unwrap(), 14),
+        ]
+    }
+
+    #[test]
+    fn navigate_follows_shortest_path() {
+        let world = build_world();
+        let navigate = TaskOption::Navigate('G');
+        let target = world.get_fixed_position('G').unwrap();
+
+        let mut state = State::build(&world, (0, 4), Some('R'), 'B').unwrap();
+        assert!(navigate.can_start(&world, &state));
+
+        let mut steps = 0;
+        while !navigate.terminates(&world, &state) {
+            let action = navigate.action(&world, &state).unwrap();
+            state = state.apply_action(&world, action).1;
+            steps += 1;
+        }
+
+        assert_eq!(state.get_taxi(), target);
+        assert_eq!(Some(steps), world.distance(Position::new(0, 4), target));
+
+        // Already there, so neither startable nor able to act.
+        assert!(!navigate.can_start(&world, &state));
+        assert_eq!(navigate.action(&world, &state), None);
+    }
+
+    #[test]
+    fn primitives_terminate_after_one_step() {
+        let world = build_world();
+        let state = State::build(&world, (2, 2), Some('R'), 'B').unwrap();
+        let options = TaskOption::standard_options(&world);
+
+        assert_eq!(options.len(), Actions::NUM_ELEMENTS + 4);
+        for option in options {
+            if let TaskOption::Primitive(action) = option {
+                assert!(option.can_start(&world, &state));
+                assert_eq!(option.action(&world, &state), Some(action));
+                assert!(option.terminates(&world, &state));
+            }
+        }
+    }
+
+    #[test]
+    fn needs_fewer_trials_than_flat_qlearner() {
+        let world = build_world();
+        let probes = build_probes(&world);
+
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let options = TaskOption::navigate_options(&world);
+        let mut option_qlearner = OptionQLearner::new(&world, options, 1.0, 0.9, 0.1);
+        let options_result =
+            run_training_session(&world, &probes, 5000, 200, &mut option_qlearner, &mut rng)
+                .unwrap();
+
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let mut qlearner = QLearner::new(&world, 1.0, 0.9, 0.1);
+        let flat_result =
+            run_training_session(&world, &probes, 5000, 200, &mut qlearner, &mut rng).unwrap();
+
+        assert!(options_result.steps.is_some());
+        assert!(options_result.trials < flat_result.trials);
+    }
+
+    #[test]
+    fn goto_needs_world_flag() {
+        let mut world = build_world();
+        let state = State::build(&world, (0, 4), Some('R'), 'B').unwrap();
+        let goto = TaskOption::GoTo('G');
+
+        assert!(!goto.can_start(&world, &state));
+        assert_eq!(
+            TaskOption::goto_options(&world).len(),
+            Actions::NUM_ELEMENTS + 4
+        );
+
+        world.goto_actions = true;
+        assert!(goto.can_start(&world, &state));
+
+        // Attempts replay it as Navigate's moves.
+        assert_eq!(
+            goto.action(&world, &state),
+            TaskOption::Navigate('G').action(&world, &state)
+        );
+    }
+
+    #[test]
+    fn learns_with_goto_actions() {
+        let mut world = build_world();
+        world.goto_actions = true;
+        let probes = build_probes(&world);
+
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let options = TaskOption::goto_options(&world);
+        let mut option_qlearner = OptionQLearner::new(&world, options, 1.0, 0.9, 0.1);
+        let result =
+            run_training_session(&world, &probes, 5000, 200, &mut option_qlearner, &mut rng)
+                .unwrap();
+        assert!(result.steps.is_some());
+
+        // The GoTos were learned, not just the primitives.
+        let state = State::build(&world, (4, 0), Some('Y'), 'G').unwrap();
+        let state_index = option_qlearner
+            .state_indexer
+            .get_index(&world, &state)
+            .unwrap();
+        let best = option_qlearner
+            .determine_best_option(&world, &state)
+            .unwrap();
+        assert_eq!(option_qlearner.options[best], TaskOption::GoTo('Y'));
+        assert!(option_qlearner.qtable[state_index][best] < option_qlearner.optimistic_value);
+    }
+}
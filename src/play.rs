@@ -0,0 +1,188 @@
+use std::io;
+
+use crossterm::event;
+use crossterm::event::{Event, KeyCode};
+use crossterm::terminal;
+
+use rand::Rng;
+
+use tui::backend::CrosstermBackend;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::widgets::{Paragraph, Wrap};
+use tui::Terminal;
+
+use taxi::actions::Actions;
+use taxi::state::State;
+use taxi::world::World;
+
+// The outcome of driving the taxi by hand.
+pub struct PlayResult {
+    pub reward: f64,
+    pub steps: usize,
+    pub delivered: bool,
+}
+
+pub struct Play<'a> {
+    world: &'a World,
+    start: State,
+    state_height: u16,
+    status_height: u16,
+    help_height: u16,
+    term_size: Rect,
+}
+
+impl<'a> Play<'a> {
+    pub fn new(world: &'a World, start: State) -> Play<'a> {
+        let state_height = (2 * world.height + 1) as u16;
+        let status_height = 3;
+        let help_height = HELP.lines().count() as u16;
+
+        let term_width = 80;
+        let term_height = state_height + status_height + help_height;
+
+        let term_size = Rect::new(0, 0, term_width, term_height);
+
+        Play {
+            world,
+            start,
+            state_height,
+            status_height,
+            help_height,
+            term_size,
+        }
+    }
+
+    // Runs until the passenger is delivered or the player quits.  The rng
+    // drives any environment randomness the world is configured with.
+    pub fn run<R: Rng>(&self, rng: &mut R) -> Result<PlayResult, io::Error> {
+        // Raw mode so that each key press acts at once, without Enter.
+        terminal::enable_raw_mode().map_err(to_io_error)?;
+
+        let result = self.drive(rng);
+
+        terminal::disable_raw_mode().map_err(to_io_error)?;
+
+        result
+    }
+
+    fn drive<R: Rng>(&self, rng: &mut R) -> Result<PlayResult, io::Error> {
+        let stdout = io::stdout();
+
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+
+        terminal.resize(self.term_size)?;
+
+        terminal.hide_cursor()?;
+        terminal.clear()?;
+
+        let mut state = self.start;
+        let mut result = PlayResult {
+            reward: 0.0,
+            steps: 0,
+            delivered: false,
+        };
+        let mut last = String::new();
+
+        self.draw(&state, &result, &last, &mut terminal)?;
+
+        while !result.delivered {
+            // Unlike a replay, a failed read must stop the loop, or a closed
+            // stdin would spin forever.
+            let event = event::read().map_err(to_io_error)?;
+
+            let action = match event {
+                Event::Key(key) => match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => break,
+                    KeyCode::Up => Actions::North,
+                    KeyCode::Down => Actions::South,
+                    KeyCode::Right => Actions::East,
+                    KeyCode::Left => Actions::West,
+                    KeyCode::Char('p') => Actions::PickUp,
+                    KeyCode::Char('d') => Actions::DropOff,
+                    KeyCode::Char('w') => Actions::Wait,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+
+            let (reward, next_state) = state.apply_action_stochastic(self.world, action, rng);
+
+            result.reward += reward;
+            result.steps += 1;
+            result.delivered = next_state.at_destination();
+            last = format!("{} for {}", action, reward);
+
+            state = next_state;
+
+            self.draw(&state, &result, &last, &mut terminal)?;
+        }
+
+        // Force a full re-draw so that it the cursor is at the end.
+        terminal.resize(self.term_size)?;
+        self.draw(&state, &result, &last, &mut terminal)?;
+
+        terminal.show_cursor()?;
+
+        Ok(result)
+    }
+
+    fn draw(
+        &self,
+        state: &State,
+        result: &PlayResult,
+        last: &str,
+        t: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    ) -> Result<(), io::Error> {
+        t.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Length(self.state_height),
+                        Constraint::Length(self.status_height),
+                        Constraint::Length(self.help_height),
+                    ]
+                    .as_ref(),
+                )
+                .split(self.term_size);
+
+            let grid = state.display(self.world);
+            let status = build_status_string(result, last);
+
+            let p0 = Paragraph::new(grid.as_str()).wrap(Wrap { trim: true });
+            f.render_widget(p0, chunks[0]);
+
+            let p1 = Paragraph::new(status.as_str()).wrap(Wrap { trim: true });
+            f.render_widget(p1, chunks[1]);
+
+            let p2 = Paragraph::new(HELP).wrap(Wrap { trim: true });
+            f.render_widget(p2, chunks[2]);
+        })?;
+
+        Ok(())
+    }
+}
+
+fn to_io_error(error: crossterm::ErrorKind) -> io::Error {
+    io::Error::other(format!("{:?}", error))
+}
+
+const HELP: &str = "\
+                    Arrows to drive, p to pick up, d to drop off, w to wait.\n\
+                    Escape or q to quit.\n\
+                    ";
+
+fn build_status_string(result: &PlayResult, last: &str) -> String {
+    let mut result_string = format!("Step {:^3} reward {}", result.steps, result.reward);
+
+    if !last.is_empty() {
+        result_string += &format!("\nLast action: {}", last);
+    }
+
+    if result.delivered {
+        result_string += "\nDelivered";
+    }
+
+    result_string
+}
@@ -0,0 +1,282 @@
+use rand::Rng;
+
+use crate::actions::Actions;
+use crate::qlearner::QLearner;
+use crate::replay_buffer::{ReplayBuffer, Transition};
+use crate::runner::{Attempt, Runner, Shaping, Trial};
+use crate::state::State;
+use crate::world::World;
+
+use super::QTable;
+
+// A QLearner that also stores every real step, and after each one replays
+// batch_size stored steps drawn uniformly from the buffer.
+#[derive(Debug, Clone)]
+pub struct QLearnerReplay {
+    qlearner: QLearner,
+    buffer: ReplayBuffer,
+    batch_size: usize,
+}
+
+impl QLearnerReplay {
+    pub fn new(
+        world: &World,
+        alpha: f64,
+        gamma: f64,
+        epsilon: f64,
+        buffer_size: usize,
+        batch_size: usize,
+    ) -> QLearnerReplay {
+        QLearnerReplay {
+            qlearner: QLearner::new(world, alpha, gamma, epsilon),
+            buffer: ReplayBuffer::new(buffer_size),
+            batch_size,
+        }
+    }
+
+    pub fn with_default_max_steps(self, max_steps: usize) -> QLearnerReplay {
+        QLearnerReplay {
+            qlearner: self.qlearner.with_default_max_steps(max_steps),
+            ..self
+        }
+    }
+
+    pub fn state_visits(&self, world: &World, state: &State) -> u32 {
+        self.qlearner.state_visits(world, state)
+    }
+
+    fn apply_transition(&mut self, transition: &Transition) {
+        self.qlearner.apply_experience(
+            transition.state_index,
+            transition.action,
+            transition.next_state_index,
+            transition.reward,
+            transition.done,
+        );
+    }
+
+    fn learn_episode<R: Rng>(
+        &mut self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        mut rng: &mut R,
+        shaping: &Shaping<'_>,
+        mut record: Option<&mut Vec<(usize, Actions)>>,
+    ) -> Trial {
+        let mut total_reward = 0.0;
+
+        for step in 0..max_steps {
+            if state.at_destination() {
+                return Trial::new(Some(step), total_reward);
+            }
+
+            let state_indexer = self.qlearner.state_indexer;
+
+            if let Some(state_index) = state_indexer.get_index(world, &state) {
+                self.qlearner.visit(state_index);
+
+                if let Some(next_action) = self
+                    .qlearner
+                    .determine_learning_action(state_index, &mut rng)
+                {
+                    if let Some(record) = record.as_mut() {
+                        record.push((state_index, next_action));
+                    }
+
+                    let (reward, next_state) =
+                        state.apply_action_stochastic(world, next_action, rng);
+                    total_reward += reward;
+
+                    if let Some(next_state_index) = state_indexer.get_index(world, &next_state) {
+                        let transition = Transition::new(
+                            state_index,
+                            next_action,
+                            reward + shaping(&state, &next_state),
+                            next_state_index,
+                            next_state.at_destination(),
+                        );
+
+                        self.apply_transition(&transition);
+                        self.buffer.push(transition, None);
+
+                        for replayed in self.buffer.sample(rng, self.batch_size) {
+                            self.apply_transition(&replayed);
+                        }
+                    } else {
+                        return Trial::new(None, total_reward);
+                    }
+
+                    state = next_state;
+                } else {
+                    return Trial::new(None, total_reward);
+                }
+            } else {
+                return Trial::new(None, total_reward);
+            }
+        }
+
+        if state.at_destination() {
+            Trial::new(Some(max_steps), total_reward)
+        } else {
+            Trial::new(None, total_reward)
+        }
+    }
+}
+
+impl Runner for QLearnerReplay {
+    fn learn<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+    ) -> Trial {
+        self.learn_shaped(world, state, max_steps, rng, &|_, _| 0.0)
+    }
+
+    // The buffer stores the shaped rewards, so replays learn from them too.
+    fn learn_shaped<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        shaping: &Shaping<'_>,
+    ) -> Trial {
+        self.learn_episode(world, state, max_steps, rng, shaping, None)
+    }
+
+    fn learn_recorded<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        record: &mut Vec<(usize, Actions)>,
+    ) -> Trial {
+        self.learn_episode(world, state, max_steps, rng, &|_, _| 0.0, Some(record))
+    }
+
+    fn attempt<R: Rng>(
+        &self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+    ) -> Attempt {
+        self.qlearner.attempt(world, state, max_steps, rng)
+    }
+
+    fn solves<R: Rng>(&self, world: &World, state: State, max_steps: usize, rng: &mut R) -> bool {
+        self.qlearner.solves(world, state, max_steps, rng)
+    }
+
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        self.qlearner.greedy_action(world, state)
+    }
+
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        self.qlearner.masked_greedy_action(world, state, available)
+    }
+
+    fn default_max_steps(&self) -> Option<usize> {
+        self.qlearner.default_max_steps()
+    }
+
+    fn reset(&mut self) {
+        self.qlearner.reset();
+        self.buffer.clear();
+    }
+
+    fn report_training_result(&self, world: &World, steps: Option<usize>) {
+        self.qlearner.report_training_result(world, steps);
+    }
+}
+
+#[cfg(test)]
+mod test_qlearner_replay {
+    use super::*;
+    use crate::runner::{run_training_session, Probe};
+    use crate::world::Costs;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn converges_no_slower_than_qlearner() {
+        let world_str = "\
+                         ┌─┬───┐\n\
+                         │R│. G│\n\
+                         │ │   │\n\
+                         │. . .│\n\
+                         │     │\n\
+                         │Y B .│\n\
+                         └─────┘\n\
+                         ";
+        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+
+        let probes = vec![
+            Probe::new(State::build(&world, (1, 1), Some('Y'), 'R').unwrap(), 20),
+            Probe::new(State::build(&world, (1, 1), Some('Y'), 'G').unwrap(), 20),
+            Probe::new(State::build(&world, (1, 1), Some('Y'), 'B').unwrap(), 20),
+            Probe::new(State::build(&world, (1, 1), Some('R'), 'B').unwrap(), 20),
+            Probe::new(State::build(&world, (1, 1), Some('G'), 'R').unwrap(), 20),
+            Probe::new(State::build(&world, (1, 1), Some('B'), 'G').unwrap(), 20),
+        ];
+
+        let mut plain_steps = 0;
+        let mut replay_steps = 0;
+
+        for seed in 0..5 {
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+            let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+            let plain =
+                run_training_session(&world, &probes, 2000, 100, &mut qlearner, &mut rng).unwrap();
+
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+            let mut replay = QLearnerReplay::new(&world, 0.1, 0.9, 0.1, 1000, 8);
+            let replayed =
+                run_training_session(&world, &probes, 2000, 100, &mut replay, &mut rng).unwrap();
+
+            plain_steps += plain.steps.expect("QLearner should converge");
+            replay_steps += replayed.steps.expect("QLearnerReplay should converge");
+        }
+
+        assert!(replay_steps <= plain_steps);
+    }
+
+    #[test]
+    fn zero_batch_matches_qlearner() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+
+        let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let mut replay = QLearnerReplay::new(&world, 0.1, 0.9, 0.1, 10, 0);
+
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let mut replay_rng = Pcg64Mcg::seed_from_u64(1);
+
+        for _ in 0..20 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            let replay_state = State::build_random(&world, &mut replay_rng).unwrap();
+
+            assert_eq!(
+                qlearner.learn(&world, state, 50, &mut rng),
+                replay.learn(&world, replay_state, 50, &mut replay_rng)
+            );
+        }
+
+        assert_eq!(qlearner.qtable, replay.qlearner.qtable);
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::actions::Actions;
+use crate::runner::{Attempt, Runner, Shaping, Trial};
+use crate::state::State;
+use crate::state_indexer::StateIndexer;
+use crate::util::argmax_action;
+use crate::world::World;
+
+use super::{initial_q_value, Abstraction, EpsilonSchedule, QTable};
+
+// A QLearner that only stores the states it has updated.  Every other state
+// still has the optimistic initial values, so it learns exactly as QLearner
+// does, but the table grows with the states visited rather than with the
+// size of the world.
+#[derive(Debug, Clone)]
+pub struct SparseQLearner {
+    alpha: f64,
+    gamma: f64,
+    epsilon: f64,
+    epsilon_schedule: EpsilonSchedule,
+    initial_values: [f64; Actions::NUM_ELEMENTS],
+
+    state_indexer: StateIndexer,
+    abstraction: Option<Abstraction>,
+    qtable: HashMap<usize, [f64; Actions::NUM_ELEMENTS]>,
+    visits: HashMap<usize, u32>,
+
+    default_max_steps: Option<usize>,
+}
+
+impl SparseQLearner {
+    pub fn new(world: &World, alpha: f64, gamma: f64, epsilon: f64) -> SparseQLearner {
+        SparseQLearner {
+            alpha,
+            gamma,
+            epsilon,
+            epsilon_schedule: EpsilonSchedule::Constant,
+            initial_values: [initial_q_value(world, gamma); Actions::NUM_ELEMENTS],
+
+            state_indexer: StateIndexer::new(world),
+            abstraction: None,
+            qtable: HashMap::new(),
+            visits: HashMap::new(),
+
+            default_max_steps: None,
+        }
+    }
+
+    // See QLearner::with_abstraction.
+    pub fn with_abstraction<F>(
+        world: &World,
+        abstraction: F,
+        num_abstract_states: usize,
+        alpha: f64,
+        gamma: f64,
+        epsilon: f64,
+    ) -> SparseQLearner
+    where
+        F: Fn(&State) -> usize + Send + Sync + 'static,
+    {
+        SparseQLearner {
+            abstraction: Some(Abstraction {
+                function: Arc::new(abstraction),
+                num_states: num_abstract_states,
+            }),
+            ..Self::new(world, alpha, gamma, epsilon)
+        }
+    }
+
+    pub fn with_epsilon_schedule(self, epsilon_schedule: EpsilonSchedule) -> SparseQLearner {
+        SparseQLearner {
+            epsilon_schedule,
+            ..self
+        }
+    }
+
+    pub fn with_default_max_steps(self, max_steps: usize) -> SparseQLearner {
+        SparseQLearner {
+            default_max_steps: Some(max_steps),
+            ..self
+        }
+    }
+
+    // Number of states with stored values.
+    pub fn num_entries(&self) -> usize {
+        self.qtable.len()
+    }
+
+    // Number of times learn has taken an action from this state.
+    pub fn state_visits(&self, world: &World, state: &State) -> u32 {
+        self.observation_index(world, state)
+            .and_then(|state_index| self.visits.get(&state_index).cloned())
+            .unwrap_or(0)
+    }
+
+    fn determine_best_action(&self, state_index: usize) -> Option<Actions> {
+        argmax_action(self.values(state_index))
+            .and_then(|(action_index, _)| Actions::from_index(action_index))
+    }
+}
+
+impl QTable for SparseQLearner {
+    fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    fn state_epsilon(&self, state_index: usize) -> f64 {
+        let visits = self.visits.get(&state_index).cloned().unwrap_or(0);
+        self.epsilon_schedule.epsilon(self.epsilon, visits)
+    }
+
+    fn observation_index(&self, world: &World, state: &State) -> Option<usize> {
+        match self.abstraction {
+            Some(ref abstraction) => abstraction.index(state),
+            None => self.state_indexer.get_observation_index(world, state),
+        }
+    }
+
+    // Reading never allocates, only values_mut does.
+    fn values(&self, state_index: usize) -> &[f64; Actions::NUM_ELEMENTS] {
+        self.qtable
+            .get(&state_index)
+            .unwrap_or(&self.initial_values)
+    }
+
+    fn values_mut(&mut self, state_index: usize) -> &mut [f64; Actions::NUM_ELEMENTS] {
+        let initial_values = self.initial_values;
+        self.qtable.entry(state_index).or_insert(initial_values)
+    }
+
+    fn visit(&mut self, state_index: usize) {
+        *self.visits.entry(state_index).or_insert(0) += 1;
+    }
+}
+
+impl Runner for SparseQLearner {
+    fn learn<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+    ) -> Trial {
+        self.learn_shaped(world, state, max_steps, rng, &|_, _| 0.0)
+    }
+
+    fn learn_shaped<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        shaping: &Shaping<'_>,
+    ) -> Trial {
+        self.learn_episode(world, state, max_steps, rng, shaping, None)
+    }
+
+    fn learn_recorded<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        record: &mut Vec<(usize, Actions)>,
+    ) -> Trial {
+        self.learn_episode_recorded(world, state, max_steps, rng, record)
+    }
+
+    fn attempt<R: Rng>(
+        &self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        _rng: &mut R,
+    ) -> Attempt {
+        let mut attempt = Attempt::new(state, max_steps);
+
+        for _ in 0..max_steps {
+            if state.at_destination() {
+                break;
+            }
+
+            if let Some(next_action) = self.greedy_action(world, &state) {
+                attempt.step(next_action);
+
+                let (_, next_state) = state.apply_action(world, next_action);
+                state = next_state;
+            } else {
+                break;
+            }
+        }
+
+        if state.at_destination() {
+            attempt.succeeded()
+        }
+
+        attempt
+    }
+
+    fn solves<R: Rng>(
+        &self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        _rng: &mut R,
+    ) -> bool {
+        for _ in 0..max_steps {
+            if state.at_destination() {
+                return true;
+            }
+
+            if let Some(next_action) = self.greedy_action(world, &state) {
+                let (_, next_state) = state.apply_action(world, next_action);
+                state = next_state;
+            } else {
+                break;
+            }
+        }
+
+        state.at_destination()
+    }
+
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        let state_index = self.observation_index(world, state)?;
+        self.determine_best_action(state_index)
+    }
+
+    fn default_max_steps(&self) -> Option<usize> {
+        self.default_max_steps
+    }
+
+    fn reset(&mut self) {
+        self.qtable.clear();
+        self.visits.clear();
+    }
+
+    fn report_training_result(&self, world: &World, _steps: Option<usize>) {
+        info!("");
+        for state in self.state_indexer.iter_states(world) {
+            if let Some(values) = self
+                .observation_index(world, &state)
+                .and_then(|observation_index| self.qtable.get(&observation_index))
+            {
+                info!("{}", state.display(world));
+                info!("{:?}", values);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sparse_qlearner {
+    use super::*;
+    use crate::qlearner::test_qlearner::build_get_abstraction;
+    use crate::qlearner::QLearner;
+    use crate::world::Costs;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    const WORLD: &str = "\
+                         ┌─┬───┐\n\
+                         │R│. G│\n\
+                         │ │   │\n\
+                         │. . .│\n\
+                         │     │\n\
+                         │Y B .│\n\
+                         └─────┘\n\
+                         ";
+
+    // Both learners take the same steps and reach the same values.
+    fn assert_matches_dense(world: &World, mut dense: QLearner, mut sparse: SparseQLearner) {
+        let mut dense_rng = Pcg64Mcg::seed_from_u64(3);
+        let mut sparse_rng = Pcg64Mcg::seed_from_u64(3);
+
+        let state = State::build(world, (1, 1), Some('Y'), 'G').unwrap();
+
+        for _ in 0..200 {
+            let mut dense_steps = Vec::new();
+            let mut sparse_steps = Vec::new();
+
+            assert_eq!(
+                dense.learn_recorded(world, state, 100, &mut dense_rng, &mut dense_steps),
+                sparse.learn_recorded(world, state, 100, &mut sparse_rng, &mut sparse_steps)
+            );
+            assert_eq!(dense_steps, sparse_steps);
+        }
+
+        for (state_index, values) in &sparse.qtable {
+            assert_eq!(values, &dense.qtable[*state_index]);
+        }
+        for (state_index, visits) in &sparse.visits {
+            assert_eq!(*visits, dense.visits[*state_index]);
+        }
+        assert!(sparse.num_entries() > 0);
+        assert!(sparse.num_entries() < dense.qtable.len());
+
+        assert!(sparse.solves(world, state, 20, &mut sparse_rng));
+        assert_eq!(
+            sparse.attempt(world, state, 20, &mut sparse_rng).actions,
+            dense.attempt(world, state, 20, &mut dense_rng).actions
+        );
+    }
+
+    #[test]
+    fn matches_dense_qlearner() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        assert_matches_dense(
+            &world,
+            QLearner::new(&world, 0.1, 0.9, 0.1),
+            SparseQLearner::new(&world, 0.1, 0.9, 0.1),
+        );
+    }
+
+    #[test]
+    fn matches_dense_qlearner_with_glie() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let schedule = EpsilonSchedule::Glie { c: 2.0 };
+
+        assert_matches_dense(
+            &world,
+            QLearner::new(&world, 0.1, 0.9, 0.1).with_epsilon_schedule(schedule),
+            SparseQLearner::new(&world, 0.1, 0.9, 0.1).with_epsilon_schedule(schedule),
+        );
+    }
+
+    #[test]
+    fn matches_dense_qlearner_with_abstraction() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let (dense_abstraction, num_abstract_states) = build_get_abstraction(&world);
+        let (sparse_abstraction, _) = build_get_abstraction(&world);
+
+        assert_matches_dense(
+            &world,
+            QLearner::with_abstraction(
+                &world,
+                dense_abstraction,
+                num_abstract_states,
+                0.1,
+                0.9,
+                0.1,
+            ),
+            SparseQLearner::with_abstraction(
+                &world,
+                sparse_abstraction,
+                num_abstract_states,
+                0.1,
+                0.9,
+                0.1,
+            ),
+        );
+    }
+
+    #[test]
+    fn unseen_states_are_optimistic() {
+        let world_str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+        let world = World::build_from_str(world_str, Costs::default()).unwrap();
+        let sparse = SparseQLearner::new(&world, 0.1, 0.9, 0.1);
+
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+        assert_eq!(sparse.greedy_action(&world, &state), Actions::from_index(0));
+        assert_eq!(sparse.num_entries(), 0);
+    }
+}
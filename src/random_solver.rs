@@ -1,17 +1,52 @@
+use std::fmt;
+
+use rand::distributions::{Distribution, WeightedError, WeightedIndex};
 use rand::Rng;
 
 use crate::actions::Actions;
 use crate::state::State;
 use crate::world::World;
 
-use crate::runner::{Attempt, Runner};
+use crate::runner::{Attempt, Runner, Trial};
+
+pub enum Error {
+    InvalidWeights(WeightedError),
+}
 
-#[derive(Default)]
-pub struct RandomSolver {}
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::InvalidWeights(ref weighted_error) => {
+                write!(f, "Invalid action weights: {}", weighted_error)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RandomSolver {
+    weights: Option<WeightedIndex<f64>>,
+}
 
 impl RandomSolver {
     pub fn new() -> RandomSolver {
-        RandomSolver {}
+        RandomSolver { weights: None }
+    }
+
+    // Weights are indexed by Actions::to_index and need not sum to one.
+    pub fn with_weights(weights: [f64; Actions::NUM_ELEMENTS]) -> Result<RandomSolver, Error> {
+        let weights = WeightedIndex::new(weights.iter()).map_err(Error::InvalidWeights)?;
+
+        Ok(RandomSolver {
+            weights: Some(weights),
+        })
+    }
+
+    fn choose_action<R: Rng>(&self, rng: &mut R) -> Actions {
+        match self.weights {
+            Some(ref weights) => Actions::from_index(weights.sample(rng)).unwrap(),
+            None => rng.gen(),
+        }
     }
 }
 
@@ -22,18 +57,21 @@ impl Runner for RandomSolver {
         mut state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize> {
+    ) -> Trial {
+        let mut total_reward = 0.0;
+
         for step in 0..max_steps {
             if state.at_destination() {
-                return Some(step);
+                return Trial::new(Some(step), total_reward);
             }
 
-            let action: Actions = rng.gen();
-            let (_, next_state) = state.apply_action(world, action);
+            let action = self.choose_action(rng);
+            let (reward, next_state) = state.apply_action_stochastic(world, action, rng);
+            total_reward += reward;
             state = next_state;
         }
 
-        None
+        Trial::new(None, total_reward)
     }
 
     fn attempt<R: Rng>(
@@ -50,7 +88,7 @@ impl Runner for RandomSolver {
                 break;
             }
 
-            let action: Actions = rng.gen();
+            let action = self.choose_action(rng);
             attempt.step(action);
             let (_, next_state) = state.apply_action(world, action);
             state = next_state;
@@ -75,7 +113,7 @@ impl Runner for RandomSolver {
                 return true;
             }
 
-            let action: Actions = rng.gen();
+            let action = self.choose_action(rng);
             let (_, next_state) = state.apply_action(world, action);
             state = next_state;
         }
@@ -83,3 +121,59 @@ impl Runner for RandomSolver {
         state.at_destination()
     }
 }
+
+#[cfg(test)]
+mod test_random_solver {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+
+    #[test]
+    fn same_seed_same_attempt() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let state = State::build(&world, (1, 0), Some('R'), 'G').unwrap();
+
+        let solver = RandomSolver::with_weights([1.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5]).unwrap();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+        let attempt = solver.attempt(&world, state, 50, &mut rng);
+
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+        let repeat = solver.attempt(&world, state, 50, &mut rng);
+
+        assert_eq!(attempt.actions, repeat.actions);
+        assert_eq!(attempt.success, repeat.success);
+    }
+
+    #[test]
+    fn weights_bias_actions() {
+        let solver = RandomSolver::with_weights([1.0, 0.0, 0.0, 0.0, 0.0, 3.0, 0.0]).unwrap();
+        let mut rng = Pcg64Mcg::seed_from_u64(4);
+
+        let mut counts = [0; Actions::NUM_ELEMENTS];
+        for _ in 0..1000 {
+            counts[solver.choose_action(&mut rng).to_index()] += 1;
+        }
+
+        assert_eq!(counts[1..5], [0, 0, 0, 0]);
+        assert_eq!(counts[Actions::Wait.to_index()], 0);
+        assert!(counts[Actions::DropOff.to_index()] > 2 * counts[Actions::North.to_index()]);
+    }
+
+    #[test]
+    fn rejects_invalid_weights() {
+        assert!(RandomSolver::with_weights([0.0; Actions::NUM_ELEMENTS]).is_err());
+        assert!(RandomSolver::with_weights([1.0, -1.0, 1.0, 1.0, 1.0, 1.0, 1.0]).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::path::Path;
 
 use crossterm::event;
 use crossterm::event::{Event, KeyCode};
@@ -9,6 +10,7 @@ use tui::widgets::{Paragraph, Wrap};
 use tui::Terminal;
 
 use taxi::actions::Actions;
+use taxi::runner;
 use taxi::runner::Attempt;
 use taxi::world::World;
 
@@ -26,15 +28,11 @@ pub struct Replay {
 
 impl Replay {
     pub fn new(world: &World, attempt: Attempt) -> Replay {
-        let mut states = Vec::with_capacity(attempt.actions.len() + 1);
-        let mut state = attempt.initial_state;
-        states.push(state.display(world));
-
-        for a in &attempt.actions {
-            let (_, next_state) = state.apply_action(world, *a);
-            states.push(next_state.display(world));
-            state = next_state;
-        }
+        let states = attempt
+            .states(world)
+            .iter()
+            .map(|state| state.display(world))
+            .collect();
 
         let state_height = (2 * world.height + 1) as u16;
 
@@ -64,6 +62,11 @@ impl Replay {
         }
     }
 
+    // Replays an attempt saved with Attempt::save.
+    pub fn from_file<P: AsRef<Path>>(world: &World, path: P) -> Result<Replay, runner::Error> {
+        Ok(Replay::new(world, Attempt::load(world, path)?))
+    }
+
     pub fn run(&self) -> Result<(), io::Error> {
         let stdout = io::stdout();
 
@@ -80,14 +83,12 @@ impl Replay {
         self.draw(step, &mut terminal)?;
 
         loop {
-            if let Ok(event) = event::read() {
-                if let Event::Key(key) = event {
-                    match key.code {
-                        KeyCode::Esc => break,
-                        KeyCode::Right => step = self.trim_step(step + 1),
-                        KeyCode::Left => step = self.trim_step(step - 1),
-                        _ => (),
-                    }
+            if let Ok(Event::Key(key)) = event::read() {
+                match key.code {
+                    KeyCode::Esc => break,
+                    KeyCode::Right => step = self.trim_step(step + 1),
+                    KeyCode::Left => step = self.trim_step(step - 1),
+                    _ => (),
                 }
             };
 
@@ -0,0 +1,234 @@
+use rand::Rng;
+
+use crate::actions::Actions;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub state_index: usize,
+    pub action: Actions,
+    pub reward: f64,
+    pub next_state_index: usize,
+    // The next state is terminal, so has no value to bootstrap from.
+    pub done: bool,
+}
+
+impl Transition {
+    pub fn new(
+        state_index: usize,
+        action: Actions,
+        reward: f64,
+        next_state_index: usize,
+        done: bool,
+    ) -> Transition {
+        Transition {
+            state_index,
+            action,
+            reward,
+            next_state_index,
+            done,
+        }
+    }
+}
+
+// A fixed capacity store of transitions.  Once full, each push replaces
+// the oldest transition.
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    transitions: Vec<Transition>,
+    priorities: Vec<f64>,
+    next_slot: usize,
+    max_priority: f64,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> ReplayBuffer {
+        ReplayBuffer {
+            capacity,
+            transitions: Vec::with_capacity(capacity),
+            priorities: Vec::with_capacity(capacity),
+            next_slot: 0,
+            max_priority: 1.0,
+        }
+    }
+
+    // Empties the buffer, keeping its allocation.
+    pub fn clear(&mut self) {
+        self.transitions.clear();
+        self.priorities.clear();
+        self.next_slot = 0;
+        self.max_priority = 1.0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    // Without a priority the transition gets the highest priority seen so
+    // far, so new experience is sampled at least once soon.
+    pub fn push(&mut self, transition: Transition, priority: Option<f64>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let priority = match priority {
+            Some(priority) => priority.max(0.0),
+            None => self.max_priority,
+        };
+        self.max_priority = self.max_priority.max(priority);
+
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+            self.priorities.push(priority);
+        } else {
+            self.transitions[self.next_slot] = transition;
+            self.priorities[self.next_slot] = priority;
+        }
+
+        self.next_slot = (self.next_slot + 1) % self.capacity;
+    }
+
+    pub fn update_priority(&mut self, index: usize, priority: f64) {
+        if let Some(slot) = self.priorities.get_mut(index) {
+            *slot = priority.max(0.0);
+            self.max_priority = self.max_priority.max(*slot);
+        }
+    }
+
+    // n transitions drawn uniformly, with replacement.
+    pub fn sample<R: Rng>(&self, rng: &mut R, n: usize) -> Vec<Transition> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        (0..n)
+            .map(|_| self.transitions[rng.gen_range(0, self.len())])
+            .collect()
+    }
+
+    // n transitions drawn in proportion to their priority, with
+    // replacement.  Each comes with its index for update_priority.  If no
+    // transition has any priority this is the same as sample.
+    pub fn sample_prioritized<R: Rng>(&self, rng: &mut R, n: usize) -> Vec<(usize, Transition)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let total_priority: f64 = self.priorities.iter().sum();
+
+        (0..n)
+            .map(|_| {
+                let index = if total_priority > 0.0 {
+                    let mut roll = rng.gen_range(0.0, total_priority);
+                    let mut chosen = self.len() - 1;
+
+                    for (index, priority) in self.priorities.iter().enumerate() {
+                        if roll < *priority {
+                            chosen = index;
+                            break;
+                        }
+                        roll -= priority;
+                    }
+
+                    chosen
+                } else {
+                    rng.gen_range(0, self.len())
+                };
+
+                (index, self.transitions[index])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_replay_buffer {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    fn transition(state_index: usize) -> Transition {
+        Transition::new(state_index, Actions::North, -1.0, state_index + 1, false)
+    }
+
+    #[test]
+    fn oldest_replaced_when_full() {
+        let mut buffer = ReplayBuffer::new(3);
+        assert!(buffer.is_empty());
+
+        for i in 0..5 {
+            buffer.push(transition(i), None);
+        }
+        assert_eq!(buffer.len(), 3);
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let mut seen: Vec<usize> = buffer
+            .sample(&mut rng, 100)
+            .iter()
+            .map(|t| t.state_index)
+            .collect();
+        seen.sort_unstable();
+        seen.dedup();
+
+        assert_eq!(seen, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn empty_samples_nothing() {
+        let buffer = ReplayBuffer::new(3);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        assert!(buffer.sample(&mut rng, 5).is_empty());
+        assert!(buffer.sample_prioritized(&mut rng, 5).is_empty());
+    }
+
+    #[test]
+    fn uniform_sampling_unbiased() {
+        let mut buffer = ReplayBuffer::new(4);
+        for i in 0..4 {
+            buffer.push(transition(i), Some((i * 10) as f64));
+        }
+
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let mut counts = [0usize; 4];
+        for t in buffer.sample(&mut rng, 40_000) {
+            counts[t.state_index] += 1;
+        }
+
+        // Priorities play no part, each is close to a quarter.
+        for count in &counts {
+            assert!((*count as f64 - 10_000.0).abs() < 500.0);
+        }
+    }
+
+    #[test]
+    fn prioritized_sampling_favors_high_priority() {
+        let mut buffer = ReplayBuffer::new(4);
+        buffer.push(transition(0), Some(1.0));
+        buffer.push(transition(1), Some(1.0));
+        buffer.push(transition(2), Some(8.0));
+        buffer.push(transition(3), Some(0.0));
+
+        let mut rng = Pcg64Mcg::seed_from_u64(2);
+        let mut counts = [0usize; 4];
+        for (index, t) in buffer.sample_prioritized(&mut rng, 10_000) {
+            assert_eq!(index, t.state_index);
+            counts[index] += 1;
+        }
+
+        assert!((counts[2] as f64 - 8_000.0).abs() < 400.0);
+        assert_eq!(counts[3], 0);
+
+        for index in 0..3 {
+            buffer.update_priority(index, 0.0);
+        }
+        buffer.update_priority(3, 5.0);
+        for (index, _) in buffer.sample_prioritized(&mut rng, 100) {
+            assert_eq!(index, 3);
+        }
+    }
+}
@@ -8,8 +8,9 @@ use crate::actions::Actions;
 use crate::state::State;
 use crate::world::World;
 
-use crate::runner::{Attempt, Runner};
+use crate::runner::{Attempt, Runner, Trial};
 use crate::state_indexer::StateIndexer;
+use crate::util::argmax_available_action;
 
 #[derive(Debug, Clone)]
 struct TransitionEntry {
@@ -32,6 +33,63 @@ struct RewardEntry {
     count: f64,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct RMaxParams {
+    pub gamma: f64,
+    pub r_max: f64,
+    pub value_iteration_tolerance: f64,
+    pub known_threshold: f64,
+    pub max_iterations: usize,
+}
+
+impl RMaxParams {
+    pub const DEFAULT_MAX_ITERATIONS: usize = 10_000;
+
+    pub fn new(world: &World, gamma: f64) -> RMaxParams {
+        RMaxParams {
+            gamma,
+            r_max: world.max_reward(),
+            value_iteration_tolerance: 1.0e-6,
+            known_threshold: 1.0,
+            max_iterations: RMaxParams::DEFAULT_MAX_ITERATIONS,
+        }
+    }
+}
+
+// How many value iteration sweeps the rebuilds before each step needed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SweepStats {
+    pub last_sweeps: usize,
+    pub max_sweeps: usize,
+    // Rebuilds that stopped at max_iterations without reaching the tolerance.
+    pub unconverged_rebuilds: usize,
+}
+
+// Consecutive rebuilds that finished on their first sweep, so the
+// experience before each left the values where they were.  Both RMax
+// variants count their value table as converged once it held for as many
+// rebuilds as there are states.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SettledRebuilds(usize);
+
+impl SettledRebuilds {
+    pub fn record(&mut self, converged: bool, sweeps: usize) {
+        if converged && sweeps == 1 {
+            self.0 += 1;
+        } else {
+            self.0 = 0;
+        }
+    }
+
+    pub fn count(self) -> usize {
+        self.0
+    }
+
+    pub fn has_converged(self, num_states: usize) -> bool {
+        self.0 >= num_states
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RMax {
     state_indexer: StateIndexer,
@@ -45,10 +103,25 @@ pub struct RMax {
     gamma: f64,
     error_delta: f64,
     known_count: f64,
+    max_iterations: usize,
+
+    sweep_stats: SweepStats,
+
+    settled_rebuilds: SettledRebuilds,
 }
 
 impl RMax {
     pub fn new(world: &World, gamma: f64, known_count: f64, error_delta: f64) -> RMax {
+        let params = RMaxParams {
+            value_iteration_tolerance: error_delta,
+            known_threshold: known_count,
+            ..RMaxParams::new(world, gamma)
+        };
+
+        RMax::with_params(world, params)
+    }
+
+    pub fn with_params(world: &World, params: RMaxParams) -> RMax {
         let state_indexer = StateIndexer::new(world);
         let num_states = state_indexer.num_states();
         let value_table = vec![0.0; num_states];
@@ -60,16 +133,33 @@ impl RMax {
 
         RMax {
             state_indexer,
-            rmax: world.max_reward(),
+            rmax: params.r_max,
 
             transition_table,
             reward_table,
 
             value_table,
 
-            gamma,
-            known_count,
-            error_delta,
+            gamma: params.gamma,
+            known_count: params.known_threshold,
+            error_delta: params.value_iteration_tolerance,
+            max_iterations: params.max_iterations,
+
+            sweep_stats: SweepStats::default(),
+
+            settled_rebuilds: SettledRebuilds::default(),
+        }
+    }
+
+    // Copies the learned model, for continuing training with new parameters.
+    pub fn with_hyperparams(&self, params: RMaxParams) -> RMax {
+        RMax {
+            rmax: params.r_max,
+            gamma: params.gamma,
+            known_count: params.known_threshold,
+            error_delta: params.value_iteration_tolerance,
+            max_iterations: params.max_iterations,
+            ..self.clone()
         }
     }
 
@@ -105,14 +195,36 @@ impl RMax {
         }
     }
 
+    fn is_known_index(&self, state_action_index: usize) -> bool {
+        self.reward_table[state_action_index].count >= self.known_count
+            && self.transition_table[state_action_index].count >= self.known_count
+    }
+
+    // Whether the model has seen this state-action often enough to plan with
+    // it rather than the optimistic rmax value.
+    pub fn is_known(&self, world: &World, state: &State, action: Actions) -> bool {
+        self.state_indexer
+            .get_index(world, state)
+            .is_some_and(|state_index| {
+                self.is_known_index(state_index * Actions::NUM_ELEMENTS + action.to_index())
+            })
+    }
+
+    // Fraction of all state-actions that are known.
+    pub fn coverage(&self) -> f64 {
+        let num_known = (0..self.reward_table.len())
+            .filter(|&state_action_index| self.is_known_index(state_action_index))
+            .count();
+
+        num_known as f64 / self.reward_table.len() as f64
+    }
+
     fn measure_value(&self, state_index: usize, action_index: usize) -> f64 {
         let state_action_index = state_index * Actions::NUM_ELEMENTS + action_index;
 
-        let transition_entry = &self.transition_table[state_action_index];
-        let reward_entry = &self.reward_table[state_action_index];
-
-        if reward_entry.count >= self.known_count && transition_entry.count >= self.known_count {
-            let mut action_value = reward_entry.mean;
+        if self.is_known_index(state_action_index) {
+            let transition_entry = &self.transition_table[state_action_index];
+            let mut action_value = self.reward_table[state_action_index].mean;
 
             for (next_state_index, transition_count) in &transition_entry.destination_counts {
                 let transition = transition_count / self.known_count;
@@ -141,6 +253,17 @@ impl RMax {
         best_value
     }
 
+    // Every action's value, indexed by Actions::to_index.
+    fn action_values(&self, world: &World, state: &State) -> Option<Vec<f64>> {
+        let state_index = self.state_indexer.get_index(world, state)?;
+
+        Some(
+            (0..Actions::NUM_ELEMENTS)
+                .map(|action_index| self.measure_value(state_index, action_index))
+                .collect(),
+        )
+    }
+
     fn determine_best_action_index<R: Rng>(&self, state_index: usize, rng: &mut R) -> usize {
         let mut best_value = -f64::MAX;
         let mut best_action_index = Actions::NUM_ELEMENTS;
@@ -165,10 +288,19 @@ impl RMax {
         best_action_index
     }
 
+    pub fn sweep_stats(&self) -> SweepStats {
+        self.sweep_stats
+    }
+
     fn rebuild_value_table(&mut self) {
         let num_states = self.state_indexer.num_states();
 
-        for _ in 0..10_000 {
+        let mut sweeps = 0;
+        let mut converged = false;
+
+        for _ in 0..self.max_iterations {
+            sweeps += 1;
+
             let mut error = 0.0;
 
             for state_index in 0..num_states {
@@ -185,56 +317,97 @@ impl RMax {
             }
 
             if error < self.error_delta {
+                converged = true;
                 break;
             }
         }
+
+        self.sweep_stats.last_sweeps = sweeps;
+        self.sweep_stats.max_sweeps = self.sweep_stats.max_sweeps.max(sweeps);
+        if !converged {
+            self.sweep_stats.unconverged_rebuilds += 1;
+        }
+
+        self.settled_rebuilds.record(converged, sweeps);
     }
 
     fn select_best_action<R: Rng>(&self, state_index: usize, rng: &mut R) -> Option<Actions> {
         let action_index = self.determine_best_action_index(state_index, rng);
         Actions::from_index(action_index)
     }
-}
 
-impl Runner for RMax {
-    fn learn<R: Rng>(
+    fn learn_episode<R: Rng>(
         &mut self,
         world: &World,
         mut state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize> {
+        mut record: Option<&mut Vec<(usize, Actions)>>,
+    ) -> Trial {
+        let mut total_reward = 0.0;
+
         for step in 0..max_steps {
             if state.at_destination() {
-                return Some(step);
+                return Trial::new(Some(step), total_reward);
             }
 
             self.rebuild_value_table();
 
             if let Some(state_index) = self.state_indexer.get_index(world, &state) {
                 if let Some(next_action) = self.select_best_action(state_index, rng) {
-                    let (reward, next_state) = state.apply_action(world, next_action);
+                    if let Some(record) = record.as_mut() {
+                        record.push((state_index, next_action));
+                    }
+
+                    let (reward, next_state) =
+                        state.apply_action_stochastic(world, next_action, rng);
+                    total_reward += reward;
 
                     if let Some(next_state_index) = self.state_indexer.get_index(world, &next_state)
                     {
                         self.apply_experience(state_index, next_action, next_state_index, reward);
                     } else {
-                        return None;
+                        return Trial::new(None, total_reward);
                     }
 
                     state = next_state;
                 } else {
-                    return None;
+                    return Trial::new(None, total_reward);
                 }
+            } else {
+                return Trial::new(None, total_reward);
             }
         }
 
         if state.at_destination() {
-            Some(max_steps)
+            Trial::new(Some(max_steps), total_reward)
         } else {
-            None
+            Trial::new(None, total_reward)
         }
     }
+}
+
+impl Runner for RMax {
+    fn learn<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+    ) -> Trial {
+        self.learn_episode(world, state, max_steps, rng, None)
+    }
+
+    fn learn_recorded<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        record: &mut Vec<(usize, Actions)>,
+    ) -> Trial {
+        self.learn_episode(world, state, max_steps, rng, Some(record))
+    }
 
     fn attempt<R: Rng>(
         &self,
@@ -297,6 +470,42 @@ impl Runner for RMax {
         state.at_destination()
     }
 
+    fn greedy_action(&self, world: &World, state: &State) -> Option<Actions> {
+        let all_actions: Vec<Actions> = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .collect();
+        self.masked_greedy_action(world, state, &all_actions)
+    }
+
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        argmax_available_action(&self.action_values(world, state)?, available)
+    }
+
+    // See SettledRebuilds.
+    fn has_converged(&self) -> bool {
+        self.settled_rebuilds
+            .has_converged(self.state_indexer.num_states())
+    }
+
+    fn reset(&mut self) {
+        for transition_entry in &mut self.transition_table {
+            transition_entry.destination_counts.clear();
+            transition_entry.count = 0.0;
+        }
+        self.reward_table
+            .iter_mut()
+            .for_each(|reward_entry| *reward_entry = RewardEntry::default());
+        self.value_table.iter_mut().for_each(|value| *value = 0.0);
+
+        self.sweep_stats = SweepStats::default();
+        self.settled_rebuilds = SettledRebuilds::default();
+    }
+
     fn report_training_result(&self, world: &World, _steps: Option<usize>) {
         let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
 
@@ -306,19 +515,335 @@ impl Runner for RMax {
 
             if !state.at_destination() {
                 if let Some(next_action) = self.select_best_action(state_index, &mut rng) {
-                    println!("===================");
-                    println!("{}", state.display(world));
-                    println!("Best action: {}", next_action);
+                    info!("===================");
+                    info!("{}", state.display(world));
+                    info!("Best action: {}", next_action);
 
                     for action_index in 0..Actions::NUM_ELEMENTS {
                         let action = Actions::from_index(action_index).unwrap();
 
                         let action_value = self.measure_value(state_index, action_index);
 
-                        println!("{} - {}", action, action_value);
+                        info!("{} - {}", action, action_value);
                     }
                 }
             }
         }
+
+        info!(
+            "Value iteration: {} sweeps last, {} max, {} rebuilds stopped at {} sweeps.",
+            self.sweep_stats.last_sweeps,
+            self.sweep_stats.max_sweeps,
+            self.sweep_stats.unconverged_rebuilds,
+            self.max_iterations
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_rmax {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
+
+    use crate::runner::{run_training_session_with_options, Probe, TrainingOptions};
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌───┐\n\
+                         │R .│\n\
+                         │   │\n\
+                         │. G│\n\
+                         └───┘\n\
+                         ";
+
+    #[test]
+    fn default_params_match_new() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        let mut rmax = RMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rmax_params = RMax::with_params(&world, RMaxParams::new(&world, 0.3));
+
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let mut rng_params = Pcg64Mcg::seed_from_u64(7);
+
+        for _ in 0..5 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            let state_params = State::build_random(&world, &mut rng_params).unwrap();
+            assert_eq!(state, state_params);
+
+            let steps = rmax.learn(&world, state, 50, &mut rng);
+            let steps_params = rmax_params.learn(&world, state_params, 50, &mut rng_params);
+            assert_eq!(steps, steps_params);
+        }
+
+        assert_eq!(rmax.value_table, rmax_params.value_table);
+    }
+
+    #[test]
+    fn known_threshold_requires_repeats() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        let params = RMaxParams {
+            known_threshold: 3.0,
+            ..RMaxParams::new(&world, 0.3)
+        };
+        let mut rmax = RMax::with_params(&world, params);
+
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+        let state_index = rmax.state_indexer.get_index(&world, &state).unwrap();
+        let (reward, next_state) = state.apply_action(&world, Actions::North);
+        let next_state_index = rmax.state_indexer.get_index(&world, &next_state).unwrap();
+
+        let optimistic = rmax.measure_value(state_index, Actions::North.to_index());
+
+        for _ in 0..2 {
+            rmax.apply_experience(state_index, Actions::North, next_state_index, reward);
+            assert!(approx_eq!(
+                f64,
+                rmax.measure_value(state_index, Actions::North.to_index()),
+                optimistic,
+                ulps = 2
+            ));
+        }
+
+        assert!(!rmax.is_known(&world, &state, Actions::North));
+        assert_eq!(rmax.coverage(), 0.0);
+
+        rmax.apply_experience(state_index, Actions::North, next_state_index, reward);
+        assert!(approx_eq!(
+            f64,
+            rmax.measure_value(state_index, Actions::North.to_index()),
+            reward,
+            ulps = 2
+        ));
+
+        assert!(rmax.is_known(&world, &state, Actions::North));
+        assert!(!rmax.is_known(&world, &state, Actions::South));
+        let num_state_actions = rmax.state_indexer.num_states() * Actions::NUM_ELEMENTS;
+        assert!(approx_eq!(
+            f64,
+            rmax.coverage(),
+            1.0 / num_state_actions as f64,
+            ulps = 2
+        ));
+    }
+
+    #[test]
+    fn coverage_grows_with_training() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut rmax = RMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+
+        let mut last_coverage = rmax.coverage();
+        for _ in 0..10 {
+            rmax.learn(&world, state, 50, &mut rng);
+
+            let coverage = rmax.coverage();
+            assert!(coverage >= last_coverage);
+            last_coverage = coverage;
+        }
+
+        assert!(last_coverage > 0.0);
+        assert!(last_coverage <= 1.0);
+    }
+
+    #[test]
+    fn max_iterations_bounds_planning() {
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+
+        let params = RMaxParams {
+            max_iterations: 1,
+            ..RMaxParams::new(&world, 0.3)
+        };
+        let mut bounded = RMax::with_params(&world, params);
+        bounded.rebuild_value_table();
+
+        // A single sweep only sees the immediate optimistic reward.
+        for value in &bounded.value_table {
+            assert!(approx_eq!(f64, *value, 20.0, ulps = 2));
+        }
+
+        let mut unbounded = RMax::with_params(&world, RMaxParams::new(&world, 0.3));
+        unbounded.rebuild_value_table();
+
+        for value in &unbounded.value_table {
+            assert!(approx_eq!(f64, *value, 20.0 / 0.7, epsilon = 1.0e-5));
+        }
+    }
+
+    #[test]
+    fn session_stops_on_convergence() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        // Never solvable in one step, so only convergence ends the session.
+        let probes = vec![Probe::new(
+            State::build(&world, (1, 0), Some('R'), 'G').unwrap(),
+            1,
+        )];
+
+        let run = |stop_on_convergence| {
+            let mut rmax = RMax::new(&world, 0.9, 1.0, 1.0e-6);
+            let mut rng = Pcg64Mcg::seed_from_u64(0);
+            let mut first_converged = None;
+
+            let result = run_training_session_with_options(
+                &world,
+                &probes,
+                (500, 30),
+                &mut rmax,
+                &mut rng,
+                &TrainingOptions {
+                    stop_on_convergence,
+                    ..TrainingOptions::default()
+                },
+                |trial, rmax: &RMax| {
+                    if first_converged.is_none() && rmax.has_converged() {
+                        first_converged = Some(trial);
+                    }
+                },
+            )
+            .unwrap();
+
+            (result, first_converged, rmax)
+        };
+
+        let (result, first_converged, rmax) = run(true);
+        assert_eq!(result.steps, None);
+        assert!(rmax.has_converged());
+        assert_eq!(Some(result.trials), first_converged);
+        assert!(result.trials < 500);
+
+        let (result, first_converged, _) = run(false);
+        assert_eq!(result.trials, 500);
+        assert!(first_converged.is_some());
+    }
+
+    #[test]
+    fn sweep_stats_report_unconverged_rebuilds() {
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(WORLD, costs).unwrap();
+
+        let params = RMaxParams {
+            max_iterations: 1,
+            ..RMaxParams::new(&world, 0.3)
+        };
+        let mut bounded = RMax::with_params(&world, params);
+        bounded.rebuild_value_table();
+        bounded.rebuild_value_table();
+
+        assert_eq!(
+            bounded.sweep_stats(),
+            SweepStats {
+                last_sweeps: 1,
+                max_sweeps: 1,
+                unconverged_rebuilds: 2,
+            }
+        );
+
+        let mut unbounded = RMax::with_params(&world, RMaxParams::new(&world, 0.3));
+        unbounded.rebuild_value_table();
+        let first_sweeps = unbounded.sweep_stats().last_sweeps;
+
+        // Nothing has been learned, so the second rebuild starts converged.
+        unbounded.rebuild_value_table();
+
+        let stats = unbounded.sweep_stats();
+        assert!(first_sweeps > 1);
+        assert_eq!(stats.last_sweeps, 1);
+        assert_eq!(stats.max_sweeps, first_sweeps);
+        assert_eq!(stats.unconverged_rebuilds, 0);
+    }
+
+    #[test]
+    fn with_hyperparams_keeps_model() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        let mut rmax = RMax::with_params(&world, RMaxParams::new(&world, 0.3));
+        let mut rng = Pcg64Mcg::seed_from_u64(8);
+
+        for _ in 0..3 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            rmax.learn(&world, state, 50, &mut rng);
+        }
+
+        let tuned = rmax.with_hyperparams(RMaxParams {
+            max_iterations: 5,
+            ..RMaxParams::new(&world, 0.9)
+        });
+
+        assert_eq!(tuned.value_table, rmax.value_table);
+        assert_eq!(tuned.reward_table.len(), rmax.reward_table.len());
+        for (tuned_entry, entry) in tuned.reward_table.iter().zip(&rmax.reward_table) {
+            assert_eq!(tuned_entry.count, entry.count);
+            assert_eq!(tuned_entry.mean, entry.mean);
+        }
+        assert!(approx_eq!(f64, tuned.gamma, 0.9, ulps = 2));
+        assert_eq!(tuned.max_iterations, 5);
+    }
+
+    #[test]
+    fn masked_greedy_action_stays_available() {
+        let world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let mut rmax = RMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(11);
+
+        for _ in 0..3 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            rmax.learn(&world, state, 20, &mut rng);
+        }
+
+        let mut num_greedy_blocked = 0;
+        for state in StateIndexer::new(&world).iter_states(&world) {
+            if state.at_destination() {
+                continue;
+            }
+
+            let available = world.available_actions(state.get_taxi());
+            let greedy = rmax.greedy_action(&world, &state).unwrap();
+            let masked = rmax
+                .masked_greedy_action(&world, &state, &available)
+                .unwrap();
+
+            assert!(available.contains(&masked));
+            if available.contains(&greedy) {
+                assert_eq!(masked, greedy);
+            } else {
+                num_greedy_blocked += 1;
+            }
+        }
+
+        assert!(num_greedy_blocked > 0);
+    }
+
+    #[test]
+    fn wandered_passenger_ends_episode() {
+        let mut world = World::build_from_str(WORLD, Costs::default()).unwrap();
+        world.passenger_wander = 1.0;
+
+        let mut rmax = RMax::new(&world, 0.3, 1.0, 1.0e-6);
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+
+        // The state indexer has no slot for a passenger off the fixed
+        // positions, so such a start gives up instead of idling to max_steps.
+        // Idling rebuilt the unchanged values every step, which counted as
+        // settled rebuilds and passed for convergence.
+        let wandered =
+            State::build_with_passenger_position(&world, (1, 1), Some((1, 0)), 'G').unwrap();
+        assert_eq!(
+            rmax.learn(&world, wandered, 50, &mut rng),
+            Trial::new(None, 0.0)
+        );
+        assert!(!rmax.has_converged());
     }
 }
@@ -1,16 +1,27 @@
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
 
 use crate::actions::Actions;
+use crate::distribution::MeasureDistribution;
+use crate::position::Position;
 use crate::state;
-use crate::state::State;
-use crate::world::World;
+use crate::state::{PassengerState, State};
+use crate::state_indexer::StateIndexer;
+use crate::world::{Costs, World};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Probe {
     pub state: State,
     pub maximum_steps: usize,
+    // The optimal number of steps, when known.
+    pub expected_steps: Option<usize>,
+    // How much the probe counts in the harness's weighted probe metrics.
+    pub weight: f64,
 }
 
 impl Probe {
@@ -18,8 +29,211 @@ impl Probe {
         Probe {
             state,
             maximum_steps,
+            expected_steps: None,
+            weight: 1.0,
         }
     }
+
+    // Probes that pass without any learning, or can never pass, would hide
+    // what the solver has actually learned.
+    pub fn validate(&self, world: &World) -> Result<(), String> {
+        let on_world = |position: Position| {
+            position.x >= 0
+                && position.x < world.width
+                && position.y >= 0
+                && position.y < world.height
+        };
+
+        if !on_world(self.state.get_taxi()) {
+            return Err(format!(
+                "the taxi at {:?} is off the {}x{} world",
+                self.state.get_taxi(),
+                world.width,
+                world.height
+            ));
+        }
+
+        match self.state.passenger_state() {
+            PassengerState::Waiting(id) if world.get_fixed_position(id).is_none() => {
+                return Err(format!("the passenger waits at unknown position {}", id));
+            }
+            PassengerState::WaitingAt(position) if !on_world(position) => {
+                return Err(format!(
+                    "the passenger at {:?} is off the {}x{} world",
+                    position, world.width, world.height
+                ));
+            }
+            _ => {}
+        }
+
+        if world
+            .get_fixed_position(self.state.get_destination())
+            .is_none()
+        {
+            return Err(format!(
+                "the destination {} is not a fixed position",
+                self.state.get_destination()
+            ));
+        }
+
+        if self.state.at_destination() {
+            return Err(format!(
+                "the passenger is already delivered, it starts at destination {}",
+                self.state.get_destination()
+            ));
+        }
+
+        if self.maximum_steps == 0 {
+            return Err(String::from(
+                "maximum_steps is 0, so it can never be solved",
+            ));
+        }
+
+        if !self.weight.is_finite() || self.weight < 0.0 {
+            return Err(format!(
+                "weight is {}, it must be a finite non-negative number",
+                self.weight
+            ));
+        }
+
+        if let Some(expected) = self.expected_steps {
+            if expected > self.maximum_steps {
+                return Err(format!(
+                    "expects {} steps but allows only {}",
+                    expected, self.maximum_steps
+                ));
+            }
+        }
+
+        match world.optimal_steps(&self.state) {
+            Some(optimal) if optimal > self.maximum_steps => Err(format!(
+                "needs at least {} steps but allows only {}",
+                optimal, self.maximum_steps
+            )),
+            Some(_) => Ok(()),
+            None => Err(String::from(
+                "the passenger can never be delivered from this state",
+            )),
+        }
+    }
+
+    pub fn check<Rnr: Runner, R: Rng>(
+        &self,
+        world: &World,
+        runner: &Rnr,
+        rng: &mut R,
+    ) -> ProbeOutcome {
+        let attempt = runner.attempt(world, self.state, self.maximum_steps, rng);
+
+        if !attempt.success {
+            return ProbeOutcome::Failed;
+        }
+
+        let steps = attempt.actions.len();
+
+        match self.expected_steps {
+            None => ProbeOutcome::Solved(steps),
+            Some(expected) if steps == expected => ProbeOutcome::Optimal,
+            Some(expected) if steps > expected => ProbeOutcome::Feasible { steps, expected },
+            Some(expected) => ProbeOutcome::BeatExpected { steps, expected },
+        }
+    }
+
+    // The discounted return of the runner's attempt at the probe, see
+    // Attempt::discounted_return.
+    pub fn discounted_return<Rnr: Runner, R: Rng>(
+        &self,
+        world: &World,
+        runner: &Rnr,
+        gamma: f64,
+        rng: &mut R,
+    ) -> f64 {
+        runner
+            .attempt(world, self.state, self.maximum_steps, rng)
+            .discounted_return(world, gamma)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeOutcome {
+    Failed,
+    // Solved, with no expected_steps to compare against.
+    Solved(usize),
+    Optimal,
+    // Solved, but taking more steps than expected.
+    Feasible { steps: usize, expected: usize },
+    // Solved in fewer steps than expected, so the expectation is too high.
+    BeatExpected { steps: usize, expected: usize },
+}
+
+// Where a probe starts, e.g. "taxi (1,1), Y -> R".
+fn describe_start(state: &State) -> String {
+    let taxi = state.get_taxi();
+    let destination = state.get_destination();
+
+    let passenger = match state.passenger_state() {
+        PassengerState::Waiting(id) => format!("{} -> {}", id, destination),
+        PassengerState::WaitingAt(position) => {
+            format!("({},{}) -> {}", position.x, position.y, destination)
+        }
+        PassengerState::InTaxi => format!("in taxi -> {}", destination),
+        PassengerState::Delivered => format!("delivered at {}", destination),
+    };
+
+    format!("taxi ({},{}), {}", taxi.x, taxi.y, passenger)
+}
+
+// A plain text table with one aligned row per probe: where it starts,
+// whether the runner's attempt delivers the passenger, the steps taken
+// against the probe's limit, and the expected steps when known.
+pub fn probe_table<Rnr: Runner, R: Rng>(
+    world: &World,
+    probes: &[Probe],
+    runner: &Rnr,
+    rng: &mut R,
+) -> String {
+    let mut rows = vec![[
+        String::from("Probe"),
+        String::from("Start"),
+        String::from("Solved"),
+        String::from("Steps"),
+        String::from("Expected"),
+    ]];
+
+    for (probe_index, probe) in probes.iter().enumerate() {
+        let attempt = runner.attempt(world, probe.state, probe.maximum_steps, rng);
+
+        rows.push([
+            probe_index.to_string(),
+            describe_start(&probe.state),
+            String::from(if attempt.success { "yes" } else { "no" }),
+            format!("{}/{}", attempt.actions.len(), probe.maximum_steps),
+            probe
+                .expected_steps
+                .map_or_else(|| String::from("-"), |expected| expected.to_string()),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+
+        table += line.join("  ").trim_end();
+        table.push('\n');
+    }
+
+    table
 }
 
 pub trait Runner {
@@ -29,7 +243,7 @@ pub trait Runner {
         state: State,
         max_steps: usize,
         rng: &mut R,
-    ) -> Option<usize>;
+    ) -> Trial;
     fn attempt<R: Rng>(
         &self,
         world: &World,
@@ -38,11 +252,312 @@ pub trait Runner {
         rng: &mut R,
     ) -> Attempt;
 
+    // Like learn, but the solver learns from each reward plus
+    // shaping(state, next_state).  The trial still reports the unshaped
+    // reward.  Solvers that cannot use shaping learn as usual.
+    fn learn_shaped<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        _shaping: &Shaping<'_>,
+    ) -> Trial {
+        self.learn(world, state, max_steps, rng)
+    }
+
+    // Like learn, but appends the state index and action of every step taken
+    // to record.  Solvers that cannot report their steps record nothing.
+    fn learn_recorded<R: Rng>(
+        &mut self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        rng: &mut R,
+        _record: &mut Vec<(usize, Actions)>,
+    ) -> Trial {
+        self.learn(world, state, max_steps, rng)
+    }
+
     fn solves<R: Rng>(&self, world: &World, state: State, max_steps: usize, rng: &mut R) -> bool;
 
+    // Fraction of the states solved within max_steps.  An empty set of
+    // states is trivially all solved, so returns 1.0.
+    fn evaluate<R: Rng>(
+        &self,
+        world: &World,
+        states: &[State],
+        max_steps: usize,
+        rng: &mut R,
+    ) -> f64 {
+        if states.is_empty() {
+            return 1.0;
+        }
+
+        let num_solved = states
+            .iter()
+            .filter(|state| self.solves(world, **state, max_steps, rng))
+            .count();
+
+        (num_solved as f64) / (states.len() as f64)
+    }
+
+    // The action attempt would take from this state, or None if the
+    // solver has no single greedy choice.
+    fn greedy_action(&self, _world: &World, _state: &State) -> Option<Actions> {
+        None
+    }
+
+    // The greedy choice among the available actions only.  Solvers that
+    // can rank their actions should override this; by default the greedy
+    // action is kept only when it is available.
+    fn masked_greedy_action(
+        &self,
+        world: &World,
+        state: &State,
+        available: &[Actions],
+    ) -> Option<Actions> {
+        self.greedy_action(world, state)
+            .filter(|action| available.contains(action))
+    }
+
+    // Like attempt, but never takes a move that World::available_actions
+    // marks as blocked, so no steps are wasted driving into walls.
+    fn attempt_masked<R: Rng>(
+        &self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        _rng: &mut R,
+    ) -> Attempt {
+        let mut attempt = Attempt::new(state, max_steps);
+
+        for _ in 0..max_steps {
+            if state.at_destination() {
+                break;
+            }
+
+            let available = world.available_actions(state.get_taxi());
+
+            if let Some(next_action) = self.masked_greedy_action(world, &state, &available) {
+                attempt.step(next_action);
+                let (_, next_state) = state.apply_action(world, next_action);
+                state = next_state;
+            } else {
+                break;
+            }
+        }
+
+        if state.at_destination() {
+            attempt.succeeded()
+        }
+
+        attempt
+    }
+
+    // Like attempt, but a state without a greedy action, such as one the
+    // solver cannot index, takes the fallback action rather than ending
+    // the attempt.
+    fn attempt_with_fallback<R: Rng>(
+        &self,
+        world: &World,
+        mut state: State,
+        max_steps: usize,
+        rng: &mut R,
+        fallback: Fallback,
+    ) -> Attempt {
+        let mut attempt = Attempt::new(state, max_steps);
+
+        for _ in 0..max_steps {
+            if state.at_destination() {
+                break;
+            }
+
+            let next_action = match self.greedy_action(world, &state) {
+                Some(action) => action,
+                None => match fallback.action(rng) {
+                    Some(action) => action,
+                    None => break,
+                },
+            };
+
+            attempt.step(next_action);
+            let (_, next_state) = state.apply_action(world, next_action);
+            state = next_state;
+        }
+
+        if state.at_destination() {
+            attempt.succeeded()
+        }
+
+        attempt
+    }
+
+    // Like attempt, but calls observer after every step with the state it
+    // led to, the action and its reward, for drawing attempts as they go.
+    // Solvers with a greedy_action are stepped by it, and observed as each
+    // step is taken.  Those without one have no greedy action from the
+    // first state, so their finished attempt is replayed instead, using a
+    // fixed seed as in solution_trajectory.
+    fn attempt_with_observer<O>(
+        &self,
+        world: &World,
+        initial_state: State,
+        max_steps: usize,
+        mut observer: O,
+    ) -> Attempt
+    where
+        O: FnMut(&State, Actions, f64),
+    {
+        let mut attempt = Attempt::new(initial_state, max_steps);
+        let mut state = initial_state;
+
+        for step in 0..max_steps {
+            if state.at_destination() {
+                break;
+            }
+
+            let next_action = match self.greedy_action(world, &state) {
+                Some(action) => action,
+                None if step == 0 => {
+                    return self.replay_with_observer(world, initial_state, max_steps, observer)
+                }
+                None => break,
+            };
+
+            attempt.step(next_action);
+            let (reward, next_state) = state.apply_action(world, next_action);
+            observer(&next_state, next_action, reward);
+            state = next_state;
+        }
+
+        if state.at_destination() {
+            attempt.succeeded()
+        }
+
+        attempt
+    }
+
+    // The fallback of attempt_with_observer, observing a finished attempt.
+    fn replay_with_observer<O>(
+        &self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+        mut observer: O,
+    ) -> Attempt
+    where
+        O: FnMut(&State, Actions, f64),
+    {
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let attempt = self.attempt(world, state, max_steps, &mut rng);
+
+        let mut state = attempt.initial_state;
+        for action in &attempt.actions {
+            let (reward, next_state) = state.apply_action(world, *action);
+            observer(&next_state, *action, reward);
+            state = next_state;
+        }
+
+        attempt
+    }
+
+    // Each state of a successful attempt with the action taken from it, or
+    // None when the attempt fails.  Attempts of most solvers ignore the
+    // rng, a fixed seed keeps the rest repeatable.
+    fn solution_trajectory(
+        &self,
+        world: &World,
+        state: State,
+        max_steps: usize,
+    ) -> Option<Vec<(State, Actions)>> {
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let attempt = self.attempt(world, state, max_steps, &mut rng);
+
+        if !attempt.success {
+            return None;
+        }
+
+        Some(
+            attempt
+                .states(world)
+                .into_iter()
+                .zip(attempt.actions)
+                .collect(),
+        )
+    }
+
+    // Every unsolved state the greedy attempt fails to solve from, with why.
+    // Attempts use a fixed seed, as in solution_trajectory.
+    fn failure_report(&self, world: &World, max_steps: usize) -> Vec<(State, FailureReason)> {
+        let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+        let state_indexer = StateIndexer::new(world);
+
+        state_indexer
+            .iter_states(world)
+            .filter(|state| !state.at_destination())
+            .filter_map(|state| {
+                let attempt = self.attempt(world, state, max_steps, &mut rng);
+
+                if attempt.success {
+                    None
+                } else {
+                    Some((state, FailureReason::classify(world, &attempt, max_steps)))
+                }
+            })
+            .collect()
+    }
+
+    // A step limit set when the solver was built.  Sessions use it in place
+    // of any larger max_steps they are given.
+    fn default_max_steps(&self) -> Option<usize> {
+        None
+    }
+
+    // Whether learning has stopped changing the solver's values, for
+    // sessions that stop on convergence rather than on the probes.
+    fn has_converged(&self) -> bool {
+        false
+    }
+
+    // Forgets everything learned, as if newly built with the same
+    // parameters, reusing the solver's tables rather than reallocating them.
+    // Solvers that learn nothing can keep this default.
+    fn reset(&mut self) {}
+
     fn report_training_result(&self, _world: &World, _steps: Option<usize>) {}
 }
 
+// What Runner::attempt_with_fallback does where the solver has no action.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fallback {
+    Random,
+    Action(Actions),
+}
+
+impl Fallback {
+    pub fn action<R: Rng>(self, rng: &mut R) -> Option<Actions> {
+        match self {
+            Fallback::Random => Actions::from_index(rng.gen_range(0, Actions::NUM_ELEMENTS)),
+            Fallback::Action(action) => Some(action),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trial {
+    // Only set when the trial reached the destination.
+    pub steps: Option<usize>,
+    pub reward: f64,
+}
+
+impl Trial {
+    pub fn new(steps: Option<usize>, reward: f64) -> Trial {
+        Trial { steps, reward }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Attempt {
     pub initial_state: State,
     pub actions: Vec<Actions>,
@@ -65,10 +580,147 @@ impl Attempt {
     pub fn succeeded(&mut self) {
         self.success = true;
     }
+
+    pub fn actions(&self) -> Vec<Actions> {
+        self.actions.clone()
+    }
+
+    // Replays the actions from the initial state, so the result holds one
+    // more state than there are actions.
+    pub fn states(&self, world: &World) -> Vec<State> {
+        let mut states = Vec::with_capacity(self.actions.len() + 1);
+        let mut state = self.initial_state;
+        states.push(state);
+
+        for action in &self.actions {
+            let (_, next_state) = state.apply_action(world, *action);
+            states.push(next_state);
+            state = next_state;
+        }
+
+        states
+    }
+
+    // The reward of each action, found by replaying them like states.
+    pub fn rewards(&self, world: &World) -> Vec<f64> {
+        let mut state = self.initial_state;
+
+        self.actions
+            .iter()
+            .map(|action| {
+                let (reward, next_state) = state.apply_action(world, *action);
+                state = next_state;
+                reward
+            })
+            .collect()
+    }
+
+    // The sum of gamma^t r_t over the attempt, 0 when no action was taken.
+    pub fn discounted_return(&self, world: &World, gamma: f64) -> f64 {
+        self.rewards(world)
+            .iter()
+            .rev()
+            .fold(0.0, |later_return, reward| reward + gamma * later_return)
+    }
+
+    pub fn to_json(&self, world: &World) -> Result<String, Error> {
+        let saved = SavedAttempt {
+            taxi: (
+                self.initial_state.get_taxi().x,
+                self.initial_state.get_taxi().y,
+            ),
+            passenger: self
+                .initial_state
+                .get_passenger_position(world)
+                .map(|position| (position.x, position.y)),
+            destination: self.initial_state.get_destination(),
+            actions: self.actions.clone(),
+            success: self.success,
+        };
+
+        serde_json::to_string(&saved).map_err(Error::AttemptFormat)
+    }
+
+    // The initial state is rebuilt against the world, so an attempt saved
+    // for another world is refused rather than replayed off the map.
+    pub fn from_json(world: &World, json: &str) -> Result<Attempt, Error> {
+        let saved: SavedAttempt = serde_json::from_str(json).map_err(Error::AttemptFormat)?;
+
+        let initial_state = State::build_with_passenger_position(
+            world,
+            saved.taxi,
+            saved.passenger,
+            saved.destination,
+        )
+        .map_err(Error::AttemptState)?;
+
+        Ok(Attempt {
+            initial_state,
+            actions: saved.actions,
+            success: saved.success,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, world: &World, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_json(world)?).map_err(Error::AttemptIo)
+    }
+
+    pub fn load<P: AsRef<Path>>(world: &World, path: P) -> Result<Attempt, Error> {
+        let json = fs::read_to_string(path).map_err(Error::AttemptIo)?;
+        Attempt::from_json(world, &json)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureReason {
+    // The attempt came back to a state it had already been in.
+    Loops,
+    // The solver had no action to take before max_steps ran out.
+    GivesUp,
+    // Every step went somewhere new, but not to the destination in time.
+    TimedOut,
+}
+
+impl FailureReason {
+    fn classify(world: &World, attempt: &Attempt, max_steps: usize) -> FailureReason {
+        let states = attempt.states(world);
+
+        let revisits = states
+            .iter()
+            .enumerate()
+            .any(|(index, state)| states[..index].contains(state));
+
+        if revisits {
+            FailureReason::Loops
+        } else if attempt.actions.len() < max_steps {
+            FailureReason::GivesUp
+        } else {
+            FailureReason::TimedOut
+        }
+    }
+}
+
+// The saved form of an Attempt, only the states need replaying.
+#[derive(Serialize, Deserialize)]
+struct SavedAttempt {
+    taxi: (i32, i32),
+    // None when the passenger starts in the taxi.
+    passenger: Option<(i32, i32)>,
+    destination: char,
+    actions: Vec<Actions>,
+    success: bool,
 }
 
 pub enum Error {
     BuildRandomState(state::Error),
+    AttemptIo(io::Error),
+    AttemptFormat(serde_json::Error),
+    AttemptState(state::Error),
+    ReplayDiverged {
+        step: usize,
+        expected: Option<(usize, Actions)>,
+        found: Option<(usize, Actions)>,
+    },
 }
 
 impl fmt::Debug for Error {
@@ -77,6 +729,166 @@ impl fmt::Debug for Error {
             Error::BuildRandomState(ref state_error) => {
                 write!(f, "Failed to build random state:\n{:?}", state_error)
             }
+            Error::AttemptIo(ref io_error) => {
+                write!(f, "Failed to access attempt file:\n{:?}", io_error)
+            }
+            Error::AttemptFormat(ref format_error) => {
+                write!(f, "Failed to read attempt:\n{:?}", format_error)
+            }
+            Error::AttemptState(ref state_error) => write!(
+                f,
+                "Failed to build the initial state of the attempt:\n{:?}",
+                state_error
+            ),
+            Error::ReplayDiverged {
+                step,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Replay diverged at step {}, expected {:?} but found {:?}.",
+                step, expected, found
+            ),
+        }
+    }
+}
+
+// Rewards are determined by the world's costs and per destination delivery
+// rewards, so transforming those transforms every reward a learner sees.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RewardTransform {
+    #[default]
+    Identity,
+    // Clip every reward to [-1, 1].
+    Clip,
+    // Divide every reward by the largest reward magnitude, as max_reward is
+    // zero with the default costs.
+    Normalize,
+}
+
+impl RewardTransform {
+    pub fn transform_costs(self, costs: &Costs) -> Costs {
+        self.transform_costs_with_scale(costs, cost_scale(costs))
+    }
+
+    pub fn transform_world(self, world: &World) -> World {
+        // The per destination rewards count towards the largest magnitude.
+        let scale = (0..world.num_fixed_positions())
+            .filter_map(|index| world.get_fixed_id_from_index(index))
+            .map(|id| world.delivery_reward(id).abs())
+            .fold(cost_scale(&world.costs), f64::max);
+
+        let mut result = world.clone();
+        result.costs = self.transform_costs_with_scale(&world.costs, scale);
+        result.map_delivery_rewards(|reward| self.transform_reward(reward, scale));
+        result
+    }
+
+    fn transform_costs_with_scale(self, costs: &Costs, scale: f64) -> Costs {
+        let transform = |reward: f64| self.transform_reward(reward, scale);
+
+        Costs {
+            movement: transform(costs.movement),
+            blocked_movement: transform(costs.blocked_movement),
+            pickup: transform(costs.pickup),
+            miss_pickup: transform(costs.miss_pickup),
+            miss_dropoff: transform(costs.miss_dropoff),
+            empty_dropoff: transform(costs.empty_dropoff),
+            delivery_reward: transform(costs.delivery_reward),
+            wait: transform(costs.wait),
+        }
+    }
+
+    fn transform_reward(self, reward: f64, scale: f64) -> f64 {
+        match self {
+            RewardTransform::Identity => reward,
+            RewardTransform::Clip => reward.clamp(-1.0, 1.0),
+            RewardTransform::Normalize if scale > 0.0 => reward / scale,
+            RewardTransform::Normalize => reward,
+        }
+    }
+}
+
+// The largest reward magnitude among the costs.
+fn cost_scale(costs: &Costs) -> f64 {
+    costs
+        .movement
+        .abs()
+        .max(costs.blocked_movement.abs())
+        .max(costs.pickup.abs())
+        .max(costs.miss_pickup.abs())
+        .max(costs.miss_dropoff.abs())
+        .max(costs.empty_dropoff.abs())
+        .max(costs.delivery_reward.abs())
+        .max(costs.wait.abs())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StartStates {
+    // The passenger is waiting somewhere, see State::build_random.
+    #[default]
+    Waiting,
+    // Any unsolved state, including ones with the passenger in the taxi.
+    Any,
+}
+
+// An extra reward for a step from the first state to the second.
+pub type Shaping<'a> = dyn Fn(&State, &State) -> f64 + 'a;
+
+// Potential based shaping, F(s, s') = gamma * phi(s') - phi(s), which
+// leaves the optimal policy unchanged.  Delivered states have potential 0,
+// as nothing follows them.
+pub struct PotentialShaping<P> {
+    gamma: f64,
+    potential: P,
+}
+
+impl<P> PotentialShaping<P>
+where
+    P: Fn(&State) -> f64,
+{
+    pub fn new(gamma: f64, potential: P) -> PotentialShaping<P> {
+        PotentialShaping { gamma, potential }
+    }
+
+    pub fn shaping(&self, state: &State, next_state: &State) -> f64 {
+        let next_potential = if next_state.at_destination() {
+            0.0
+        } else {
+            (self.potential)(next_state)
+        };
+
+        self.gamma * next_potential - (self.potential)(state)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrainingOptions {
+    // Learners see, and the session reports, transformed rewards.  Build the
+    // solver with the transformed world for matching initial values.
+    pub reward_transform: RewardTransform,
+    pub start_states: StartStates,
+    // End the session once the runner reports it has converged, even if
+    // the probes have not passed.
+    pub stop_on_convergence: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionResult {
+    // Only set when the probes passed before running out of trials.
+    pub steps: Option<usize>,
+    pub trials: usize,
+    pub total_reward: f64,
+    // One per probe, from the final policy.
+    pub probe_outcomes: Vec<ProbeOutcome>,
+}
+
+impl SessionResult {
+    pub fn average_reward(&self) -> f64 {
+        if self.trials > 0 {
+            self.total_reward / (self.trials as f64)
+        } else {
+            0.0
         }
     }
 }
@@ -87,37 +899,1750 @@ pub fn run_training_session<Rnr, R>(
     max_trials: usize,
     max_steps: usize,
     runner: &mut Rnr,
-    mut rng: &mut R,
-) -> Result<Option<usize>, Error>
+    rng: &mut R,
+) -> Result<SessionResult, Error>
 where
     Rnr: Runner,
     R: Rng,
 {
-    let mut total_steps = 0;
-
-    for _ in 0..max_trials {
-        match State::build_random(world, &mut rng) {
-            Err(state_error) => {
-                return Err(Error::BuildRandomState(state_error));
-            }
+    run_training_session_with_observer(world, probes, max_trials, max_steps, runner, rng, |_, _| {})
+}
 
-            Ok(state) => {
-                if let Some(num_steps) = runner.learn(world, state, max_steps, &mut rng) {
+// The observer is called after every trial with the number of trials run so
+// far and the runner, before the probes are checked.
+pub fn run_training_session_with_observer<Rnr, R, O>(
+    world: &World,
+    probes: &[Probe],
+    max_trials: usize,
+    max_steps: usize,
+    runner: &mut Rnr,
+    rng: &mut R,
+    observer: O,
+) -> Result<SessionResult, Error>
+where
+    Rnr: Runner,
+    R: Rng,
+    O: FnMut(usize, &Rnr),
+{
+    run_training_session_with_options(
+        world,
+        probes,
+        (max_trials, max_steps),
+        runner,
+        rng,
+        &TrainingOptions::default(),
+        observer,
+    )
+}
+
+pub fn run_training_session_with_options<Rnr, R, O>(
+    world: &World,
+    probes: &[Probe],
+    limits: (usize, usize),
+    runner: &mut Rnr,
+    rng: &mut R,
+    options: &TrainingOptions,
+    observer: O,
+) -> Result<SessionResult, Error>
+where
+    Rnr: Runner,
+    R: Rng,
+    O: FnMut(usize, &Rnr),
+{
+    run_session(
+        world,
+        probes,
+        limits,
+        runner,
+        rng,
+        options,
+        |runner, world, state, max_steps, rng| runner.learn(world, state, max_steps, rng),
+        observer,
+    )
+}
+
+// Learners see the shaped rewards, see Runner::learn_shaped, while the
+// session reports the unshaped ones.
+pub fn run_training_session_with_shaping<Rnr, R, P>(
+    world: &World,
+    probes: &[Probe],
+    limits: (usize, usize),
+    runner: &mut Rnr,
+    rng: &mut R,
+    options: &TrainingOptions,
+    shaping: &PotentialShaping<P>,
+) -> Result<SessionResult, Error>
+where
+    Rnr: Runner,
+    R: Rng,
+    P: Fn(&State) -> f64,
+{
+    let shaping = |state: &State, next_state: &State| shaping.shaping(state, next_state);
+
+    run_session(
+        world,
+        probes,
+        limits,
+        runner,
+        rng,
+        options,
+        |runner, world, state, max_steps, rng| {
+            runner.learn_shaped(world, state, max_steps, rng, &shaping)
+        },
+        |_, _| {},
+    )
+}
+
+// Also returns the state index and action of every learning step, see
+// Runner::learn_recorded.
+pub fn run_training_session_recorded<Rnr, R>(
+    world: &World,
+    probes: &[Probe],
+    limits: (usize, usize),
+    runner: &mut Rnr,
+    rng: &mut R,
+    options: &TrainingOptions,
+) -> Result<(SessionResult, Vec<(usize, Actions)>), Error>
+where
+    Rnr: Runner,
+    R: Rng,
+{
+    let mut record = Vec::new();
+
+    let result = run_session(
+        world,
+        probes,
+        limits,
+        runner,
+        rng,
+        options,
+        |runner, world, state, max_steps, rng| {
+            runner.learn_recorded(world, state, max_steps, rng, &mut record)
+        },
+        |_, _| {},
+    )?;
+
+    Ok((result, record))
+}
+
+// Reruns a recorded session, failing at the first step that differs from
+// the recording.  Given the same seed and a fresh runner, a deterministic
+// solver always reproduces its recording.
+pub fn replay_recorded<Rnr, R>(
+    world: &World,
+    probes: &[Probe],
+    limits: (usize, usize),
+    runner: &mut Rnr,
+    rng: &mut R,
+    options: &TrainingOptions,
+    recorded: &[(usize, Actions)],
+) -> Result<SessionResult, Error>
+where
+    Rnr: Runner,
+    R: Rng,
+{
+    let (result, record) =
+        run_training_session_recorded(world, probes, limits, runner, rng, options)?;
+
+    for step in 0..record.len().max(recorded.len()) {
+        let expected = recorded.get(step).cloned();
+        let found = record.get(step).cloned();
+
+        if expected != found {
+            return Err(Error::ReplayDiverged {
+                step,
+                expected,
+                found,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+// learn runs one trial, so callers choose between learn, learn_shaped and
+// learn_recorded.
+#[allow(clippy::too_many_arguments)]
+fn run_session<Rnr, R, L, O>(
+    world: &World,
+    probes: &[Probe],
+    (max_trials, max_steps): (usize, usize),
+    runner: &mut Rnr,
+    mut rng: &mut R,
+    options: &TrainingOptions,
+    mut learn: L,
+    mut observer: O,
+) -> Result<SessionResult, Error>
+where
+    Rnr: Runner,
+    R: Rng,
+    L: FnMut(&mut Rnr, &World, State, usize, &mut R) -> Trial,
+    O: FnMut(usize, &Rnr),
+{
+    let learning_world = options.reward_transform.transform_world(world);
+    let world = &learning_world;
+
+    let max_steps = runner
+        .default_max_steps()
+        .map_or(max_steps, |default_max_steps| {
+            default_max_steps.min(max_steps)
+        });
+
+    let mut total_steps = 0;
+    let mut result = SessionResult {
+        steps: None,
+        trials: 0,
+        total_reward: 0.0,
+        probe_outcomes: Vec::new(),
+    };
+
+    for _ in 0..max_trials {
+        let start_state = match options.start_states {
+            StartStates::Waiting => State::build_random(world, &mut rng),
+            StartStates::Any => State::random(world, &mut rng),
+        };
+
+        match start_state {
+            Err(state_error) => {
+                return Err(Error::BuildRandomState(state_error));
+            }
+
+            Ok(state) => {
+                let trial = learn(runner, world, state, max_steps, rng);
+
+                if let Some(num_steps) = trial.steps {
                     total_steps += num_steps;
                 } else {
                     total_steps += max_steps;
                 }
+
+                result.trials += 1;
+                result.total_reward += trial.reward;
             }
         }
 
+        observer(result.trials, runner);
+
         let probes_passed = probes
             .iter()
             .all(|probe| runner.solves(world, probe.state, probe.maximum_steps, &mut rng));
 
         if probes_passed {
-            return Ok(Some(total_steps));
+            result.steps = Some(total_steps);
+            break;
+        }
+
+        if options.stop_on_convergence && runner.has_converged() {
+            break;
+        }
+    }
+
+    // Fixed rng so that checking the probes does not disturb the caller's.
+    let mut probe_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+    result.probe_outcomes = probes
+        .iter()
+        .map(|probe| probe.check(world, runner, &mut probe_rng))
+        .collect();
+
+    Ok(result)
+}
+
+// Evaluates the greedy policy on the probes without learning, for a
+// learning curve from a custom training loop.  Each probe is one trial,
+// attempted for at most max_steps and its own maximum_steps.  The steps
+// are only set when every probe is solved.  The runner is borrowed
+// immutably, so its tables are the same afterwards.
+pub fn run_evaluation<Rnr: Runner>(
+    world: &World,
+    probes: &[Probe],
+    runner: &Rnr,
+    max_steps: usize,
+) -> SessionResult {
+    // Fixed rng, as for the probe outcomes of a training session.
+    let mut rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    let mut total_steps = 0;
+    let mut all_solved = true;
+    let mut total_reward = 0.0;
+
+    for probe in probes {
+        let attempt = runner.attempt(
+            world,
+            probe.state,
+            probe.maximum_steps.min(max_steps),
+            &mut rng,
+        );
+
+        total_steps += attempt.actions.len();
+        all_solved &= attempt.success;
+        total_reward += attempt.rewards(world).iter().sum::<f64>();
+    }
+
+    let mut probe_rng = Pcg64Mcg::new(0xcafe_f00d_d15e_a5e5);
+
+    SessionResult {
+        steps: if all_solved { Some(total_steps) } else { None },
+        trials: probes.len(),
+        total_reward,
+        probe_outcomes: probes
+            .iter()
+            .map(|probe| probe.check(world, runner, &mut probe_rng))
+            .collect(),
+    }
+}
+
+// Runs one training session per item, each with a fresh runner from the
+// builder.  Session seeds are drawn from the root seed the same way the
+// binary draws them, so a root seed gives the same sessions either way.
+pub struct TrainingRun<'a, B> {
+    world: &'a World,
+    probes: &'a [Probe],
+    max_trials: usize,
+    max_steps: usize,
+    builder: B,
+    options: TrainingOptions,
+    seed_generator: Pcg64Mcg,
+    remaining_sessions: usize,
+}
+
+impl<'a, B, Rnr> TrainingRun<'a, B>
+where
+    B: FnMut() -> Rnr,
+    Rnr: Runner,
+{
+    pub fn new(
+        world: &'a World,
+        probes: &'a [Probe],
+        (max_trials, max_steps): (usize, usize),
+        sessions: usize,
+        root_seed: u128,
+        builder: B,
+    ) -> TrainingRun<'a, B> {
+        TrainingRun {
+            world,
+            probes,
+            max_trials,
+            max_steps,
+            builder,
+            options: TrainingOptions::default(),
+            seed_generator: Pcg64Mcg::new(root_seed),
+            remaining_sessions: sessions,
+        }
+    }
+
+    pub fn with_options(self, options: TrainingOptions) -> TrainingRun<'a, B> {
+        TrainingRun { options, ..self }
+    }
+}
+
+impl<'a, B, Rnr> Iterator for TrainingRun<'a, B>
+where
+    B: FnMut() -> Rnr,
+    Rnr: Runner,
+{
+    type Item = Result<SessionResult, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_sessions == 0 {
+            return None;
+        }
+        self.remaining_sessions -= 1;
+
+        let seed: u128 = self.seed_generator.gen();
+        let mut rng = Pcg64Mcg::new(seed);
+        let mut runner = (self.builder)();
+
+        Some(run_training_session_with_options(
+            self.world,
+            self.probes,
+            (self.max_trials, self.max_steps),
+            &mut runner,
+            &mut rng,
+            &self.options,
+            |_, _| {},
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_sessions, Some(self.remaining_sessions))
+    }
+}
+
+// Trains a fresh solver per seed and collects the steps of the sessions
+// that passed their probes.  Failed sessions are left out, so
+// seeds.len() - get_count() of them failed.
+pub fn sweep_seeds<F, Rnr>(
+    world: &World,
+    probes: &[Probe],
+    (max_trials, max_steps): (usize, usize),
+    seeds: &[u64],
+    solver_factory: F,
+) -> Result<MeasureDistribution, Error>
+where
+    F: Fn() -> Rnr,
+    Rnr: Runner,
+{
+    let mut distribution = MeasureDistribution::default();
+
+    for seed in seeds {
+        let mut rng = Pcg64Mcg::seed_from_u64(*seed);
+        let mut runner = solver_factory();
+
+        let result =
+            run_training_session(world, probes, max_trials, max_steps, &mut runner, &mut rng)?;
+
+        if let Some(steps) = result.steps {
+            distribution.add_value(steps as f64);
+        }
+    }
+
+    Ok(distribution)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolicyAgreement {
+    pub num_states: usize,
+    pub num_agreed: usize,
+    // States where either solver had no greedy action.  These count as
+    // disagreements.
+    pub num_unseen: usize,
+}
+
+impl PolicyAgreement {
+    // Fraction of the states where both solvers chose the same action.
+    // With no states to compare the policies trivially agree, so 1.0.
+    pub fn agreement(&self) -> f64 {
+        if self.num_states == 0 {
+            1.0
+        } else {
+            (self.num_agreed as f64) / (self.num_states as f64)
+        }
+    }
+}
+
+// Compares the greedy actions of two solvers over every unsolved state.
+pub fn policy_agreement<A, B>(a: &A, b: &B, world: &World) -> PolicyAgreement
+where
+    A: Runner,
+    B: Runner,
+{
+    let mut result = PolicyAgreement {
+        num_states: 0,
+        num_agreed: 0,
+        num_unseen: 0,
+    };
+
+    let state_indexer = StateIndexer::new(world);
+
+    for state in state_indexer.iter_states(world) {
+        if state.at_destination() {
+            continue;
+        }
+
+        result.num_states += 1;
+
+        match (
+            a.greedy_action(world, &state),
+            b.greedy_action(world, &state),
+        ) {
+            (Some(a_action), Some(b_action)) => {
+                if a_action == b_action {
+                    result.num_agreed += 1;
+                }
+            }
+            _ => result.num_unseen += 1,
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test_runner {
+    use super::*;
+
+    use crate::world::Costs;
+
+    // Repeats a fixed cycle of actions, never learning anything.
+    struct Scripted {
+        actions: Vec<Actions>,
+        max_steps: Option<usize>,
+    }
+
+    impl Runner for Scripted {
+        fn learn<R: Rng>(
+            &mut self,
+            world: &World,
+            mut state: State,
+            max_steps: usize,
+            _rng: &mut R,
+        ) -> Trial {
+            let mut reward = 0.0;
+
+            for step in 0..max_steps {
+                if state.at_destination() {
+                    return Trial::new(Some(step), reward);
+                }
+
+                let action = self.actions[step % self.actions.len()];
+                let (action_reward, next_state) = state.apply_action(world, action);
+                reward += action_reward;
+                state = next_state;
+            }
+
+            Trial::new(None, reward)
+        }
+
+        fn attempt<R: Rng>(
+            &self,
+            world: &World,
+            mut state: State,
+            max_steps: usize,
+            _rng: &mut R,
+        ) -> Attempt {
+            let mut attempt = Attempt::new(state, max_steps);
+
+            for step in 0..max_steps {
+                if state.at_destination() {
+                    break;
+                }
+
+                let action = self.actions[step % self.actions.len()];
+                attempt.step(action);
+                let (_, next_state) = state.apply_action(world, action);
+                state = next_state;
+            }
+
+            if state.at_destination() {
+                attempt.succeeded();
+            }
+
+            attempt
+        }
+
+        fn solves<R: Rng>(
+            &self,
+            world: &World,
+            mut state: State,
+            max_steps: usize,
+            _rng: &mut R,
+        ) -> bool {
+            for step in 0..max_steps {
+                if state.at_destination() {
+                    return true;
+                }
+
+                let action = self.actions[step % self.actions.len()];
+                let (_, next_state) = state.apply_action(world, action);
+                state = next_state;
+            }
+
+            state.at_destination()
+        }
+
+        fn default_max_steps(&self) -> Option<usize> {
+            self.max_steps
+        }
+    }
+
+    #[test]
+    fn illegal_pickups_lower_average_reward() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        let mut moving = Scripted {
+            actions: vec![Actions::North, Actions::South],
+            max_steps: None,
+        };
+        let mut picking = Scripted {
+            actions: vec![Actions::North, Actions::PickUp],
+            max_steps: None,
+        };
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let moving_result =
+            run_training_session(&world, &probes, 5, 10, &mut moving, &mut rng).unwrap();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let picking_result =
+            run_training_session(&world, &probes, 5, 10, &mut picking, &mut rng).unwrap();
+
+        assert_eq!(moving_result.steps, None);
+        assert_eq!(moving_result.trials, 5);
+        assert_eq!(picking_result.trials, 5);
+
+        assert!(approx_eq!(
+            f64,
+            moving_result.average_reward(),
+            -10.0,
+            ulps = 2
+        ));
+        assert!(picking_result.average_reward() < moving_result.average_reward());
+    }
+
+    #[test]
+    fn failure_report_classifies() {
+        // Never has an action to take.
+        struct Stalled;
+
+        impl Runner for Stalled {
+            fn learn<R: Rng>(&mut self, _: &World, _: State, _: usize, _: &mut R) -> Trial {
+                Trial::new(None, 0.0)
+            }
+
+            fn attempt<R: Rng>(
+                &self,
+                _: &World,
+                state: State,
+                max_steps: usize,
+                _: &mut R,
+            ) -> Attempt {
+                Attempt::new(state, max_steps)
+            }
+
+            fn solves<R: Rng>(&self, _: &World, state: State, _: usize, _: &mut R) -> bool {
+                state.at_destination()
+            }
+        }
+
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        // 4 taxi positions * 3 passenger states * 2 destinations, less the
+        // 8 with the passenger already at the destination.
+        let stalled = Stalled.failure_report(&world, 10);
+        assert_eq!(stalled.len(), 16);
+        assert!(stalled
+            .iter()
+            .all(|(_, reason)| *reason == FailureReason::GivesUp));
+
+        let bouncing = Scripted {
+            actions: vec![Actions::North, Actions::South],
+            max_steps: None,
+        };
+        let bounced = bouncing.failure_report(&world, 10);
+        assert_eq!(bounced.len(), 16);
+        assert!(bounced
+            .iter()
+            .all(|(_, reason)| *reason == FailureReason::Loops));
+
+        // Only a passenger in the taxi headed for G gets there, moves into
+        // walls just stay put.  From R it takes 3 steps.
+        let direct = Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+        let in_taxi = State::build(&world, (0, 0), None, 'G').unwrap();
+
+        let report = direct.failure_report(&world, 10);
+        assert_eq!(report.len(), 12);
+        assert!(report
+            .iter()
+            .all(|(state, _)| state.get_destination() == 'R' || !state.passenger_in_taxi()));
+
+        let report = direct.failure_report(&world, 2);
+        assert!(report.contains(&(in_taxi, FailureReason::TimedOut)));
+    }
+
+    // Trains a fresh solver and a reset one on the same experience, they
+    // must learn and act identically.
+    fn assert_reset_matches_fresh<B, Rnr>(world: &World, build: B)
+    where
+        B: Fn() -> Rnr,
+        Rnr: Runner,
+    {
+        let train = |solver: &mut Rnr, seed: u64| -> Vec<Trial> {
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+            (0..30)
+                .map(|_| {
+                    let state = State::build_random(world, &mut rng).unwrap();
+                    solver.learn(world, state, 30, &mut rng)
+                })
+                .collect()
+        };
+
+        let mut fresh = build();
+        let mut reused = build();
+        train(&mut reused, 1);
+        reused.reset();
+
+        assert_eq!(train(&mut fresh, 2), train(&mut reused, 2));
+
+        let state_indexer = StateIndexer::new(world);
+        for state in state_indexer.iter_states(world) {
+            let mut fresh_rng = Pcg64Mcg::seed_from_u64(3);
+            let mut reused_rng = Pcg64Mcg::seed_from_u64(3);
+
+            assert_eq!(
+                fresh.attempt(world, state, 20, &mut fresh_rng).actions,
+                reused.attempt(world, state, 20, &mut reused_rng).actions
+            );
         }
     }
 
-    Ok(None)
+    #[test]
+    fn reset_matches_fresh_solvers() {
+        use crate::doormax::DoorMax;
+        use crate::factoredrmax::FactoredRMax;
+        use crate::maxq::MaxQ;
+        use crate::qlearner::{InitStrategy, QLearner, QLearnerReplay, SparseQLearner};
+        use crate::rmax::RMax;
+
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        assert_reset_matches_fresh(&world, || QLearner::new(&world, 0.1, 0.9, 0.1));
+        assert_reset_matches_fresh(&world, || {
+            QLearner::with_init(
+                &world,
+                0.1,
+                0.9,
+                0.1,
+                InitStrategy::Random {
+                    low: -1.0,
+                    high: 1.0,
+                    seed: 5,
+                },
+            )
+        });
+        assert_reset_matches_fresh(&world, || {
+            QLearnerReplay::new(&world, 0.1, 0.9, 0.1, 100, 4)
+        });
+        assert_reset_matches_fresh(&world, || SparseQLearner::new(&world, 0.1, 0.9, 0.1));
+        assert_reset_matches_fresh(&world, || RMax::new(&world, 0.9, 1.0, 1.0e-6));
+        assert_reset_matches_fresh(&world, || FactoredRMax::new(&world, 0.9, 1.0, 1.0e-6));
+        assert_reset_matches_fresh(&world, || MaxQ::new(&world, 0.1, 0.9, 0.1, false));
+        assert_reset_matches_fresh(&world, || DoorMax::new(&world, 0.9, true, 1.0, 1.0e-6));
+        assert_reset_matches_fresh(&world, || DoorMax::new(&world, 0.9, false, 1.0, 1.0e-6));
+    }
+
+    #[test]
+    fn policy_agreement_counts_unseen() {
+        use crate::qlearner::QLearner;
+
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        for _ in 0..20 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            qlearner.learn(&world, state, 50, &mut rng);
+        }
+
+        // 4 taxi positions * 3 passenger states * 2 destinations, less the
+        // 8 with the passenger already at the destination.
+        let same = policy_agreement(&qlearner, &qlearner.clone(), &world);
+        assert_eq!(same.num_states, 16);
+        assert_eq!(same.num_agreed, 16);
+        assert_eq!(same.num_unseen, 0);
+        assert!(approx_eq!(f64, same.agreement(), 1.0, ulps = 2));
+
+        let untrained = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let drifted = policy_agreement(&qlearner, &untrained, &world);
+        assert_eq!(drifted.num_states, 16);
+        assert!(drifted.num_agreed < 16);
+
+        // The scripted runner has no greedy action anywhere.
+        let scripted = Scripted {
+            actions: vec![Actions::North],
+            max_steps: None,
+        };
+        let unseen = policy_agreement(&qlearner, &scripted, &world);
+        assert_eq!(unseen.num_unseen, 16);
+        assert_eq!(unseen.num_agreed, 0);
+        assert!(approx_eq!(f64, unseen.agreement(), 0.0, ulps = 2));
+    }
+
+    #[test]
+    fn recorded_sessions_replay() {
+        use crate::qlearner::QLearner;
+        use crate::rmax::RMax;
+
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+        let probes = vec![Probe::new(
+            State::build(&world, (1, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+        let options = TrainingOptions::default();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let (result, recorded) = run_training_session_recorded(
+            &world,
+            &probes,
+            (20, 30),
+            &mut qlearner,
+            &mut rng,
+            &options,
+        )
+        .unwrap();
+        assert!(!recorded.is_empty());
+
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let replayed = replay_recorded(
+            &world,
+            &probes,
+            (20, 30),
+            &mut qlearner,
+            &mut rng,
+            &options,
+            &recorded,
+        )
+        .unwrap();
+        assert_eq!(replayed, result);
+
+        let mut rng = Pcg64Mcg::seed_from_u64(8);
+        let mut qlearner = QLearner::new(&world, 0.1, 0.9, 0.1);
+        let diverged = replay_recorded(
+            &world,
+            &probes,
+            (20, 30),
+            &mut qlearner,
+            &mut rng,
+            &options,
+            &recorded,
+        );
+        assert_matches!(diverged, Err(Error::ReplayDiverged { .. }));
+
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let mut rmax = RMax::new(&world, 0.9, 1.0, 1.0e-6);
+        let (_, recorded) =
+            run_training_session_recorded(&world, &probes, (5, 30), &mut rmax, &mut rng, &options)
+                .unwrap();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let mut rmax = RMax::new(&world, 0.9, 1.0, 1.0e-6);
+        assert!(replay_recorded(
+            &world,
+            &probes,
+            (5, 30),
+            &mut rmax,
+            &mut rng,
+            &options,
+            &recorded
+        )
+        .is_ok());
+
+        // Without learn_recorded nothing is recorded.
+        let mut scripted = Scripted {
+            actions: vec![Actions::West, Actions::PickUp],
+            max_steps: None,
+        };
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        let (_, recorded) = run_training_session_recorded(
+            &world,
+            &probes,
+            (5, 30),
+            &mut scripted,
+            &mut rng,
+            &options,
+        )
+        .unwrap();
+        assert!(recorded.is_empty());
+    }
+
+    #[test]
+    fn shaping_keeps_policy_and_speeds_learning() {
+        use crate::qlearner::QLearner;
+
+        let source = "\
+                      ┌─┬───┐\n\
+                      │R│. G│\n\
+                      │ │   │\n\
+                      │. . .│\n\
+                      │     │\n\
+                      │Y B .│\n\
+                      └─────┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes: Vec<Probe> = [('Y', 'R'), ('Y', 'G'), ('R', 'B'), ('G', 'R'), ('B', 'G')]
+            .iter()
+            .map(|(passenger, destination)| {
+                let state = State::build(&world, (1, 1), Some(*passenger), *destination).unwrap();
+                Probe::new(state, 20)
+            })
+            .collect();
+
+        // Minus the Manhattan distance to whoever the taxi drives to next.
+        let potential = |state: &State| {
+            let taxi = state.get_taxi();
+            let target = if state.passenger_in_taxi() {
+                world.get_fixed_position(state.get_destination()).unwrap()
+            } else {
+                state.get_passenger_position(&world).unwrap()
+            };
+
+            -f64::from((taxi.x - target.x).abs() + (taxi.y - target.y).abs())
+        };
+        let shaping = PotentialShaping::new(0.9, potential);
+
+        let mut plain_steps = 0;
+        let mut shaped_steps = 0;
+
+        for seed in 0..5 {
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+            let mut plain = QLearner::new(&world, 0.1, 0.9, 0.1);
+            let plain_result =
+                run_training_session(&world, &probes, 5000, 100, &mut plain, &mut rng).unwrap();
+
+            let mut rng = Pcg64Mcg::seed_from_u64(seed);
+            let mut shaped = QLearner::new(&world, 0.1, 0.9, 0.1);
+            let shaped_result = run_training_session_with_shaping(
+                &world,
+                &probes,
+                (5000, 100),
+                &mut shaped,
+                &mut rng,
+                &TrainingOptions::default(),
+                &shaping,
+            )
+            .unwrap();
+
+            // Shaping is by 0.9 * phi, so the whole costs show it was left
+            // out of the reported rewards.
+            assert!(approx_eq!(f64, shaped_result.total_reward.fract(), 0.0));
+
+            // The same optimal route from every probe.
+            for probe in &probes {
+                let plain_attempt = plain.attempt(&world, probe.state, 20, &mut rng);
+                let shaped_attempt = shaped.attempt(&world, probe.state, 20, &mut rng);
+                assert_eq!(plain_attempt.actions.len(), shaped_attempt.actions.len());
+            }
+
+            plain_steps += plain_result.steps.expect("plain QLearner should converge");
+            shaped_steps += shaped_result
+                .steps
+                .expect("shaped QLearner should converge");
+        }
+
+        assert!(shaped_steps < plain_steps);
+    }
+
+    #[test]
+    fn probe_validation() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let waiting = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+        assert_eq!(Probe::new(waiting, 10).validate(&world), Ok(()));
+
+        let delivered = State::build(&world, (0, 0), Some('G'), 'G').unwrap();
+        assert!(Probe::new(delivered, 10).validate(&world).is_err());
+
+        assert!(Probe::new(waiting, 0).validate(&world).is_err());
+
+        let overexpected = Probe {
+            expected_steps: Some(11),
+            ..Probe::new(waiting, 10)
+        };
+        assert!(overexpected.validate(&world).is_err());
+
+        for weight in &[-1.0, f64::NAN, f64::INFINITY] {
+            let weighted = Probe {
+                weight: *weight,
+                ..Probe::new(waiting, 10)
+            };
+            assert!(weighted.validate(&world).is_err());
+        }
+
+        // Too few steps for even the optimal route.
+        assert_eq!(world.optimal_steps(&waiting), Some(4));
+        assert!(Probe::new(waiting, 3).validate(&world).is_err());
+
+        // A state from a larger world is off this one.
+        let larger = World::build_from_str(
+            "\
+             ┌─────┐\n\
+             │R . G│\n\
+             └─────┘\n\
+             ",
+            Costs::default(),
+        )
+        .unwrap();
+        let far_taxi = State::build(&larger, (2, 0), Some('R'), 'G').unwrap();
+        assert!(Probe::new(far_taxi, 10).validate(&larger).is_ok());
+        assert!(Probe::new(far_taxi, 10).validate(&world).is_err());
+
+        // The taxi starts walled off, so it can never reach the passenger.
+        let stranded = World::build_from_str(
+            "\
+             ┌───┬─┐\n\
+             │R G│.│\n\
+             └───┴─┘\n\
+             ",
+            Costs::default(),
+        )
+        .unwrap();
+        let unreachable = State::build(&stranded, (2, 0), Some('R'), 'G').unwrap();
+        assert_eq!(stranded.optimal_steps(&unreachable), None);
+        assert!(Probe::new(unreachable, 10).validate(&stranded).is_err());
+    }
+
+    #[test]
+    fn default_max_steps_caps_trials() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        // Moving costs -1 a step and never solves, so the average reward
+        // is minus the number of steps each trial ran.
+        let average_reward = |default_max_steps| {
+            let mut moving = Scripted {
+                actions: vec![Actions::North, Actions::South],
+                max_steps: default_max_steps,
+            };
+
+            let mut rng = Pcg64Mcg::seed_from_u64(0);
+            run_training_session(&world, &probes, 5, 10, &mut moving, &mut rng)
+                .unwrap()
+                .average_reward()
+        };
+
+        assert!(approx_eq!(f64, average_reward(None), -10.0, ulps = 2));
+        assert!(approx_eq!(f64, average_reward(Some(4)), -4.0, ulps = 2));
+        assert!(approx_eq!(f64, average_reward(Some(20)), -10.0, ulps = 2));
+    }
+
+    #[test]
+    fn normalize_scales_delivery_rewards() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let mut world = World::build_from_str(source, Costs::default()).unwrap();
+        world.set_delivery_reward('G', Some(24.0)).unwrap();
+
+        let normalized = RewardTransform::Normalize.transform_world(&world);
+        assert_eq!(normalized.delivery_reward('G'), 1.0);
+        assert_eq!(normalized.costs.empty_dropoff, -0.5);
+        assert_eq!(normalized.max_reward(), 1.0);
+    }
+
+    #[test]
+    fn clipped_pickups_cost_no_more_than_moves() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let clipped = RewardTransform::Clip.transform_costs(&world.costs);
+        assert_eq!(clipped.movement, -1.0);
+        assert_eq!(clipped.miss_pickup, -1.0);
+        assert_eq!(clipped.empty_dropoff, -1.0);
+        assert_eq!(clipped.delivery_reward, 0.0);
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        let mut picking = Scripted {
+            actions: vec![Actions::North, Actions::PickUp],
+            max_steps: None,
+        };
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let picking_result = run_training_session_with_options(
+            &world,
+            &probes,
+            (5, 10),
+            &mut picking,
+            &mut rng,
+            &TrainingOptions {
+                reward_transform: RewardTransform::Clip,
+                ..TrainingOptions::default()
+            },
+            |_, _| {},
+        )
+        .unwrap();
+
+        // Moving back and forth costs -10 per trial, as in
+        // illegal_pickups_lower_average_reward.
+        assert!(picking_result.average_reward() >= -10.0);
+    }
+
+    #[test]
+    fn normalized_rewards_ignore_cost_scale() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+        let scaled_world =
+            World::build_from_str(source, Costs::new(-10.0, -100.0, -110.0, -120.0)).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        let average_reward = |world: &World, reward_transform| {
+            let mut picking = Scripted {
+                actions: vec![Actions::North, Actions::PickUp],
+                max_steps: None,
+            };
+            let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+            run_training_session_with_options(
+                world,
+                &probes,
+                (5, 10),
+                &mut picking,
+                &mut rng,
+                &TrainingOptions {
+                    reward_transform,
+                    ..TrainingOptions::default()
+                },
+                |_, _| {},
+            )
+            .unwrap()
+            .average_reward()
+        };
+
+        assert!(!approx_eq!(
+            f64,
+            average_reward(&world, RewardTransform::Identity),
+            average_reward(&scaled_world, RewardTransform::Identity),
+            ulps = 2
+        ));
+        assert!(approx_eq!(
+            f64,
+            average_reward(&world, RewardTransform::Normalize),
+            average_reward(&scaled_world, RewardTransform::Normalize),
+            ulps = 2
+        ));
+    }
+
+    #[test]
+    fn evaluate_reports_fraction_solved() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let dropping = Scripted {
+            actions: vec![Actions::DropOff],
+            max_steps: None,
+        };
+
+        let states = vec![
+            State::build(&world, (1, 1), None, 'G').unwrap(),
+            State::build(&world, (0, 0), None, 'G').unwrap(),
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            State::build(&world, (1, 1), Some('R'), 'G').unwrap(),
+        ];
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        assert!(approx_eq!(
+            f64,
+            dropping.evaluate(&world, &states, 5, &mut rng),
+            0.25,
+            ulps = 2
+        ));
+        assert!(approx_eq!(
+            f64,
+            dropping.evaluate(&world, &[], 5, &mut rng),
+            1.0,
+            ulps = 2
+        ));
+    }
+
+    #[test]
+    fn observer_sees_every_trial() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        let mut moving = Scripted {
+            actions: vec![Actions::North, Actions::South],
+            max_steps: None,
+        };
+
+        let mut observed = Vec::new();
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        run_training_session_with_observer(
+            &world,
+            &probes,
+            3,
+            10,
+            &mut moving,
+            &mut rng,
+            |trial, _| observed.push(trial),
+        )
+        .unwrap();
+
+        assert_eq!(observed, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn attempt_states_match_steps() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let mut state = State::build(&world, (1, 0), Some('R'), 'G').unwrap();
+        let actions = [
+            Actions::West,
+            Actions::PickUp,
+            Actions::East,
+            Actions::South,
+            Actions::DropOff,
+        ];
+
+        let mut attempt = Attempt::new(state, actions.len());
+        let mut recorded = vec![state];
+
+        for action in &actions {
+            attempt.step(*action);
+            let (_, next_state) = state.apply_action(&world, *action);
+            recorded.push(next_state);
+            state = next_state;
+        }
+
+        assert!(state.at_destination());
+        assert_eq!(attempt.actions(), actions.to_vec());
+        assert_eq!(attempt.states(&world), recorded);
+    }
+
+    #[test]
+    fn evaluation_leaves_tables_unchanged() {
+        use crate::checkpoint::Persist;
+        use crate::qlearner::QLearner;
+
+        let source = "\
+                      ┌─────┐\n\
+                      │R . .│\n\
+                      │     │\n\
+                      │. . G│\n\
+                      └─────┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+        let probes = vec![
+            Probe::new(State::build(&world, (1, 1), Some('R'), 'G').unwrap(), 7),
+            Probe::new(State::build(&world, (0, 1), Some('G'), 'R').unwrap(), 7),
+        ];
+
+        let mut qlearner = QLearner::new(&world, 1.0, 0.9, 0.1);
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let mut curve = Vec::new();
+
+        for trial in 0..300 {
+            let state = State::build_random(&world, &mut rng).unwrap();
+            qlearner.learn(&world, state, 50, &mut rng);
+
+            if trial % 50 == 49 {
+                let before = qlearner.to_json().unwrap();
+                let evaluation = run_evaluation(&world, &probes, &qlearner, 20);
+                assert_eq!(qlearner.to_json().unwrap(), before);
+
+                assert_eq!(evaluation.trials, probes.len());
+                assert_eq!(evaluation.probe_outcomes.len(), probes.len());
+                curve.push(evaluation.steps);
+            }
+        }
+
+        // Each probe needs 7 steps once learned.
+        assert_eq!(curve.last(), Some(&Some(14)));
+    }
+
+    #[test]
+    fn discounted_return_by_hand() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(source, costs).unwrap();
+
+        let state = State::build(&world, (1, 0), Some('R'), 'G').unwrap();
+        let mut attempt = Attempt::new(state, 5);
+        assert_eq!(attempt.discounted_return(&world, 0.5), 0.0);
+
+        for action in &[
+            Actions::West,
+            Actions::PickUp,
+            Actions::East,
+            Actions::South,
+            Actions::DropOff,
+        ] {
+            attempt.step(*action);
+        }
+
+        assert_eq!(attempt.rewards(&world), vec![-1.0, 0.0, -1.0, -1.0, 20.0]);
+
+        // -1 + 0.5 * 0 + 0.25 * -1 + 0.125 * -1 + 0.0625 * 20
+        assert_eq!(attempt.discounted_return(&world, 0.5), -0.125);
+        assert_eq!(attempt.discounted_return(&world, 1.0), 17.0);
+    }
+
+    #[test]
+    fn observer_sees_every_attempt_step() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let costs = Costs {
+            delivery_reward: 20.0,
+            ..Costs::default()
+        };
+        let world = World::build_from_str(source, costs).unwrap();
+
+        let direct = Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+        let state = State::build(&world, (0, 0), None, 'G').unwrap();
+
+        let mut steps = Vec::new();
+        let attempt = direct.attempt_with_observer(&world, state, 10, |state, action, reward| {
+            steps.push((*state, action, reward))
+        });
+
+        assert!(attempt.success);
+        assert_eq!(steps.len(), attempt.actions.len());
+        assert_eq!(
+            steps,
+            vec![
+                (
+                    State::build(&world, (1, 0), None, 'G').unwrap(),
+                    Actions::East,
+                    -1.0
+                ),
+                (
+                    State::build(&world, (1, 1), None, 'G').unwrap(),
+                    Actions::South,
+                    -1.0
+                ),
+                (
+                    State::build(&world, (1, 1), Some('G'), 'G').unwrap(),
+                    Actions::DropOff,
+                    20.0
+                ),
+            ]
+        );
+
+        // Cut short, the observer only sees the steps taken.
+        let mut num_calls = 0;
+        let attempt = direct.attempt_with_observer(&world, state, 2, |_, _, _| num_calls += 1);
+        assert!(!attempt.success);
+        assert_eq!(num_calls, 2);
+    }
+
+    #[test]
+    fn observer_sees_greedy_steps_as_taken() {
+        // Takes its actions in order through greedy_action, counting each
+        // choice, and has no attempt to replay.
+        struct Stepwise {
+            actions: Vec<Actions>,
+            num_chosen: std::cell::Cell<usize>,
+        }
+
+        impl Runner for Stepwise {
+            fn learn<R: Rng>(&mut self, _: &World, _: State, _: usize, _: &mut R) -> Trial {
+                Trial::new(None, 0.0)
+            }
+
+            fn attempt<R: Rng>(&self, _: &World, _: State, _: usize, _: &mut R) -> Attempt {
+                unreachable!("observed attempts step through greedy_action")
+            }
+
+            fn solves<R: Rng>(&self, _: &World, state: State, _: usize, _: &mut R) -> bool {
+                state.at_destination()
+            }
+
+            fn greedy_action(&self, _: &World, _: &State) -> Option<Actions> {
+                let num_chosen = self.num_chosen.get();
+                self.num_chosen.set(num_chosen + 1);
+                self.actions.get(num_chosen).cloned()
+            }
+        }
+
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let stepwise = Stepwise {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            num_chosen: std::cell::Cell::new(0),
+        };
+        let state = State::build(&world, (0, 0), None, 'G').unwrap();
+
+        // Each step is observed before the next one is chosen.
+        let mut observed = Vec::new();
+        let attempt = stepwise.attempt_with_observer(&world, state, 10, |_, action, _| {
+            observed.push((action, stepwise.num_chosen.get()))
+        });
+
+        assert!(attempt.success);
+        assert_eq!(
+            attempt.actions,
+            vec![Actions::East, Actions::South, Actions::DropOff]
+        );
+        assert_eq!(
+            observed,
+            vec![
+                (Actions::East, 1),
+                (Actions::South, 2),
+                (Actions::DropOff, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn solution_trajectory_ends_delivered() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let direct = Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+        let state = State::build(&world, (0, 0), None, 'G').unwrap();
+
+        let trajectory = direct.solution_trajectory(&world, state, 10).unwrap();
+        assert_eq!(
+            trajectory,
+            vec![
+                (state, Actions::East),
+                (
+                    State::build(&world, (1, 0), None, 'G').unwrap(),
+                    Actions::South
+                ),
+                (
+                    State::build(&world, (1, 1), None, 'G').unwrap(),
+                    Actions::DropOff
+                ),
+            ]
+        );
+
+        let (last_state, last_action) = *trajectory.last().unwrap();
+        assert!(last_state
+            .apply_action(&world, last_action)
+            .1
+            .at_destination());
+
+        // Too few steps to deliver.
+        assert_eq!(direct.solution_trajectory(&world, state, 2), None);
+
+        let lost = Scripted {
+            actions: vec![Actions::North],
+            max_steps: None,
+        };
+        assert_eq!(lost.solution_trajectory(&world, state, 10), None);
+    }
+
+    #[test]
+    fn probe_table_lists_every_probe() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![
+            Probe {
+                expected_steps: Some(3),
+                ..Probe::new(State::build(&world, (0, 0), None, 'G').unwrap(), 10)
+            },
+            Probe::new(State::build(&world, (1, 0), Some('R'), 'G').unwrap(), 4),
+        ];
+
+        let direct = Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let table = probe_table(&world, &probes, &direct, &mut rng);
+
+        let expected = "\
+                        Probe  Start                     Solved  Steps  Expected\n\
+                        0      taxi (0,0), in taxi -> G  yes     3/10   3\n\
+                        1      taxi (1,0), R -> G        no      4/4    -\n\
+                        ";
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn probe_outcomes_compare_expected_steps() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let state = State::build(&world, (0, 0), None, 'G').unwrap();
+        let probe = |expected_steps| Probe {
+            expected_steps,
+            ..Probe::new(state, 10)
+        };
+
+        // East, South, DropOff is optimal, the extra Wait makes a detour.
+        let mut direct = Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+        let detour = Scripted {
+            actions: vec![
+                Actions::Wait,
+                Actions::East,
+                Actions::South,
+                Actions::DropOff,
+            ],
+            max_steps: None,
+        };
+        let lost = Scripted {
+            actions: vec![Actions::North],
+            max_steps: None,
+        };
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+
+        assert_eq!(
+            probe(Some(3)).check(&world, &direct, &mut rng),
+            ProbeOutcome::Optimal
+        );
+        assert_eq!(
+            probe(Some(3)).check(&world, &detour, &mut rng),
+            ProbeOutcome::Feasible {
+                steps: 4,
+                expected: 3
+            }
+        );
+        assert_eq!(
+            probe(Some(5)).check(&world, &direct, &mut rng),
+            ProbeOutcome::BeatExpected {
+                steps: 3,
+                expected: 5
+            }
+        );
+        assert_eq!(
+            probe(None).check(&world, &direct, &mut rng),
+            ProbeOutcome::Solved(3)
+        );
+        assert_eq!(
+            probe(Some(3)).check(&world, &lost, &mut rng),
+            ProbeOutcome::Failed
+        );
+
+        let probes = vec![probe(Some(3)), probe(Some(5))];
+        let result = run_training_session(&world, &probes, 1, 10, &mut direct, &mut rng).unwrap();
+
+        assert_eq!(
+            result.probe_outcomes,
+            vec![
+                ProbeOutcome::Optimal,
+                ProbeOutcome::BeatExpected {
+                    steps: 3,
+                    expected: 5
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn training_run_matches_seeded_sessions() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), Some('R'), 'G').unwrap(),
+            10,
+        )];
+
+        let actions = vec![Actions::North, Actions::PickUp];
+        let builder = || Scripted {
+            actions: actions.clone(),
+            max_steps: None,
+        };
+
+        let results: Vec<SessionResult> =
+            TrainingRun::new(&world, &probes, (3, 10), 4, 17, builder)
+                .map(|result| result.unwrap())
+                .collect();
+        assert_eq!(results.len(), 4);
+
+        let mut seed_generator = Pcg64Mcg::new(17);
+        for result in &results {
+            let seed: u128 = seed_generator.gen();
+            let mut rng = Pcg64Mcg::new(seed);
+            let mut runner = builder();
+
+            let expected =
+                run_training_session(&world, &probes, 3, 10, &mut runner, &mut rng).unwrap();
+            assert_eq!(*result, expected);
+        }
+
+        // Lazy, so stopping early runs no further sessions.
+        let mut built = 0;
+        let first = TrainingRun::new(&world, &probes, (3, 10), 100, 17, || {
+            built += 1;
+            builder()
+        })
+        .take_while(|result| result.is_err())
+        .count();
+
+        assert_eq!(first, 0);
+        assert_eq!(built, 1);
+    }
+
+    #[test]
+    fn sweep_seeds_collects_solved_sessions() {
+        let source = "\
+                      ┌───┐\n\
+                      │R .│\n\
+                      │   │\n\
+                      │. G│\n\
+                      └───┘\n\
+                      ";
+        let world = World::build_from_str(source, Costs::default()).unwrap();
+
+        let probes = vec![Probe::new(
+            State::build(&world, (0, 0), None, 'G').unwrap(),
+            10,
+        )];
+
+        let seeds = [1, 2, 3, 4, 5];
+
+        let direct = || Scripted {
+            actions: vec![Actions::East, Actions::South, Actions::DropOff],
+            max_steps: None,
+        };
+        let distribution = sweep_seeds(&world, &probes, (3, 10), &seeds, direct).unwrap();
+
+        let mut expected = MeasureDistribution::default();
+        for seed in &seeds {
+            let mut rng = Pcg64Mcg::seed_from_u64(*seed);
+            let result =
+                run_training_session(&world, &probes, 3, 10, &mut direct(), &mut rng).unwrap();
+            expected.add_value(result.steps.unwrap() as f64);
+        }
+
+        assert_eq!(distribution.get_count(), 5.0);
+        assert_eq!(distribution.get_distribution(), expected.get_distribution());
+
+        let lost = || Scripted {
+            actions: vec![Actions::North],
+            max_steps: None,
+        };
+        let distribution = sweep_seeds(&world, &probes, (3, 10), &seeds, lost).unwrap();
+        assert_eq!(distribution.get_count(), 0.0);
+    }
 }
@@ -1,15 +1,75 @@
 use std::fmt;
 
+use rand::seq::SliceRandom;
 use rand::Rng;
 
 use crate::actions::Actions;
 use crate::position::Position;
+use crate::state_indexer::StateIndexer;
 use crate::world::{ActionAffect, World};
 
+mod multi_taxi;
+mod render;
+pub use self::multi_taxi::{MultiTaxiPassenger, MultiTaxiState};
+pub use self::render::RenderError;
+
+// The glyphs State::display_with_style draws into the world's cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayStyle {
+    pub taxi: char,
+    pub taxi_with_passenger: char,
+    pub passenger: char,
+    pub taxi_on_passenger: char,
+    pub destination: char,
+    pub taxi_on_destination: char,
+    pub delivered: char,
+    pub empty: char,
+}
+
+impl Default for DisplayStyle {
+    fn default() -> Self {
+        DisplayStyle {
+            taxi: 't',
+            taxi_with_passenger: 'T',
+            passenger: 'p',
+            taxi_on_passenger: 'p',
+            destination: 'd',
+            taxi_on_destination: 'd',
+            delivered: 'D',
+            empty: '.',
+        }
+    }
+}
+
+impl DisplayStyle {
+    // The default hides the taxi when it stands on the waiting passenger or
+    // the destination.  This style keeps it visible as 'P' and 'X'.
+    pub fn distinct_overlaps() -> Self {
+        DisplayStyle {
+            taxi_on_passenger: 'P',
+            taxi_on_destination: 'X',
+            ..DisplayStyle::default()
+        }
+    }
+}
+
+// Where the passenger is, from State::passenger_state.  Only fixed
+// positions have ids, so a passenger waiting at any other cell is WaitingAt.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PassengerState {
+    Waiting(char),
+    WaitingAt(Position),
+    InTaxi,
+    Delivered,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct State {
     taxi: Position,
     passenger: Option<char>,
+    // A passenger waiting at a cell that is not a fixed position, passenger
+    // is None in that case.
+    passenger_cell: Option<Position>,
     destination: char,
 }
 
@@ -29,6 +89,11 @@ pub enum Error {
         world: String,
     },
 
+    InvalidPassengerPosition {
+        passenger_pos: (i32, i32),
+        world_dims: (i32, i32),
+    },
+
     TooFewFixedPositions {
         num_fixed_positions: usize,
         world: String,
@@ -38,6 +103,17 @@ pub enum Error {
         destination_offset: usize,
         world: String,
     },
+
+    NoTaxis,
+
+    SharedTaxiCell {
+        taxi_pos: (i32, i32),
+    },
+
+    InvalidCarrier {
+        index: usize,
+        num_taxis: usize,
+    },
 }
 
 impl fmt::Debug for Error {
@@ -64,6 +140,15 @@ impl fmt::Debug for Error {
                 id, world
             ),
 
+            Error::InvalidPassengerPosition {
+                passenger_pos,
+                world_dims,
+            } => write!(
+                f,
+                "Passenger position ({},{}) is invalid, world (width, height) is ({},{}).",
+                passenger_pos.0, passenger_pos.1, world_dims.0, world_dims.1
+            ),
+
             Error::TooFewFixedPositions {
                 num_fixed_positions,
                 ref world,
@@ -83,6 +168,20 @@ impl fmt::Debug for Error {
                  Looking for destation offset {} in world:\n{}",
                 destination_offset, world,
             ),
+
+            Error::NoTaxis => write!(f, "At least one taxi is needed."),
+
+            Error::SharedTaxiCell { taxi_pos } => write!(
+                f,
+                "More than one taxi at position ({},{}).",
+                taxi_pos.0, taxi_pos.1
+            ),
+
+            Error::InvalidCarrier { index, num_taxis } => write!(
+                f,
+                "Passenger is in taxi {}, but there are only {} taxis.",
+                index, num_taxis
+            ),
         }
     }
 }
@@ -105,7 +204,7 @@ impl State {
             });
         }
 
-        if world.get_fixed_position(destination) == None {
+        if world.get_fixed_position(destination).is_none() {
             return Err(Error::InvalidDestination {
                 id: destination,
                 world: world.display(),
@@ -113,7 +212,7 @@ impl State {
         }
 
         if let Some(passenger_id) = passenger {
-            if world.get_fixed_position(passenger_id) == None {
+            if world.get_fixed_position(passenger_id).is_none() {
                 return Err(Error::InvalidPassenger {
                     id: passenger_id,
                     world: world.display(),
@@ -124,10 +223,50 @@ impl State {
         Ok(State {
             taxi: Position::new(taxi_pos.0, taxi_pos.1),
             passenger,
+            passenger_cell: None,
             destination,
         })
     }
 
+    // Like build, but the passenger may wait at any cell, not just at a
+    // fixed position.  None means the passenger is in the taxi.
+    pub fn build_with_passenger_position(
+        world: &World,
+        taxi_pos: (i32, i32),
+        passenger_pos: Option<(i32, i32)>,
+        destination: char,
+    ) -> Result<State, Error> {
+        let passenger_pos = match passenger_pos {
+            Some(passenger_pos) => passenger_pos,
+            None => return State::build(world, taxi_pos, None, destination),
+        };
+
+        if passenger_pos.0 < 0
+            || passenger_pos.0 >= world.width
+            || passenger_pos.1 < 0
+            || passenger_pos.1 >= world.height
+        {
+            return Err(Error::InvalidPassengerPosition {
+                passenger_pos,
+                world_dims: (world.width, world.height),
+            });
+        }
+
+        let position = Position::new(passenger_pos.0, passenger_pos.1);
+
+        match world.get_fixed_id(position) {
+            Some(passenger_id) => State::build(world, taxi_pos, Some(passenger_id), destination),
+            None => {
+                let state = State::build(world, taxi_pos, None, destination)?;
+
+                Ok(State {
+                    passenger_cell: Some(position),
+                    ..state
+                })
+            }
+        }
+    }
+
     pub fn build_random<R: Rng>(world: &World, rng: &mut R) -> Result<State, Error> {
         let taxi_x = rng.gen_range(0, world.width);
         let taxi_y = rng.gen_range(0, world.height);
@@ -146,6 +285,48 @@ impl State {
                 Ok(State {
                     taxi: Position::new(taxi_x, taxi_y),
                     passenger,
+                    passenger_cell: None,
+                    destination,
+                })
+            } else {
+                Err(Error::FailedToFindDestination {
+                    destination_offset: destination_fp_index,
+                    world: world.display(),
+                })
+            }
+        } else {
+            Err(Error::TooFewFixedPositions {
+                num_fixed_positions,
+                world: world.display(),
+            })
+        }
+    }
+
+    // Unlike build_random, the passenger may already be in the taxi.  Every
+    // unsolved state is equally likely.
+    pub fn random<R: Rng>(world: &World, rng: &mut R) -> Result<State, Error> {
+        let taxi_x = rng.gen_range(0, world.width);
+        let taxi_y = rng.gen_range(0, world.height);
+
+        let num_fixed_positions = world.num_fixed_positions();
+
+        if num_fixed_positions >= 1 {
+            let destination_fp_index = rng.gen_range(0, num_fixed_positions);
+            if let Some(destination) = world.get_fixed_id_from_index(destination_fp_index) {
+                // The destination's index stands in for the passenger being
+                // in the taxi, a passenger waiting there is already delivered.
+                let passenger_fp_index = rng.gen_range(0, num_fixed_positions);
+
+                let passenger = if passenger_fp_index == destination_fp_index {
+                    None
+                } else {
+                    world.get_fixed_id_from_index(passenger_fp_index)
+                };
+
+                Ok(State {
+                    taxi: Position::new(taxi_x, taxi_y),
+                    passenger,
+                    passenger_cell: None,
                     destination,
                 })
             } else {
@@ -162,7 +343,75 @@ impl State {
         }
     }
 
+    // The state with this index under StateIndexer::new, None past the last
+    // state.
+    pub fn from_index(world: &World, index: usize) -> Option<State> {
+        StateIndexer::new(world).get_state(world, index)
+    }
+
+    // The inverse of from_index.  None for a passenger waiting away from the
+    // fixed positions, which StateIndexer::new does not index.
+    pub fn to_index(&self, world: &World) -> Option<usize> {
+        StateIndexer::new(world).get_index(world, self)
+    }
+
     pub fn display(&self, world: &World) -> String {
+        self.display_with_style(world, &DisplayStyle::default())
+    }
+
+    // display with each row's y to its left and each column's x above it.
+    // Past ten columns a row of tens sits above the row of units.
+    pub fn display_with_coords(&self, world: &World) -> String {
+        let label_width = (world.height - 1).max(0).to_string().len();
+        let margin = " ".repeat(label_width);
+
+        let column_labels = |digit: fn(i32) -> Option<i32>| -> String {
+            let mut labels = margin.clone();
+
+            for x in 0..world.width {
+                labels.push(' ');
+                labels.push_str(&digit(x).map_or(String::from(" "), |d| d.to_string()));
+            }
+
+            labels.trim_end().to_string() + "\n"
+        };
+
+        let mut result = String::new();
+
+        if world.width > 10 {
+            result += &column_labels(|x| if x < 10 { None } else { Some(x / 10 % 10) });
+        }
+        result += &column_labels(|x| Some(x % 10));
+
+        for (i_r, line) in self.display(world).lines().enumerate() {
+            if i_r % 2 == 1 {
+                result += &format!("{:>width$}", i_r / 2, width = label_width);
+            } else {
+                result += &margin;
+            }
+
+            result += line;
+            result.push('\n');
+        }
+
+        result
+    }
+
+    // A stable one line form: "taxi=(x,y) pass=<id, (x,y) or in_taxi> dest=<id>".
+    pub fn summary(&self) -> String {
+        let passenger = match (self.passenger, self.passenger_cell) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(cell)) => format!("({},{})", cell.x, cell.y),
+            (None, None) => String::from("in_taxi"),
+        };
+
+        format!(
+            "taxi=({},{}) pass={} dest={}",
+            self.taxi.x, self.taxi.y, passenger, self.destination
+        )
+    }
+
+    pub fn display_with_style(&self, world: &World, style: &DisplayStyle) -> String {
         let world_strings = world.display_strings();
 
         let mut result = String::new();
@@ -173,7 +422,7 @@ impl State {
             if i_r % 2 == 1 {
                 for (i_c, c) in r.chars().enumerate() {
                     if i_c % 2 == 1 {
-                        result.push(self.calc_character(c, current_position));
+                        result.push(self.calc_character(c, current_position, style));
 
                         current_position.x += 1;
                     } else {
@@ -193,49 +442,67 @@ impl State {
         result
     }
 
-    fn calc_character(&self, id: char, position: Position) -> char {
+    // When several things share a cell a delivered passenger wins, then the
+    // taxi, then whatever is waiting in the cell.
+    fn calc_character(&self, id: char, position: Position, style: &DisplayStyle) -> char {
+        let taxi_here = self.taxi == position;
+
         if id == self.destination {
-            match self.passenger {
-                Some(passenger_id) if passenger_id == self.destination => 'D',
-                _ => 'd',
+            if self.at_destination() {
+                style.delivered
+            } else if taxi_here {
+                style.taxi_on_destination
+            } else {
+                style.destination
             }
-        } else {
-            match self.passenger {
-                Some(passenger_id) => {
-                    if passenger_id == id {
-                        'p'
-                    } else if self.taxi == position {
-                        't'
-                    } else {
-                        '.'
-                    }
-                }
-                None => {
-                    if self.taxi == position {
-                        'T'
-                    } else {
-                        '.'
-                    }
-                }
+        } else if self.passenger == Some(id) || self.passenger_cell == Some(position) {
+            if taxi_here {
+                style.taxi_on_passenger
+            } else {
+                style.passenger
+            }
+        } else if taxi_here {
+            if !self.passenger_in_taxi() {
+                style.taxi
+            } else {
+                style.taxi_with_passenger
             }
+        } else {
+            style.empty
         }
     }
 
     pub fn apply_action(&self, world: &World, action: Actions) -> (f64, State) {
+        // The world only knows about pick ups at fixed positions.
+        if let Actions::PickUp = action {
+            if self.passenger_cell == Some(self.taxi) {
+                return (
+                    world.costs.pickup,
+                    State {
+                        passenger_cell: None,
+                        ..*self
+                    },
+                );
+            }
+        }
+
         match world.determine_affect(self.taxi, action) {
             ActionAffect::Invalid => match action {
                 Actions::North | Actions::South | Actions::East | Actions::West => {
-                    (world.costs.movement, *self)
+                    (world.costs.blocked_movement, *self)
                 }
                 Actions::PickUp => (world.costs.miss_pickup, *self),
+                Actions::Wait => (world.costs.wait, *self),
                 Actions::DropOff => {
-                    if self.passenger == None {
+                    if self.passenger_in_taxi() {
                         (world.costs.miss_dropoff, *self)
                     } else {
                         (world.costs.empty_dropoff, *self)
                     }
                 }
             },
+            ActionAffect::Wait => (world.costs.wait, *self),
+
             ActionAffect::Move(delta) => (
                 world.costs.movement,
                 State {
@@ -247,7 +514,7 @@ impl State {
             ActionAffect::PickUp(id) => {
                 if self.passenger == Some(id) {
                     (
-                        0.0,
+                        world.costs.pickup,
                         State {
                             passenger: None,
                             ..*self
@@ -259,10 +526,10 @@ impl State {
             }
 
             ActionAffect::DropOff(id) => {
-                if self.passenger == None {
+                if self.passenger_in_taxi() {
                     if id == self.destination {
                         (
-                            0.0,
+                            world.delivery_reward(id),
                             State {
                                 passenger: Some(id),
                                 ..*self
@@ -278,22 +545,200 @@ impl State {
         }
     }
 
-    pub fn at_destination(&self) -> bool {
-        if let Some(passenger_id) = self.passenger {
-            passenger_id == self.destination
+    // Drives the taxi straight to a fixed position in one step, costing a
+    // move for every cell of the shortest path.  Without world.goto_actions,
+    // or with no path there, the taxi stays put as if it hit a wall.
+    pub fn apply_goto(&self, world: &World, id: char) -> (f64, State) {
+        let distance = if world.goto_actions {
+            world
+                .get_fixed_position(id)
+                .and_then(|target| world.distance(self.taxi, target).map(|d| (target, d)))
         } else {
-            false
+            None
+        };
+
+        match distance {
+            Some((_, 0)) => (world.costs.wait, *self),
+            Some((target, distance)) => (
+                world.costs.movement * distance as f64,
+                State {
+                    taxi: target,
+                    ..*self
+                },
+            ),
+            None => (world.costs.blocked_movement, *self),
+        }
+    }
+
+    // Like apply_action, but a pick up that would succeed is refused with
+    // probability world.pickup_refusal, costing a move and leaving the
+    // passenger waiting.  Afterwards the destination may move to another
+    // fixed position with probability world.destination_churn, and then a
+    // waiting passenger may step to a neighbouring cell with probability
+    // world.passenger_wander.  These draw from world.env_rng when it is
+    // seeded, otherwise from rng, which is untouched when none can happen.
+    pub fn apply_action_stochastic<R: Rng>(
+        &self,
+        world: &World,
+        action: Actions,
+        rng: &mut R,
+    ) -> (f64, State) {
+        match world.env_rng.lock() {
+            Some(mut env_rng) => self.apply_environment(world, action, &mut *env_rng),
+            None => self.apply_environment(world, action, rng),
+        }
+    }
+
+    fn apply_environment<R: Rng>(
+        &self,
+        world: &World,
+        action: Actions,
+        rng: &mut R,
+    ) -> (f64, State) {
+        let (reward, next_state) = self.apply_action(world, action);
+
+        let picked_up = action == Actions::PickUp
+            && !self.passenger_in_taxi()
+            && next_state.passenger_in_taxi();
+
+        let (reward, next_state) = if picked_up
+            && world.pickup_refusal > 0.0
+            && rng.gen_range(0.0f64, 1.0f64) < world.pickup_refusal
+        {
+            (world.costs.movement, *self)
+        } else {
+            (reward, next_state)
+        };
+
+        let next_state = next_state
+            .churn_destination(world, rng)
+            .wander_passenger(world, rng);
+
+        (reward, next_state)
+    }
+
+    fn churn_destination<R: Rng>(self, world: &World, rng: &mut R) -> State {
+        if world.destination_churn <= 0.0 || self.at_destination() {
+            return self;
+        }
+
+        if rng.gen_range(0.0f64, 1.0f64) >= world.destination_churn {
+            return self;
+        }
+
+        // A destination where the passenger waits would deliver them.
+        let candidates: Vec<char> = (0..world.num_fixed_positions())
+            .filter_map(|index| world.get_fixed_id_from_index(index))
+            .filter(|id| *id != self.destination && Some(*id) != self.passenger)
+            .collect();
+
+        match candidates.choose(rng) {
+            Some(destination) => State {
+                destination: *destination,
+                ..self
+            },
+            None => self,
         }
     }
 
+    // The passenger never steps into the taxi, it has to pick them up, nor
+    // onto the destination, which would deliver them.
+    fn wander_passenger<R: Rng>(self, world: &World, rng: &mut R) -> State {
+        if world.passenger_wander <= 0.0 || self.at_destination() {
+            return self;
+        }
+
+        let position = match self.get_passenger_position(world) {
+            Some(position) => position,
+            None => return self,
+        };
+
+        if rng.gen_range(0.0f64, 1.0f64) >= world.passenger_wander {
+            return self;
+        }
+
+        let candidates: Vec<Position> =
+            [Actions::North, Actions::South, Actions::East, Actions::West]
+                .iter()
+                .filter_map(|action| match world.determine_affect(position, *action) {
+                    ActionAffect::Move(delta) => Some(position + delta),
+                    _ => None,
+                })
+                .filter(|next_position| {
+                    *next_position != self.taxi
+                        && world.get_fixed_id(*next_position) != Some(self.destination)
+                })
+                .collect();
+
+        match candidates.choose(rng) {
+            Some(next_position) => match world.get_fixed_id(*next_position) {
+                Some(passenger_id) => State {
+                    passenger: Some(passenger_id),
+                    passenger_cell: None,
+                    ..self
+                },
+                None => State {
+                    passenger: None,
+                    passenger_cell: Some(*next_position),
+                    ..self
+                },
+            },
+            None => self,
+        }
+    }
+
+    pub fn passenger_state(&self) -> PassengerState {
+        match (self.passenger, self.passenger_cell) {
+            (Some(passenger_id), _) if passenger_id == self.destination => {
+                PassengerState::Delivered
+            }
+            (Some(passenger_id), _) => PassengerState::Waiting(passenger_id),
+            (None, Some(position)) => PassengerState::WaitingAt(position),
+            (None, None) => PassengerState::InTaxi,
+        }
+    }
+
+    pub fn at_destination(&self) -> bool {
+        self.passenger_state() == PassengerState::Delivered
+    }
+
+    // Only fixed positions have ids, so a passenger waiting at any other
+    // cell is None here too, see passenger_in_taxi and get_passenger_position.
+    #[deprecated(
+        note = "None is either in the taxi or off the fixed positions, use passenger_state"
+    )]
     pub fn get_passenger(&self) -> Option<char> {
         self.passenger
     }
 
+    pub fn passenger_in_taxi(&self) -> bool {
+        self.passenger_state() == PassengerState::InTaxi
+    }
+
+    // Where the passenger is waiting, or None when in the taxi.
+    pub fn get_passenger_position(&self, world: &World) -> Option<Position> {
+        match self.passenger {
+            Some(passenger_id) => world.get_fixed_position(passenger_id),
+            None => self.passenger_cell,
+        }
+    }
+
     pub fn get_destination(&self) -> char {
         self.destination
     }
 
+    // Moves from the taxi to the passenger, or to the destination once the
+    // passenger is aboard.
+    pub fn goal_distance(&self, world: &World) -> Option<usize> {
+        let goal = if self.passenger_in_taxi() {
+            world.get_fixed_position(self.destination)?
+        } else {
+            self.get_passenger_position(world)?
+        };
+
+        world.distance(self.taxi, goal)
+    }
+
     pub fn get_taxi(&self) -> Position {
         self.taxi
     }
@@ -306,12 +751,29 @@ pub struct StateIterator<'a> {
 
 impl<'a> StateIterator<'a> {
     pub fn new(world: &World) -> StateIterator<'_> {
-        let first_fp = world.get_fixed_id_from_index(0).unwrap();
+        let first_fp = match world.get_fixed_id_from_index(0) {
+            Some(first_fp) => first_fp,
+            None => {
+                // No fixed positions means no states.  Starting on the last
+                // cell with an unknown destination makes next return None.
+                return StateIterator {
+                    current: State {
+                        taxi: Position::new(world.width - 1, world.height - 1),
+                        passenger: None,
+                        passenger_cell: None,
+                        destination: '?',
+                    },
+
+                    world,
+                };
+            }
+        };
 
         StateIterator {
             current: State {
                 taxi: Position::new(-1, 0),
                 passenger: Some(first_fp),
+                passenger_cell: None,
                 destination: first_fp,
             },
 
@@ -358,8 +820,9 @@ impl<'a> Iterator for StateIterator<'a> {
 mod test_state {
 
     use super::*;
-    use crate::world::Costs;
-    use rand::thread_rng;
+    use crate::world::{Costs, EnvRng, WorldBuilder};
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64Mcg;
 
     #[test]
     fn build_correct() {
@@ -381,6 +844,7 @@ mod test_state {
         let expected_state = State {
             taxi: Position::new(1, 3),
             passenger: Some('R'),
+            passenger_cell: None,
             destination: 'B',
         };
 
@@ -388,6 +852,45 @@ mod test_state {
         assert_eq!(res_state, expected_state);
     }
 
+    #[test]
+    fn index_round_trips() {
+        let source_world = "\
+                            ┌───┬─────┐\n\
+                            │R .│. . .│\n\
+                            │   │     │\n\
+                            │. .│G . .│\n\
+                            │         │\n\
+                            │. . . . .│\n\
+                            │         │\n\
+                            │.│Y .│B .│\n\
+                            │ │   │   │\n\
+                            │.│. .│. .│\n\
+                            └─┴───┴───┘\n\
+                            ";
+        let costs = Costs::default();
+        let w = World::build_from_str(source_world, costs).unwrap();
+        let num_states = StateIndexer::new(&w).num_states();
+        assert_eq!(num_states, 25 * 5 * 4);
+
+        for index in &[0, 1, 24, 25, 137, 250, num_states - 1] {
+            let state = State::from_index(&w, *index).unwrap();
+            assert_eq!(state.to_index(&w), Some(*index));
+        }
+
+        assert_eq!(
+            State::from_index(&w, 0),
+            State::build(&w, (0, 0), Some('R'), 'R').ok()
+        );
+        assert_eq!(
+            State::from_index(&w, 26),
+            State::build(&w, (1, 0), Some('G'), 'R').ok()
+        );
+        assert_eq!(State::from_index(&w, num_states), None);
+
+        let wandered = State::build_with_passenger_position(&w, (0, 0), Some((1, 1)), 'B').unwrap();
+        assert_eq!(wandered.to_index(&w), None);
+    }
+
     #[test]
     fn pickup_dropoff_does_nothing_off_fixedpoint() {
         let source_world = "\
@@ -868,7 +1371,7 @@ mod test_state {
         let costs = Costs::default();
         let w = World::build_from_str(source_world, costs).unwrap();
 
-        let mut rng = thread_rng();
+        let mut rng = Pcg64Mcg::seed_from_u64(10);
 
         for _ in 0..20 {
             let state = State::build_random(&w, &mut rng).unwrap();
@@ -890,4 +1393,529 @@ mod test_state {
             assert_ne!(passenger_fp_index, destination_fp_index);
         }
     }
+
+    #[test]
+    fn random_state_covers_unsolved_states() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(11);
+
+        let samples: Vec<State> = (0..2000)
+            .map(|_| State::random(&w, &mut rng).unwrap())
+            .collect();
+
+        assert!(samples.iter().all(|state| !state.at_destination()));
+
+        let unsolved: Vec<State> = StateIterator::new(&w)
+            .filter(|state| !state.at_destination())
+            .collect();
+        assert_eq!(unsolved.len(), 16);
+
+        for state in unsolved {
+            assert!(samples.contains(&state), "{:?} never sampled", state);
+        }
+    }
+
+    #[test]
+    fn goal_distance_follows_passenger() {
+        let source_world = "\
+                            ┌─────┐\n\
+                            │R . G│\n\
+                            │  ─  │\n\
+                            │. . .│\n\
+                            │     │\n\
+                            │Y . B│\n\
+                            └─────┘\n\
+                            ";
+        let world = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let waiting = State::build(&world, (1, 1), Some('R'), 'B').unwrap();
+        assert_eq!(waiting.goal_distance(&world), Some(2));
+
+        let carrying = State::build(&world, (1, 0), None, 'B').unwrap();
+        assert_eq!(carrying.goal_distance(&world), Some(3));
+    }
+
+    #[test]
+    fn passenger_states() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let waiting = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+        assert_eq!(waiting.passenger_state(), PassengerState::Waiting('R'));
+
+        let waiting_at =
+            State::build_with_passenger_position(&w, (1, 0), Some((1, 0)), 'G').unwrap();
+        assert_eq!(
+            waiting_at.passenger_state(),
+            PassengerState::WaitingAt(Position::new(1, 0))
+        );
+
+        let (_, in_taxi) = waiting_at.apply_action(&w, Actions::PickUp);
+        assert_eq!(in_taxi.passenger_state(), PassengerState::InTaxi);
+        assert!(!in_taxi.at_destination());
+
+        let delivered = State::build(&w, (0, 0), Some('G'), 'G').unwrap();
+        assert_eq!(delivered.passenger_state(), PassengerState::Delivered);
+        assert!(delivered.at_destination());
+    }
+
+    #[test]
+    fn no_fixed_positions_no_states() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │. .│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        assert_eq!(StateIterator::new(&w).count(), 0);
+        assert_matches!(
+            State::build(&w, (0, 0), None, 'R'),
+            Err(Error::InvalidDestination { id: 'R', .. })
+        );
+        assert_matches!(
+            State::build_random(&w, &mut Pcg64Mcg::seed_from_u64(12)),
+            Err(Error::TooFewFixedPositions { .. })
+        );
+    }
+
+    #[test]
+    fn display_with_custom_style() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let style = DisplayStyle {
+            taxi: 'a',
+            taxi_with_passenger: 'b',
+            passenger: 'c',
+            taxi_on_passenger: 'h',
+            destination: 'e',
+            taxi_on_destination: 'f',
+            delivered: 'g',
+            empty: '_',
+        };
+
+        let waiting = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+        let expected_waiting = "\
+                                ┌───┐\n\
+                                │c a│\n\
+                                │   │\n\
+                                │_ e│\n\
+                                └───┘\n\
+                                ";
+        assert_eq!(expected_waiting, waiting.display_with_style(&w, &style));
+
+        let carrying = State::build(&w, (0, 1), None, 'G').unwrap();
+        let expected_carrying = "\
+                                 ┌───┐\n\
+                                 │_ _│\n\
+                                 │   │\n\
+                                 │b e│\n\
+                                 └───┘\n\
+                                 ";
+        assert_eq!(expected_carrying, carrying.display_with_style(&w, &style));
+
+        let arrived = State::build(&w, (1, 1), None, 'G').unwrap();
+        let expected_arrived = "\
+                                ┌───┐\n\
+                                │_ _│\n\
+                                │   │\n\
+                                │_ f│\n\
+                                └───┘\n\
+                                ";
+        assert_eq!(expected_arrived, arrived.display_with_style(&w, &style));
+
+        let (_, delivered) = arrived.apply_action(&w, Actions::DropOff);
+        let expected_delivered = "\
+                                  ┌───┐\n\
+                                  │_ _│\n\
+                                  │   │\n\
+                                  │_ g│\n\
+                                  └───┘\n\
+                                  ";
+        assert_eq!(expected_delivered, delivered.display_with_style(&w, &style));
+    }
+
+    #[test]
+    fn display_with_coords() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+        let state = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+
+        let expected =
+            ["  0 1", " ┌───┐", "0│p t│", " │   │", "1│. d│", " └───┘"].join("\n") + "\n";
+        assert_eq!(expected, state.display_with_coords(&w));
+
+        // Two digit rows and columns stay aligned.
+        let w = WorldBuilder::new(12, 11)
+            .add_fixed_position('R', Position::new(0, 0))
+            .add_fixed_position('G', Position::new(11, 10))
+            .build()
+            .unwrap();
+        let state = State::build(&w, (11, 0), Some('R'), 'G').unwrap();
+
+        let display = state.display_with_coords(&w);
+        let lines: Vec<&str> = display.lines().collect();
+        assert_eq!(lines.len(), 2 + 2 * 11 + 1);
+        assert_eq!(lines[0], "                       1 1");
+        assert_eq!(lines[1], "   0 1 2 3 4 5 6 7 8 9 0 1");
+        assert!(lines[3].starts_with(" 0│p"));
+        assert!(lines[3].ends_with("t│"));
+        assert!(lines[23].starts_with("10│"));
+        assert!(lines[23].ends_with("d│"));
+
+        assert_eq!(state.display(&w).lines().nth(1), Some(&lines[3][2..]));
+    }
+
+    #[test]
+    fn display_overlap_precedence() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let cell = |style: &DisplayStyle, taxi, passenger, position: Position| {
+            let state = State::build(&w, taxi, passenger, 'G').unwrap();
+            let display = state.display_with_style(&w, style);
+            let row: Vec<char> = display
+                .lines()
+                .nth(1 + 2 * position.y as usize)
+                .unwrap()
+                .chars()
+                .collect();
+            row[1 + 2 * position.x as usize]
+        };
+
+        let r = Position::new(0, 0);
+        let g = Position::new(1, 1);
+
+        // The default hides the taxi under the passenger and destination.
+        let style = DisplayStyle::default();
+        assert_eq!(cell(&style, (0, 0), Some('R'), r), 'p');
+        assert_eq!(cell(&style, (1, 1), Some('R'), g), 'd');
+        assert_eq!(cell(&style, (1, 1), None, g), 'd');
+        assert_eq!(cell(&style, (0, 0), Some('G'), g), 'D');
+        assert_eq!(cell(&style, (1, 1), Some('G'), g), 'D');
+
+        let style = DisplayStyle::distinct_overlaps();
+        // Taxi and waiting passenger.
+        assert_eq!(cell(&style, (0, 0), Some('R'), r), 'P');
+        // Empty taxi and destination.
+        assert_eq!(cell(&style, (1, 1), Some('R'), g), 'X');
+        // Carrying taxi and destination.
+        assert_eq!(cell(&style, (1, 1), None, g), 'X');
+        // Passenger and destination, the passenger has been delivered.
+        assert_eq!(cell(&style, (0, 0), Some('G'), g), 'D');
+        // All three.
+        assert_eq!(cell(&style, (1, 1), Some('G'), g), 'D');
+    }
+
+    #[test]
+    fn summary_format() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let waiting = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+        assert_eq!(waiting.summary(), "taxi=(1,0) pass=R dest=G");
+
+        let carrying = State::build(&w, (0, 1), None, 'G').unwrap();
+        assert_eq!(carrying.summary(), "taxi=(0,1) pass=in_taxi dest=G");
+    }
+
+    #[test]
+    fn pickup_passenger_mid_grid() {
+        let source_world = "\
+                            ┌─────┐\n\
+                            │R . G│\n\
+                            │     │\n\
+                            │. . .│\n\
+                            │     │\n\
+                            │. Y .│\n\
+                            └─────┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let state = State::build_with_passenger_position(&w, (1, 0), Some((1, 1)), 'G').unwrap();
+
+        let expected_waiting = "\
+                                ┌─────┐\n\
+                                │. t d│\n\
+                                │     │\n\
+                                │. p .│\n\
+                                │     │\n\
+                                │. . .│\n\
+                                └─────┘\n\
+                                ";
+        assert_eq!(expected_waiting, state.display(&w));
+        assert_eq!(
+            state.passenger_state(),
+            PassengerState::WaitingAt(Position::new(1, 1))
+        );
+        assert!(!state.passenger_in_taxi());
+        assert_eq!(state.get_passenger_position(&w), Some(Position::new(1, 1)));
+        assert_eq!(state.summary(), "taxi=(1,0) pass=(1,1) dest=G");
+
+        // Neither pick up away from the passenger nor drop off does anything.
+        let (reward, missed) = state.apply_action(&w, Actions::PickUp);
+        assert_eq!(reward, w.costs.miss_pickup);
+        assert_eq!(missed, state);
+
+        let (reward, dropped) = state.apply_action(&w, Actions::DropOff);
+        assert_eq!(reward, w.costs.empty_dropoff);
+        assert_eq!(dropped, state);
+
+        let (_, arrived) = state.apply_action(&w, Actions::South);
+        let (reward, carrying) = arrived.apply_action(&w, Actions::PickUp);
+        assert_eq!(reward, 0.0);
+        assert!(carrying.passenger_in_taxi());
+        assert_eq!(carrying.get_passenger_position(&w), None);
+
+        let (_, next) = carrying.apply_action(&w, Actions::North);
+        let (_, next) = next.apply_action(&w, Actions::East);
+        let (reward, delivered) = next.apply_action(&w, Actions::DropOff);
+        assert_eq!(reward, w.costs.delivery_reward);
+        assert!(delivered.at_destination());
+
+        // A fixed position is the same as building with its id.
+        assert_eq!(
+            State::build_with_passenger_position(&w, (1, 0), Some((1, 2)), 'G').unwrap(),
+            State::build(&w, (1, 0), Some('Y'), 'G').unwrap()
+        );
+        assert!(State::build_with_passenger_position(&w, (1, 0), Some((3, 0)), 'G').is_err());
+    }
+
+    #[test]
+    fn destination_churn() {
+        let source_world = "\
+                            ┌─────┐\n\
+                            │R . G│\n\
+                            │     │\n\
+                            │. . .│\n\
+                            │     │\n\
+                            │. Y .│\n\
+                            └─────┘\n\
+                            ";
+        let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let waiting = State::build(&w, (1, 1), Some('R'), 'G').unwrap();
+        let carrying = State::build(&w, (1, 1), None, 'G').unwrap();
+
+        let mut rng = Pcg64Mcg::seed_from_u64(13);
+
+        // Without churn this is apply_action.
+        for action_index in 0..Actions::NUM_ELEMENTS {
+            let action = Actions::from_index(action_index).unwrap();
+            assert_eq!(
+                waiting.apply_action_stochastic(&w, action, &mut rng),
+                waiting.apply_action(&w, action)
+            );
+        }
+
+        w.destination_churn = 1.0;
+
+        for _ in 0..20 {
+            let (reward, next_state) = waiting.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+            assert_eq!(reward, w.costs.wait);
+            // Neither the old destination nor where the passenger waits.
+            assert_eq!(next_state.get_destination(), 'Y');
+            assert!(!next_state.at_destination());
+
+            let (_, next_state) = carrying.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+            assert_ne!(next_state.get_destination(), 'G');
+        }
+
+        // Delivering ends the episode, so the destination stays put.
+        let (_, delivered) = State::build(&w, (2, 0), None, 'G')
+            .unwrap()
+            .apply_action_stochastic(&w, Actions::DropOff, &mut rng);
+        assert!(delivered.at_destination());
+    }
+
+    #[test]
+    fn seeded_environment() {
+        let source_world = "\
+                            ┌─────┐\n\
+                            │R . G│\n\
+                            │     │\n\
+                            │. . .│\n\
+                            │     │\n\
+                            │. Y .│\n\
+                            └─────┘\n\
+                            ";
+        let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+        w.destination_churn = 0.3;
+        w.passenger_wander = 0.3;
+        w.env_rng = EnvRng::new(7);
+
+        let outcomes = |world: &World, policy_seed: u64| -> Vec<State> {
+            let mut rng = Pcg64Mcg::seed_from_u64(policy_seed);
+            let mut state = State::build(world, (1, 1), Some('R'), 'G').unwrap();
+            let mut states = Vec::new();
+
+            for _ in 0..50 {
+                let (_, next_state) = state.apply_action_stochastic(world, Actions::Wait, &mut rng);
+                states.push(next_state);
+                state = next_state;
+            }
+
+            states
+        };
+
+        // Each clone starts the environment's stream afresh, whatever the
+        // policy's rng.
+        let first = outcomes(&w.clone(), 1);
+        assert_eq!(first, outcomes(&w.clone(), 2));
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+
+        // The policy's rng is left alone.
+        let mut rng = Pcg64Mcg::seed_from_u64(3);
+        let state = State::build(&w, (1, 1), Some('R'), 'G').unwrap();
+        state.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+        assert_eq!(rng.gen::<u64>(), Pcg64Mcg::seed_from_u64(3).gen::<u64>());
+
+        // Without a seed the environment follows the policy's rng instead.
+        w.env_rng = EnvRng::default();
+        assert_ne!(outcomes(&w, 1), outcomes(&w, 2));
+    }
+
+    #[test]
+    fn pickup_refusal() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R .│\n\
+                            │   │\n\
+                            │. G│\n\
+                            └───┘\n\
+                            ";
+        let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+        w.pickup_refusal = 0.25;
+
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let waiting = State::build(&w, (0, 0), Some('R'), 'G').unwrap();
+
+        let num_tries = 10_000;
+        let mut num_picked_up = 0;
+
+        for _ in 0..num_tries {
+            let (reward, next_state) =
+                waiting.apply_action_stochastic(&w, Actions::PickUp, &mut rng);
+
+            if next_state.passenger_in_taxi() {
+                assert_eq!(reward, w.costs.pickup);
+                num_picked_up += 1;
+            } else {
+                // A refusal is not a missed pick up.
+                assert_eq!(reward, w.costs.movement);
+                assert_eq!(next_state, waiting);
+            }
+        }
+
+        let success_rate = num_picked_up as f64 / num_tries as f64;
+        assert!((success_rate - 0.75).abs() < 0.02);
+
+        // Pick ups that fail anyway are unchanged.
+        let elsewhere = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+        assert_eq!(
+            elsewhere.apply_action_stochastic(&w, Actions::PickUp, &mut rng),
+            elsewhere.apply_action(&w, Actions::PickUp)
+        );
+    }
+
+    #[test]
+    fn passenger_wander() {
+        let source_world = "\
+                            ┌─────┐\n\
+                            │R . G│\n\
+                            │     │\n\
+                            │. . .│\n\
+                            │     │\n\
+                            │. Y .│\n\
+                            └─────┘\n\
+                            ";
+        let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+        let mut rng = Pcg64Mcg::seed_from_u64(14);
+
+        let waiting = State::build(&w, (1, 0), Some('R'), 'G').unwrap();
+        assert_eq!(
+            waiting.apply_action_stochastic(&w, Actions::Wait, &mut rng),
+            waiting.apply_action(&w, Actions::Wait)
+        );
+
+        w.passenger_wander = 1.0;
+
+        // The only free neighbour of R, the taxi is on the other.
+        for _ in 0..20 {
+            let (reward, next_state) = waiting.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+            assert_eq!(reward, w.costs.wait);
+            assert_eq!(
+                next_state.passenger_state(),
+                PassengerState::WaitingAt(Position::new(0, 1))
+            );
+        }
+
+        // Stepping onto a fixed position waits there by id, but never onto
+        // the destination.
+        let beside_y = State::build_with_passenger_position(&w, (1, 1), Some((0, 2)), 'G').unwrap();
+        for _ in 0..20 {
+            let (_, next_state) = beside_y.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+            assert!(
+                next_state.passenger_state() == PassengerState::Waiting('Y')
+                    || next_state.passenger_state()
+                        == PassengerState::WaitingAt(Position::new(0, 1))
+            );
+        }
+
+        let beside_g = State::build_with_passenger_position(&w, (1, 1), Some((2, 1)), 'G').unwrap();
+        for _ in 0..20 {
+            let (_, next_state) = beside_g.apply_action_stochastic(&w, Actions::Wait, &mut rng);
+            assert!(!next_state.at_destination());
+            assert_eq!(
+                next_state.passenger_state(),
+                PassengerState::WaitingAt(Position::new(2, 2))
+            );
+        }
+
+        // Once picked up the passenger stays in the taxi.
+        let (_, carrying) = waiting
+            .apply_action(&w, Actions::West)
+            .1
+            .apply_action_stochastic(&w, Actions::PickUp, &mut rng);
+        assert!(carrying.passenger_in_taxi());
+    }
 }
@@ -0,0 +1,302 @@
+use crate::actions::Actions;
+use crate::position::Position;
+use crate::state::{DisplayStyle, Error, State};
+use crate::world::World;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MultiTaxiPassenger {
+    // Waiting at the destination means delivered, as with State.
+    Waiting(char),
+    InTaxi(usize),
+}
+
+// Several taxis sharing one world and one passenger.  No two taxis are ever
+// in the same cell, a move into an occupied cell leaves the taxi where it is.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MultiTaxiState {
+    taxis: Vec<Position>,
+    passenger: MultiTaxiPassenger,
+    destination: char,
+}
+
+impl MultiTaxiState {
+    pub fn build(
+        world: &World,
+        taxi_positions: &[(i32, i32)],
+        passenger: MultiTaxiPassenger,
+        destination: char,
+    ) -> Result<MultiTaxiState, Error> {
+        if taxi_positions.is_empty() {
+            return Err(Error::NoTaxis);
+        }
+
+        let passenger_id = match passenger {
+            MultiTaxiPassenger::Waiting(id) => Some(id),
+            MultiTaxiPassenger::InTaxi(index) => {
+                if index >= taxi_positions.len() {
+                    return Err(Error::InvalidCarrier {
+                        index,
+                        num_taxis: taxi_positions.len(),
+                    });
+                }
+                None
+            }
+        };
+
+        let mut taxis = Vec::with_capacity(taxi_positions.len());
+
+        for &taxi_pos in taxi_positions {
+            // State::build does the bounds and id checks for each taxi.
+            let state = State::build(world, taxi_pos, passenger_id, destination)?;
+
+            if taxis.contains(&state.taxi) {
+                return Err(Error::SharedTaxiCell { taxi_pos });
+            }
+            taxis.push(state.taxi);
+        }
+
+        Ok(MultiTaxiState {
+            taxis,
+            passenger,
+            destination,
+        })
+    }
+
+    pub fn num_taxis(&self) -> usize {
+        self.taxis.len()
+    }
+
+    pub fn get_taxis(&self) -> &[Position] {
+        &self.taxis
+    }
+
+    pub fn get_passenger(&self) -> MultiTaxiPassenger {
+        self.passenger
+    }
+
+    pub fn get_destination(&self) -> char {
+        self.destination
+    }
+
+    pub fn at_destination(&self) -> bool {
+        self.passenger == MultiTaxiPassenger::Waiting(self.destination)
+    }
+
+    // The world as seen by one taxi.  A passenger in another taxi waits in
+    // that taxi's cell, which this taxi can never enter.
+    fn taxi_view(&self, taxi_index: usize) -> State {
+        let (passenger, passenger_cell) = match self.passenger {
+            MultiTaxiPassenger::Waiting(id) => (Some(id), None),
+            MultiTaxiPassenger::InTaxi(index) if index == taxi_index => (None, None),
+            MultiTaxiPassenger::InTaxi(index) => (None, Some(self.taxis[index])),
+        };
+
+        State {
+            taxi: self.taxis[taxi_index],
+            passenger,
+            passenger_cell,
+            destination: self.destination,
+        }
+    }
+
+    // Only the indexed taxi acts, the others stay put.  An index past the
+    // last taxi is a wait.
+    pub fn apply_action(
+        &self,
+        world: &World,
+        taxi_index: usize,
+        action: Actions,
+    ) -> (f64, MultiTaxiState) {
+        if taxi_index >= self.taxis.len() {
+            return (world.costs.wait, self.clone());
+        }
+
+        let (reward, next_view) = self.taxi_view(taxi_index).apply_action(world, action);
+
+        let mut next_state = self.clone();
+
+        let blocked = self
+            .taxis
+            .iter()
+            .enumerate()
+            .any(|(index, taxi)| index != taxi_index && *taxi == next_view.taxi);
+
+        if !blocked {
+            next_state.taxis[taxi_index] = next_view.taxi;
+        }
+
+        if let MultiTaxiPassenger::InTaxi(index) = self.passenger {
+            if index != taxi_index {
+                return (reward, next_state);
+            }
+        }
+
+        next_state.passenger = match next_view.passenger {
+            Some(id) => MultiTaxiPassenger::Waiting(id),
+            None => MultiTaxiPassenger::InTaxi(taxi_index),
+        };
+
+        (reward, next_state)
+    }
+
+    pub fn display(&self, world: &World) -> String {
+        self.display_with_style(world, &DisplayStyle::default())
+    }
+
+    // Each taxi is drawn as its index, the passenger and destination as with
+    // State, and a delivered passenger still wins.
+    pub fn display_with_style(&self, world: &World, style: &DisplayStyle) -> String {
+        // Taxis are drawn separately, so the view has none.
+        let view = State {
+            taxi: Position::new(-1, -1),
+            passenger_cell: None,
+            ..self.taxi_view(0)
+        };
+
+        let world_strings = world.display_strings();
+
+        let mut result = String::new();
+
+        let mut current_position = Position::new(0, 0);
+
+        for (i_r, r) in world_strings.iter().enumerate() {
+            if i_r % 2 == 1 {
+                for (i_c, c) in r.chars().enumerate() {
+                    if i_c % 2 == 1 {
+                        let glyph = view.calc_character(c, current_position, style);
+                        let taxi_here = self.taxis.iter().position(|t| *t == current_position);
+
+                        result.push(match taxi_here {
+                            Some(index) if glyph != style.delivered => {
+                                std::char::from_digit(index as u32, 36).unwrap_or('?')
+                            }
+                            _ => glyph,
+                        });
+
+                        current_position.x += 1;
+                    } else {
+                        result.push(c);
+                    }
+                }
+
+                current_position.x = 0;
+                current_position.y += 1;
+            } else {
+                result += r;
+            }
+
+            result.push('\n');
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test_multi_taxi {
+    use super::*;
+    use crate::world::Costs;
+
+    const WORLD: &str = "\
+                         ┌───────┐\n\
+                         │R . . G│\n\
+                         │       │\n\
+                         │. . . .│\n\
+                         └───────┘\n\
+                         ";
+
+    #[test]
+    fn build_rejects_invalid() {
+        let w = World::build_from_str(WORLD, Costs::default()).unwrap();
+
+        assert_matches!(
+            MultiTaxiState::build(&w, &[], MultiTaxiPassenger::Waiting('R'), 'G'),
+            Err(Error::NoTaxis)
+        );
+        assert_matches!(
+            MultiTaxiState::build(&w, &[(1, 0), (1, 0)], MultiTaxiPassenger::Waiting('R'), 'G'),
+            Err(Error::SharedTaxiCell { taxi_pos: (1, 0) })
+        );
+        assert_matches!(
+            MultiTaxiState::build(&w, &[(1, 0), (4, 0)], MultiTaxiPassenger::Waiting('R'), 'G'),
+            Err(Error::InvalidTaxi { .. })
+        );
+        assert_matches!(
+            MultiTaxiState::build(&w, &[(1, 0), (2, 0)], MultiTaxiPassenger::InTaxi(2), 'G'),
+            Err(Error::InvalidCarrier {
+                index: 2,
+                num_taxis: 2
+            })
+        );
+    }
+
+    #[test]
+    fn taxis_cannot_swap() {
+        let w = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let state =
+            MultiTaxiState::build(&w, &[(1, 0), (2, 0)], MultiTaxiPassenger::Waiting('R'), 'G')
+                .unwrap();
+
+        let (reward, next) = state.apply_action(&w, 0, Actions::East);
+        assert_eq!(reward, w.costs.movement);
+        assert_eq!(next, state);
+
+        let (_, next) = state.apply_action(&w, 1, Actions::West);
+        assert_eq!(next, state);
+
+        // Going around works.
+        let (_, next) = state.apply_action(&w, 0, Actions::South);
+        let (_, next) = next.apply_action(&w, 1, Actions::West);
+        assert_eq!(
+            next.get_taxis(),
+            &[Position::new(1, 1), Position::new(1, 0)]
+        );
+    }
+
+    #[test]
+    fn either_taxi_delivers() {
+        let w = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let state =
+            MultiTaxiState::build(&w, &[(0, 0), (3, 1)], MultiTaxiPassenger::Waiting('R'), 'G')
+                .unwrap();
+
+        // Only the taxi at R can pick up.
+        let (reward, next) = state.apply_action(&w, 1, Actions::PickUp);
+        assert_eq!(reward, w.costs.miss_pickup);
+        assert_eq!(next, state);
+
+        let (_, carrying) = state.apply_action(&w, 0, Actions::PickUp);
+        assert_eq!(carrying.get_passenger(), MultiTaxiPassenger::InTaxi(0));
+
+        // The other taxi has nothing to drop off.
+        let (reward, _) = carrying.apply_action(&w, 1, Actions::DropOff);
+        assert_eq!(reward, w.costs.empty_dropoff);
+
+        let mut state = carrying;
+        for _ in 0..3 {
+            state = state.apply_action(&w, 0, Actions::East).1;
+        }
+        assert_eq!(state.get_passenger(), MultiTaxiPassenger::InTaxi(0));
+
+        let (reward, delivered) = state.apply_action(&w, 0, Actions::DropOff);
+        assert_eq!(reward, w.costs.delivery_reward);
+        assert!(delivered.at_destination());
+    }
+
+    #[test]
+    fn display_numbers_taxis() {
+        let w = World::build_from_str(WORLD, Costs::default()).unwrap();
+        let state =
+            MultiTaxiState::build(&w, &[(1, 0), (2, 1)], MultiTaxiPassenger::Waiting('R'), 'G')
+                .unwrap();
+
+        let expected = "\
+                        ┌───────┐\n\
+                        │p 0 . d│\n\
+                        │       │\n\
+                        │. . 1 .│\n\
+                        └───────┘\n\
+                        ";
+        assert_eq!(state.display(&w), expected);
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::position::Position;
+use crate::state::{DisplayStyle, State};
+use crate::world::World;
+
+type Color = [u8; 3];
+
+const BACKGROUND: Color = [255, 255, 255];
+const FIXED_POSITION: Color = [220, 220, 220];
+const WALL: Color = [0, 0, 0];
+const TAXI: Color = [240, 200, 0];
+const TAXI_WITH_PASSENGER: Color = [240, 120, 0];
+const PASSENGER: Color = [40, 90, 220];
+const DESTINATION: Color = [230, 90, 90];
+const DELIVERED: Color = [40, 170, 60];
+
+// Below this the cells are too small for an inset, so each cell is a
+// single block in the colour of what the display would show there.
+const MIN_DETAILED_CELL_SIZE: u32 = 8;
+
+pub enum RenderError {
+    InvalidCellSize(u32),
+    Io(io::Error),
+    Encoding(png::EncodingError),
+}
+
+impl fmt::Debug for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RenderError::InvalidCellSize(cell_size) => {
+                write!(f, "Cell size {} is too small to render.", cell_size)
+            }
+            RenderError::Io(ref io_error) => write!(f, "Failed to write image:\n{:?}", io_error),
+            RenderError::Encoding(ref encoding_error) => {
+                write!(f, "Failed to encode image:\n{:?}", encoding_error)
+            }
+        }
+    }
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Image {
+        let mut data = Vec::with_capacity((width * height * 3) as usize);
+        for _ in 0..(width * height) {
+            data.extend_from_slice(&BACKGROUND);
+        }
+
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                let offset = ((py * self.width + px) * 3) as usize;
+                self.data[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+impl State {
+    pub fn render_png<P: AsRef<Path>>(
+        &self,
+        world: &World,
+        cell_size: u32,
+        path: P,
+    ) -> Result<(), RenderError> {
+        if cell_size == 0 {
+            return Err(RenderError::InvalidCellSize(cell_size));
+        }
+
+        let image = self.render(world, cell_size);
+
+        let file = File::create(path).map_err(RenderError::Io)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(RenderError::Encoding)?;
+        writer
+            .write_image_data(&image.data)
+            .map_err(RenderError::Encoding)
+    }
+
+    fn render(&self, world: &World, cell_size: u32) -> Image {
+        let mut image = Image::new(
+            world.width as u32 * cell_size,
+            world.height as u32 * cell_size,
+        );
+
+        // Every overlap needs its own glyph to be told apart below.
+        let style = DisplayStyle::distinct_overlaps();
+        let detailed = cell_size >= MIN_DETAILED_CELL_SIZE;
+
+        for y in 0..world.height {
+            for x in 0..world.width {
+                let position = Position::new(x, y);
+                let fixed_id = world.get_fixed_id(position);
+                let glyph = self.calc_character(fixed_id.unwrap_or('.'), position, &style);
+
+                let left = x as u32 * cell_size;
+                let top = y as u32 * cell_size;
+
+                if detailed {
+                    let background = if fixed_id == Some(self.destination) {
+                        DESTINATION
+                    } else if fixed_id.is_some() {
+                        FIXED_POSITION
+                    } else {
+                        BACKGROUND
+                    };
+                    image.fill(left, top, cell_size, cell_size, background);
+
+                    // The occupant is an inset square, with the passenger
+                    // nested inside a taxi that stands on them.
+                    let inset = cell_size / 4;
+                    let inner = cell_size - 2 * inset;
+
+                    if let Some(color) = occupant_color(&style, glyph) {
+                        image.fill(left + inset, top + inset, inner, inner, color);
+                    }
+
+                    if glyph == style.taxi_on_passenger {
+                        let inset = cell_size * 3 / 8;
+                        let inner = cell_size - 2 * inset;
+                        image.fill(left + inset, top + inset, inner, inner, PASSENGER);
+                    }
+                } else {
+                    let color = occupant_color(&style, glyph).unwrap_or(match fixed_id {
+                        Some(id) if id == self.destination => DESTINATION,
+                        Some(_) => FIXED_POSITION,
+                        None => BACKGROUND,
+                    });
+                    image.fill(left, top, cell_size, cell_size, color);
+                }
+            }
+        }
+
+        // Walls go on top, as thick lines along the inside of each cell.
+        let thickness = (cell_size / 8).max(1);
+
+        for y in 0..world.height {
+            for x in 0..world.width {
+                let wall = world.get_wall(Position::new(x, y));
+
+                let left = x as u32 * cell_size;
+                let top = y as u32 * cell_size;
+                let right = left + cell_size - thickness;
+                let bottom = top + cell_size - thickness;
+
+                if wall.north {
+                    image.fill(left, top, cell_size, thickness, WALL);
+                }
+                if wall.south {
+                    image.fill(left, bottom, cell_size, thickness, WALL);
+                }
+                if wall.west {
+                    image.fill(left, top, thickness, cell_size, WALL);
+                }
+                if wall.east {
+                    image.fill(right, top, thickness, cell_size, WALL);
+                }
+            }
+        }
+
+        image
+    }
+}
+
+fn occupant_color(style: &DisplayStyle, glyph: char) -> Option<Color> {
+    if glyph == style.taxi || glyph == style.taxi_on_passenger {
+        Some(TAXI)
+    } else if glyph == style.taxi_with_passenger || glyph == style.taxi_on_destination {
+        Some(TAXI_WITH_PASSENGER)
+    } else if glyph == style.passenger {
+        Some(PASSENGER)
+    } else if glyph == style.delivered {
+        Some(DELIVERED)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test_render {
+    use super::*;
+    use crate::world::Costs;
+
+    fn pixel(image: &Image, x: u32, y: u32) -> Color {
+        let offset = ((y * image.width + x) * 3) as usize;
+        [
+            image.data[offset],
+            image.data[offset + 1],
+            image.data[offset + 2],
+        ]
+    }
+
+    #[test]
+    fn render_cells_and_walls() {
+        let source_world = "\
+                            ┌───┬─┐\n\
+                            │R .│G│\n\
+                            │   │ │\n\
+                            │. . .│\n\
+                            └─────┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let state = State::build(&w, (1, 1), Some('R'), 'G').unwrap();
+
+        let image = state.render(&w, 16);
+        assert_eq!((image.width, image.height), (48, 32));
+
+        // Cell centres.
+        assert_eq!(pixel(&image, 8, 8), PASSENGER);
+        assert_eq!(pixel(&image, 40, 8), DESTINATION);
+        assert_eq!(pixel(&image, 24, 24), TAXI);
+        assert_eq!(pixel(&image, 8, 24), BACKGROUND);
+
+        // The wall between (1, 0) and (2, 0), and the outer border.
+        assert_eq!(pixel(&image, 31, 8), WALL);
+        assert_eq!(pixel(&image, 32, 8), WALL);
+        assert_eq!(pixel(&image, 0, 24), WALL);
+        assert_eq!(pixel(&image, 31, 24), BACKGROUND);
+    }
+
+    #[test]
+    fn render_small_cells_as_blocks() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+        let on_passenger = State::build(&w, (0, 0), Some('R'), 'G').unwrap();
+
+        let image = on_passenger.render(&w, 4);
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(pixel(&image, 1, 1), TAXI);
+        assert_eq!(pixel(&image, 5, 1), DESTINATION);
+    }
+
+    #[test]
+    fn render_png_writes_file() {
+        let source_world = "\
+                            ┌───┐\n\
+                            │R G│\n\
+                            └───┘\n\
+                            ";
+        let w = World::build_from_str(source_world, Costs::default()).unwrap();
+        let state = State::build(&w, (0, 0), Some('R'), 'G').unwrap();
+
+        let path = std::env::temp_dir().join(format!("taxi_render_{}.png", std::process::id()));
+
+        assert!(state.render_png(&w, 0, &path).is_err());
+        state.render_png(&w, 10, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let (info, _) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (20, 10));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
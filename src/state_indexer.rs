@@ -1,11 +1,19 @@
-use crate::state::State;
+use crate::state::{PassengerState, State};
 use crate::world::World;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct StateIndexer {
     num_taxi_states: usize,
     num_passenger_states: usize,
     num_destination_states: usize,
+
+    // Whether waiting passengers are indexed by cell rather than by fixed
+    // position.
+    passenger_cells: bool,
+
+    // Whether observations hide the destination of a waiting passenger.
+    #[serde(default)]
+    hidden_destination: bool,
 }
 
 impl StateIndexer {
@@ -18,6 +26,44 @@ impl StateIndexer {
             num_taxi_states,
             num_passenger_states,
             num_destination_states,
+            passenger_cells: false,
+            hidden_destination: false,
+        }
+    }
+
+    // Also indexes passengers waiting away from the fixed positions.
+    pub fn with_passenger_cells(world: &World) -> StateIndexer {
+        let num_taxi_states = (world.width * world.height) as usize;
+
+        StateIndexer {
+            num_taxi_states,
+            num_passenger_states: num_taxi_states + 1,
+            num_destination_states: world.num_fixed_positions(),
+            passenger_cells: true,
+            hidden_destination: false,
+        }
+    }
+
+    // The passenger only tells the driver where to go once in the taxi, so
+    // observations of a waiting passenger share one unknown destination.
+    pub fn with_hidden_destination(world: &World) -> StateIndexer {
+        StateIndexer {
+            hidden_destination: true,
+            ..StateIndexer::new(world)
+        }
+    }
+
+    // Indexes states of another world the same way.
+    pub fn for_world(&self, world: &World) -> StateIndexer {
+        let state_indexer = if self.passenger_cells {
+            StateIndexer::with_passenger_cells(world)
+        } else {
+            StateIndexer::new(world)
+        };
+
+        StateIndexer {
+            hidden_destination: self.hidden_destination,
+            ..state_indexer
         }
     }
 
@@ -25,14 +71,40 @@ impl StateIndexer {
         self.num_taxi_states * self.num_passenger_states * self.num_destination_states
     }
 
+    // The unknown destination takes one more destination slot, after the
+    // real ones.
+    pub fn num_observations(&self) -> usize {
+        if self.hidden_destination {
+            self.num_states() + self.num_taxi_states * self.num_passenger_states
+        } else {
+            self.num_states()
+        }
+    }
+
+    // Every state with an index, in index order.
+    pub fn iter_states<'a>(&'a self, world: &'a World) -> impl Iterator<Item = State> + 'a {
+        (0..self.num_states()).filter_map(move |state_index| self.get_state(world, state_index))
+    }
+
     pub fn get_index(&self, world: &World, state: &State) -> Option<usize> {
         if let Some(destination_index) = world.get_fixed_index(state.get_destination()) {
             let mut result = destination_index;
 
-            if let Some(passenger_index) = match state.get_passenger() {
-                Some(passenger_id) => world.get_fixed_index(passenger_id),
-                None => Some(self.num_passenger_states - 1),
-            } {
+            let passenger_index = if state.passenger_in_taxi() {
+                Some(self.num_passenger_states - 1)
+            } else if self.passenger_cells {
+                state
+                    .get_passenger_position(world)
+                    .map(|position| (world.width * position.y + position.x) as usize)
+            } else {
+                match state.passenger_state() {
+                    PassengerState::Waiting(passenger_id) => world.get_fixed_index(passenger_id),
+                    PassengerState::Delivered => world.get_fixed_index(state.get_destination()),
+                    PassengerState::InTaxi | PassengerState::WaitingAt(_) => None,
+                }
+            };
+
+            if let Some(passenger_index) = passenger_index {
                 result *= self.num_passenger_states;
                 result += passenger_index;
 
@@ -49,6 +121,25 @@ impl StateIndexer {
         None
     }
 
+    // The index of what a solver sees of the state, the same as get_index
+    // unless the destination is hidden.  A delivered passenger is no longer
+    // waiting, so terminal states keep their destination.
+    pub fn get_observation_index(&self, world: &World, state: &State) -> Option<usize> {
+        let state_index = self.get_index(world, state)?;
+
+        match state.passenger_state() {
+            PassengerState::Waiting(_) | PassengerState::WaitingAt(_)
+                if self.hidden_destination =>
+            {
+                Some(
+                    self.num_states()
+                        + state_index % (self.num_taxi_states * self.num_passenger_states),
+                )
+            }
+            _ => Some(state_index),
+        }
+    }
+
     pub fn get_state(&self, world: &World, mut state_index: usize) -> Option<State> {
         let taxi_index = state_index % self.num_taxi_states;
         state_index /= self.num_taxi_states;
@@ -62,6 +153,25 @@ impl StateIndexer {
         let taxi_y = taxi_index / (world.width as usize);
 
         if let Some(destination) = world.get_fixed_id_from_index(destination_index) {
+            if self.passenger_cells {
+                let passenger_pos = if passenger_index < self.num_taxi_states {
+                    Some((
+                        (passenger_index % (world.width as usize)) as i32,
+                        (passenger_index / (world.width as usize)) as i32,
+                    ))
+                } else {
+                    None
+                };
+
+                return State::build_with_passenger_position(
+                    world,
+                    (taxi_x as i32, taxi_y as i32),
+                    passenger_pos,
+                    destination,
+                )
+                .ok();
+            }
+
             let passenger = if passenger_index < world.num_fixed_positions() {
                 world.get_fixed_id_from_index(passenger_index)
             } else {
@@ -0,0 +1,97 @@
+use std::f64;
+
+use crate::actions::Actions;
+
+// Index and value of the largest entry, skipping NaN values.  Values within
+// a couple of ulps of each other count as tied, and ties go to the lowest
+// index.  Returns None when there are no non-NaN values.
+pub fn argmax_action(values: &[f64]) -> Option<(usize, f64)> {
+    let mut best: Option<(usize, f64)> = None;
+
+    for (index, value) in values.iter().enumerate() {
+        if value.is_nan() {
+            continue;
+        }
+
+        best = match best {
+            Some((_, best_value))
+                if *value > best_value && !approx_eq!(f64, *value, best_value, ulps = 2) =>
+            {
+                Some((index, *value))
+            }
+            None => Some((index, *value)),
+            _ => best,
+        };
+    }
+
+    best
+}
+
+// The values of the available actions, where values are indexed by
+// Actions::to_index, with NaN for the others so that argmax_action skips
+// them.
+pub fn mask_unavailable(values: &[f64], available: &[Actions]) -> Vec<f64> {
+    values
+        .iter()
+        .enumerate()
+        .map(
+            |(action_index, value)| match Actions::from_index(action_index) {
+                Some(action) if available.contains(&action) => *value,
+                _ => f64::NAN,
+            },
+        )
+        .collect()
+}
+
+// The best of the available actions, where values are indexed by
+// Actions::to_index.  Ties break as in argmax_action.
+pub fn argmax_available_action(values: &[f64], available: &[Actions]) -> Option<Actions> {
+    argmax_action(&mask_unavailable(values, available))
+        .and_then(|(action_index, _)| Actions::from_index(action_index))
+}
+
+#[cfg(test)]
+mod test_util {
+    use super::*;
+
+    #[test]
+    fn argmax_picks_largest() {
+        assert_eq!(argmax_action(&[1.0, 3.0, 2.0]), Some((1, 3.0)));
+        assert_eq!(argmax_action(&[-5.0]), Some((0, -5.0)));
+        assert_eq!(argmax_action(&[f64::NEG_INFINITY, -1.0]), Some((1, -1.0)));
+    }
+
+    #[test]
+    fn argmax_ties_go_to_lowest_index() {
+        assert_eq!(argmax_action(&[0.0, 2.0, 2.0, 1.0]), Some((1, 2.0)));
+        assert_eq!(argmax_action(&[4.0; 6]), Some((0, 4.0)));
+
+        let nearly = 0.1 + 0.2;
+        assert_eq!(argmax_action(&[0.3, nearly]), Some((0, 0.3)));
+    }
+
+    #[test]
+    fn argmax_skips_nan() {
+        assert_eq!(argmax_action(&[f64::NAN, 1.0, f64::NAN]), Some((1, 1.0)));
+        assert_eq!(argmax_action(&[2.0, f64::NAN, 3.0]), Some((2, 3.0)));
+        assert_eq!(argmax_action(&[f64::NAN, f64::NAN]), None);
+        assert_eq!(argmax_action(&[]), None);
+    }
+
+    #[test]
+    fn argmax_available_skips_masked_actions() {
+        let values = [1.0, 5.0, 3.0, 3.0, 0.0, 0.0, 0.0];
+
+        let all: Vec<Actions> = (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .collect();
+        assert_eq!(argmax_available_action(&values, &all), Some(Actions::South));
+
+        let available = [Actions::North, Actions::East, Actions::West];
+        assert_eq!(
+            argmax_available_action(&values, &available),
+            Some(Actions::East)
+        );
+        assert_eq!(argmax_available_action(&values, &[]), None);
+    }
+}
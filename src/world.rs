@@ -1,8 +1,18 @@
+mod builder;
+mod standard;
+
+use std::collections::VecDeque;
 use std::fmt;
-use std::iter;
+use std::sync::{Mutex, MutexGuard};
+
+use rand_pcg::Pcg64Mcg;
 
 use crate::actions::Actions;
 use crate::position::Position;
+use crate::state::State;
+use crate::state_indexer::StateIndexer;
+
+pub use self::builder::{Side, WorldBuilder};
 
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct Wall {
@@ -47,6 +57,41 @@ impl fmt::Display for Wall {
     }
 }
 
+// Sets or clears one side of a cell and the matching side of the cell
+// beyond it, so the two always agree.
+fn set_wall(walls: &mut [Vec<Wall>], position: Position, side: Side, present: bool) {
+    let (x, y) = (position.x as usize, position.y as usize);
+    let height = walls.len();
+    let width = walls[y].len();
+
+    match side {
+        Side::North => {
+            walls[y][x].north = present;
+            if y > 0 {
+                walls[y - 1][x].south = present;
+            }
+        }
+        Side::South => {
+            walls[y][x].south = present;
+            if y + 1 < height {
+                walls[y + 1][x].north = present;
+            }
+        }
+        Side::East => {
+            walls[y][x].east = present;
+            if x + 1 < width {
+                walls[y][x + 1].west = present;
+            }
+        }
+        Side::West => {
+            walls[y][x].west = present;
+            if x > 0 {
+                walls[y][x - 1].east = present;
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct FixedPosition {
     id: char,
@@ -56,20 +101,52 @@ struct FixedPosition {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Costs {
     pub movement: f64,
+    // A move into a wall, the taxi stays put.
+    pub blocked_movement: f64,
+    // A pick up where the passenger is waiting.
+    pub pickup: f64,
     pub miss_pickup: f64,
     pub miss_dropoff: f64,
     pub empty_dropoff: f64,
+    pub delivery_reward: f64,
+    pub wait: f64,
 }
 
 impl Costs {
     pub fn new(movement: f64, miss_pickup: f64, miss_dropoff: f64, empty_dropoff: f64) -> Self {
         Costs {
             movement,
+            blocked_movement: movement,
+            pickup: 0.0,
             miss_pickup,
             miss_dropoff,
             empty_dropoff,
+            delivery_reward: 0.0,
+            wait: movement,
         }
     }
+
+    // Every step reward is one of the costs.
+    fn step_rewards(&self) -> [f64; 8] {
+        [
+            self.movement,
+            self.blocked_movement,
+            self.pickup,
+            self.miss_pickup,
+            self.miss_dropoff,
+            self.empty_dropoff,
+            self.delivery_reward,
+            self.wait,
+        ]
+    }
+
+    pub fn max_reward(&self) -> f64 {
+        self.step_rewards().iter().cloned().fold(f64::MIN, f64::max)
+    }
+
+    pub fn min_reward(&self) -> f64 {
+        self.step_rewards().iter().cloned().fold(f64::MAX, f64::min)
+    }
 }
 
 impl Default for Costs {
@@ -78,7 +155,47 @@ impl Default for Costs {
     }
 }
 
-#[derive(Debug, PartialEq)]
+// Draws the environment's randomness, destination churn and a wandering
+// passenger, apart from the solver's rng.  A clone continues from the same
+// point in the stream, independently of the original.
+#[derive(Default)]
+pub struct EnvRng(Option<Mutex<Pcg64Mcg>>);
+
+impl EnvRng {
+    pub fn new(seed: u128) -> EnvRng {
+        EnvRng(Some(Mutex::new(Pcg64Mcg::new(seed))))
+    }
+
+    pub(crate) fn lock(&self) -> Option<MutexGuard<'_, Pcg64Mcg>> {
+        self.0
+            .as_ref()
+            .map(|rng| rng.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl Clone for EnvRng {
+    fn clone(&self) -> EnvRng {
+        EnvRng(self.lock().map(|rng| Mutex::new(rng.clone())))
+    }
+}
+
+impl fmt::Debug for EnvRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "EnvRng(seeded)"),
+            None => write!(f, "EnvRng(shared)"),
+        }
+    }
+}
+
+// Where the stream has got to is not part of the world.
+impl PartialEq for EnvRng {
+    fn eq(&self, other: &EnvRng) -> bool {
+        self.0.is_some() == other.0.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct World {
     pub width: i32,
     pub height: i32,
@@ -86,6 +203,23 @@ pub struct World {
     fixed_positions: Vec<FixedPosition>,
 
     pub costs: Costs,
+    // Replaces costs.delivery_reward for deliveries to these fixed
+    // positions, see World::set_delivery_reward.
+    delivery_rewards: Vec<(char, f64)>,
+    // Chance per learning step that the destination moves elsewhere.
+    pub destination_churn: f64,
+    // Chance per learning step that a waiting passenger moves to a
+    // neighbouring cell.
+    pub passenger_wander: f64,
+    // Chance per learning step that a pick up which would succeed is
+    // refused.
+    pub pickup_refusal: f64,
+    // Without a seed the environment draws from the rng passed to
+    // State::apply_action_stochastic.
+    pub env_rng: EnvRng,
+    // Whether State::apply_goto may move the taxi straight to a fixed
+    // position.
+    pub goto_actions: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -94,11 +228,15 @@ pub enum ActionAffect {
     Move(Position),
     PickUp(char),
     DropOff(char),
+    Wait,
 }
 
 pub enum Error {
     EmptyString,
     Parse { source: String, error: ParseError },
+    Build(BuildError),
+    Edit(EditError),
+    Unreachable(char),
 }
 
 impl fmt::Debug for Error {
@@ -109,11 +247,20 @@ impl fmt::Debug for Error {
                 ref source,
                 ref error,
             } => write!(f, "Parse failure: {:?}\nSource string:\n{}", error, source),
+            Error::Build(ref error) => write!(f, "Build failure: {:?}", error),
+            Error::Edit(ref error) => write!(f, "Edit failure: {:?}", error),
+            Error::Unreachable(id) => write!(
+                f,
+                "Fixed position '{}' cannot be reached from the other fixed positions.",
+                id
+            ),
         }
     }
 }
 
+// Lines and columns count from 1, as in an editor.
 pub enum ParseError {
+    // Any line, too long or short, that does not match the map's width.
     LineTooLong {
         line: usize,
         num_chars: usize,
@@ -121,6 +268,18 @@ pub enum ParseError {
     },
     DuplicateFixedPosition {
         id: char,
+        position: Position,
+    },
+    // A character that does not belong where it is, like a wall in a cell
+    // or a letter in a wall.
+    UnknownCharacter {
+        character: char,
+        line: usize,
+        column: usize,
+    },
+    // The map ends on a row of cells, with no wall line below it.
+    MissingBorder {
+        line: usize,
     },
 }
 
@@ -137,19 +296,140 @@ impl fmt::Debug for ParseError {
                 line, num_chars, expected_num_chars
             ),
 
-            ParseError::DuplicateFixedPosition { id } => {
-                write!(f, "Found duplicate fixed position '{}'.", id)
+            ParseError::DuplicateFixedPosition { id, position } => write!(
+                f,
+                "Found duplicate fixed position '{}' at ({},{}).",
+                id, position.x, position.y
+            ),
+
+            ParseError::UnknownCharacter {
+                character,
+                line,
+                column,
+            } => write!(
+                f,
+                "Unexpected character '{}' on line {} column {}.",
+                character, line, column
+            ),
+
+            ParseError::MissingBorder { line } => {
+                write!(
+                    f,
+                    "Line {} is a row of cells with no wall line below it.",
+                    line
+                )
+            }
+        }
+    }
+}
+
+pub enum BuildError {
+    InvalidDimensions {
+        width: i32,
+        height: i32,
+    },
+    OutOfBounds {
+        position: Position,
+        width: i32,
+        height: i32,
+    },
+    DuplicateFixedPosition {
+        id: char,
+        position: Position,
+    },
+}
+
+impl fmt::Debug for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BuildError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid world dimensions ({},{}).", width, height)
+            }
+
+            BuildError::OutOfBounds {
+                position,
+                width,
+                height,
+            } => write!(
+                f,
+                "Position ({},{}) is outside of world (width, height) ({},{}).",
+                position.x, position.y, width, height
+            ),
+
+            BuildError::DuplicateFixedPosition { id, position } => write!(
+                f,
+                "Fixed position '{}' at ({},{}) duplicates an existing id or position.",
+                id, position.x, position.y
+            ),
+        }
+    }
+}
+
+pub enum EditError {
+    OutOfBounds { position: Position },
+    // The border has no cell beyond it to keep consistent.
+    BorderWall { position: Position, side: Side },
+    // The new wall would leave its two cells with no path between them.
+    Disconnects { position: Position, side: Side },
+    UnknownFixedPosition { id: char },
+    Occupied { position: Position, id: char },
+}
+
+impl fmt::Debug for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EditError::OutOfBounds { position } => write!(
+                f,
+                "Position ({},{}) is outside of the world.",
+                position.x, position.y
+            ),
+            EditError::BorderWall { position, side } => write!(
+                f,
+                "The {:?} wall of ({},{}) is on the border and cannot be toggled.",
+                side, position.x, position.y
+            ),
+            EditError::Disconnects { position, side } => write!(
+                f,
+                "A wall on the {:?} side of ({},{}) would disconnect the world.",
+                side, position.x, position.y
+            ),
+            EditError::UnknownFixedPosition { id } => {
+                write!(f, "There is no fixed position '{}'.", id)
             }
+            EditError::Occupied { position, id } => write!(
+                f,
+                "Position ({},{}) is already fixed position '{}'.",
+                position.x, position.y, id
+            ),
         }
     }
 }
 
 impl World {
+    pub fn build_from_str_default(source: &str) -> Result<World, Error> {
+        World::build_from_str(source, Costs::default())
+    }
+
+    // Lines starting with '#' are comments and are dropped before parsing,
+    // as is trailing whitespace, so line numbers in parse errors count map
+    // lines only.  Blank lines are kept, as they are the wall lines of a
+    // world without borders.
     pub fn build_from_str(source: &str, costs: Costs) -> Result<World, Error> {
-        let mut lines = source.lines();
+        let map_lines: Vec<&str> = source
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .map(str::trim_end)
+            .collect();
+        let mut lines = map_lines.iter();
 
         if let Some(first_line) = lines.next() {
-            let line_width = first_line.chars().count();
+            // Every cell holds a character, so the widest content line
+            // gives the width whether or not the world has an east border.
+            let line_width = map_lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0);
 
             let mut line_count = 1;
 
@@ -160,7 +440,7 @@ impl World {
             let mut walls = Vec::new();
 
             let mut wall_row = Vec::with_capacity(width);
-            wall_row.extend(iter::repeat(Wall::new()).take(width));
+            wall_row.extend(std::iter::repeat_n(Wall::new(), width));
             parse_wall_line(first_line, line_count, width, None, Some(&mut wall_row)).map_err(
                 |error| Error::Parse {
                     source: String::from(source),
@@ -168,9 +448,16 @@ impl World {
                 },
             )?;
 
-            while let (Some(content_line), Some(wall_line)) = (lines.next(), lines.next()) {
+            while let Some(content_line) = lines.next() {
+                let wall_line = lines.next().ok_or_else(|| Error::Parse {
+                    source: String::from(source),
+                    error: ParseError::MissingBorder {
+                        line: line_count + 1,
+                    },
+                })?;
+
                 let mut next_wall_row = Vec::with_capacity(width);
-                next_wall_row.extend(iter::repeat(Wall::new()).take(width));
+                next_wall_row.extend(std::iter::repeat_n(Wall::new(), width));
 
                 line_count += 1;
                 parse_content_line(
@@ -204,19 +491,375 @@ impl World {
 
             let height = walls.len() as i32;
 
-            Ok(World {
+            let world = World {
                 width: width as i32,
-                height: height as i32,
+                height,
                 walls,
                 fixed_positions,
 
                 costs,
-            })
+                delivery_rewards: Vec::new(),
+                destination_churn: 0.0,
+                passenger_wander: 0.0,
+                pickup_refusal: 0.0,
+                env_rng: EnvRng::default(),
+                goto_actions: false,
+            };
+
+            world.check_connectivity()?;
+
+            Ok(world)
         } else {
             Err(Error::EmptyString)
         }
     }
 
+    // The fixed positions each reach a set of the others, and the largest
+    // set is taken as the world, so the error names a position cut off
+    // from it rather than one of the positions it cannot reach.  Ties go
+    // to the position listed first.
+    fn check_connectivity(&self) -> Result<(), Error> {
+        let reached: Vec<Vec<bool>> = self
+            .fixed_positions
+            .iter()
+            .map(|fp| {
+                let distances = self.distances_from(fp.position);
+
+                self.fixed_positions
+                    .iter()
+                    .map(|other| {
+                        distances[other.position.y as usize][other.position.x as usize].is_some()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let count = |component: &Vec<bool>| component.iter().filter(|r| **r).count();
+
+        let mut main_component: Option<&Vec<bool>> = None;
+        for component in &reached {
+            if main_component.is_none_or(|main| count(component) > count(main)) {
+                main_component = Some(component);
+            }
+        }
+
+        if let Some(main_component) = main_component {
+            for (fp, is_reached) in self.fixed_positions.iter().zip(main_component) {
+                if !is_reached {
+                    return Err(Error::Unreachable(fp.id));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Cells the taxi can never drive from to a fixed position, in row
+    // order.  Every fixed position reaches the others, so checking from
+    // the first is enough.
+    pub fn stranded_cells(&self) -> Vec<Position> {
+        let distances = match self.fixed_positions.first() {
+            Some(first) => self.distances_from(first.position),
+            None => return Vec::new(),
+        };
+
+        let mut result = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if distances[y as usize][x as usize].is_none() {
+                    result.push(Position::new(x, y));
+                }
+            }
+        }
+
+        result
+    }
+
+    // The fewest moves from one cell to another, None when walls keep them
+    // apart or either is outside the world.
+    pub fn distance(&self, from: Position, to: Position) -> Option<usize> {
+        if !self.is_inside(from) || !self.is_inside(to) {
+            return None;
+        }
+
+        self.distances_from(from)[to.y as usize][to.x as usize]
+    }
+
+    fn is_inside(&self, position: Position) -> bool {
+        position.x >= 0 && position.x < self.width && position.y >= 0 && position.y < self.height
+    }
+
+    // Adds the wall if it is missing, removes it if present.  Both cells
+    // either side of it are updated.  A new wall is refused when it would
+    // leave no path between those two cells.
+    pub fn toggle_wall(&mut self, position: Position, side: Side) -> Result<(), Error> {
+        if !self.is_inside(position) {
+            return Err(Error::Edit(EditError::OutOfBounds { position }));
+        }
+
+        let neighbour = position
+            + match side {
+                Side::North => Position::new(0, -1),
+                Side::South => Position::new(0, 1),
+                Side::East => Position::new(1, 0),
+                Side::West => Position::new(-1, 0),
+            };
+
+        if !self.is_inside(neighbour) {
+            return Err(Error::Edit(EditError::BorderWall { position, side }));
+        }
+
+        let wall = self.get_wall(position);
+        let present = match side {
+            Side::North => wall.north,
+            Side::South => wall.south,
+            Side::East => wall.east,
+            Side::West => wall.west,
+        };
+
+        set_wall(&mut self.walls, position, side, !present);
+
+        if !present && self.distance(position, neighbour).is_none() {
+            set_wall(&mut self.walls, position, side, present);
+            return Err(Error::Edit(EditError::Disconnects { position, side }));
+        }
+
+        Ok(())
+    }
+
+    // Fails, leaving the world unchanged, when the new position is taken by
+    // another fixed position or cannot be reached from the others.
+    pub fn move_fixed_position(&mut self, id: char, position: Position) -> Result<(), Error> {
+        if !self.is_inside(position) {
+            return Err(Error::Edit(EditError::OutOfBounds { position }));
+        }
+
+        let index = self
+            .get_fixed_index(id)
+            .ok_or(Error::Edit(EditError::UnknownFixedPosition { id }))?;
+
+        if let Some(other_id) = self.get_fixed_id(position) {
+            if other_id != id {
+                return Err(Error::Edit(EditError::Occupied {
+                    position,
+                    id: other_id,
+                }));
+            }
+        }
+
+        let old_position = self.fixed_positions[index].position;
+        self.fixed_positions[index].position = position;
+
+        if let Err(error) = self.check_connectivity() {
+            self.fixed_positions[index].position = old_position;
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    // Keeps only the cells in the bounding box of the positions, for
+    // reproducing a problem on a smaller world.  Walls between kept cells
+    // stay as they are, and the new border is walled off.  Fixed positions
+    // outside the box are dropped.  The returned function moves a position
+    // from this world into the cropped one, so a state can be rebuilt there
+    // with State::build.
+    pub fn crop_to_bbox(
+        &self,
+        positions: &[Position],
+    ) -> Result<(World, impl Fn(Position) -> Position), Error> {
+        if let Some(&position) = positions.iter().find(|p| !self.is_inside(**p)) {
+            return Err(Error::Build(BuildError::OutOfBounds {
+                position,
+                width: self.width,
+                height: self.height,
+            }));
+        }
+
+        let min_x = positions.iter().map(|p| p.x).min();
+        let max_x = positions.iter().map(|p| p.x).max();
+        let min_y = positions.iter().map(|p| p.y).min();
+        let max_y = positions.iter().map(|p| p.y).max();
+
+        let (min_x, max_x, min_y, max_y) = match (min_x, max_x, min_y, max_y) {
+            (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) => (min_x, max_x, min_y, max_y),
+            _ => {
+                return Err(Error::Build(BuildError::InvalidDimensions {
+                    width: 0,
+                    height: 0,
+                }))
+            }
+        };
+
+        let width = max_x - min_x + 1;
+        let height = max_y - min_y + 1;
+
+        let mut walls: Vec<Vec<Wall>> = self.walls[min_y as usize..=max_y as usize]
+            .iter()
+            .map(|row| row[min_x as usize..=max_x as usize].to_vec())
+            .collect();
+
+        for wall in walls[0].iter_mut() {
+            wall.north = true;
+        }
+
+        for wall in walls[(height - 1) as usize].iter_mut() {
+            wall.south = true;
+        }
+
+        for row in walls.iter_mut() {
+            row[0].west = true;
+            row[(width - 1) as usize].east = true;
+        }
+
+        let translate = move |position: Position| position + Position::new(-min_x, -min_y);
+
+        let fixed_positions: Vec<FixedPosition> = self
+            .fixed_positions
+            .iter()
+            .filter(|fp| {
+                fp.position.x >= min_x
+                    && fp.position.x <= max_x
+                    && fp.position.y >= min_y
+                    && fp.position.y <= max_y
+            })
+            .map(|fp| FixedPosition {
+                id: fp.id,
+                position: translate(fp.position),
+            })
+            .collect();
+
+        let delivery_rewards = self
+            .delivery_rewards
+            .iter()
+            .filter(|(id, _)| fixed_positions.iter().any(|fp| fp.id == *id))
+            .cloned()
+            .collect();
+
+        let world = World {
+            width,
+            height,
+            walls,
+            fixed_positions,
+            delivery_rewards,
+            ..self.clone()
+        };
+
+        world.check_connectivity()?;
+
+        Ok((world, translate))
+    }
+
+    // The fewest actions that deliver the passenger from this state, found
+    // with a breadth first search over the deterministic transitions.  None
+    // when the passenger can never be delivered.
+    pub fn optimal_steps(&self, state: &State) -> Option<usize> {
+        // Also indexes passengers waiting away from the fixed positions.
+        let state_indexer = StateIndexer::with_passenger_cells(self);
+
+        let mut visited = vec![false; state_indexer.num_states()];
+        let mut queue = VecDeque::new();
+
+        visited[state_indexer.get_index(self, state)?] = true;
+        queue.push_back((*state, 0));
+
+        while let Some((state, steps)) = queue.pop_front() {
+            if state.at_destination() {
+                return Some(steps);
+            }
+
+            for action_index in 0..Actions::NUM_ELEMENTS {
+                let action = Actions::from_index(action_index).unwrap();
+                let (_, next_state) = state.apply_action(self, action);
+
+                if let Some(next_index) = state_indexer.get_index(self, &next_state) {
+                    if !visited[next_index] {
+                        visited[next_index] = true;
+                        queue.push_back((next_state, steps + 1));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // The optimal value of every state index of StateIndexer::new, found
+    // with value iteration over the deterministic transitions.  Delivered
+    // states and indices with no state are 0.  Sweeps stop once no value
+    // changes by more than tolerance.  None when that takes more than
+    // max_sweeps, as with gamma 1 and a state that can never deliver its
+    // passenger, whose value keeps falling.
+    pub fn optimal_values(
+        &self,
+        gamma: f64,
+        tolerance: f64,
+        max_sweeps: usize,
+    ) -> Option<Vec<f64>> {
+        let state_indexer = StateIndexer::new(self);
+        let states: Vec<Option<State>> = (0..state_indexer.num_states())
+            .map(|state_index| state_indexer.get_state(self, state_index))
+            .collect();
+
+        let mut values = vec![0.0; states.len()];
+
+        for _ in 0..max_sweeps {
+            let mut largest_change: f64 = 0.0;
+
+            for (state_index, state) in states.iter().enumerate() {
+                let state = match state {
+                    Some(state) if !state.at_destination() => state,
+                    _ => continue,
+                };
+
+                let best_value = (0..Actions::NUM_ELEMENTS)
+                    .filter_map(|action_index| {
+                        let action = Actions::from_index(action_index)?;
+                        let (reward, next_state) = state.apply_action(self, action);
+                        let next_index = state_indexer.get_index(self, &next_state)?;
+
+                        Some(reward + gamma * values[next_index])
+                    })
+                    .fold(f64::NEG_INFINITY, f64::max);
+
+                largest_change = largest_change.max((best_value - values[state_index]).abs());
+                values[state_index] = best_value;
+            }
+
+            if largest_change <= tolerance {
+                return Some(values);
+            }
+        }
+
+        None
+    }
+
+    // Number of moves needed to reach every cell from start, found with a
+    // breadth first search.  Unreachable cells are None.
+    fn distances_from(&self, start: Position) -> Vec<Vec<Option<usize>>> {
+        let mut distances = vec![vec![None; self.width as usize]; self.height as usize];
+        let mut queue = VecDeque::new();
+
+        distances[start.y as usize][start.x as usize] = Some(0);
+        queue.push_back((start, 0));
+
+        while let Some((position, distance)) = queue.pop_front() {
+            for action in &[Actions::North, Actions::South, Actions::East, Actions::West] {
+                if let ActionAffect::Move(delta) = self.determine_affect(position, *action) {
+                    let next = position + delta;
+                    let entry = &mut distances[next.y as usize][next.x as usize];
+
+                    if entry.is_none() {
+                        *entry = Some(distance + 1);
+                        queue.push_back((next, distance + 1));
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
     pub fn get_fixed_position(&self, id: char) -> Option<Position> {
         for fp in &self.fixed_positions {
             if fp.id == id {
@@ -247,8 +890,63 @@ impl World {
         None
     }
 
+    // The reward for delivering the passenger to the fixed position, which
+    // is costs.delivery_reward unless set for that destination.
+    pub fn delivery_reward(&self, id: char) -> f64 {
+        self.delivery_rewards
+            .iter()
+            .find(|(other_id, _)| *other_id == id)
+            .map_or(self.costs.delivery_reward, |&(_, reward)| reward)
+    }
+
+    // Makes some destinations worth more than others.  None goes back to
+    // costs.delivery_reward.
+    pub fn set_delivery_reward(&mut self, id: char, reward: Option<f64>) -> Result<(), Error> {
+        if self.get_fixed_index(id).is_none() {
+            return Err(Error::Edit(EditError::UnknownFixedPosition { id }));
+        }
+
+        self.delivery_rewards
+            .retain(|(other_id, _)| *other_id != id);
+        if let Some(reward) = reward {
+            self.delivery_rewards.push((id, reward));
+        }
+
+        Ok(())
+    }
+
+    // Transforms every per destination reward, for rescaling a world's
+    // rewards along with its costs.
+    pub fn map_delivery_rewards<F: Fn(f64) -> f64>(&mut self, f: F) {
+        for (_, reward) in self.delivery_rewards.iter_mut() {
+            *reward = f(*reward);
+        }
+    }
+
     pub fn max_reward(&self) -> f64 {
-        0.0
+        self.delivery_rewards
+            .iter()
+            .map(|&(_, reward)| reward)
+            .fold(self.costs.max_reward(), f64::max)
+    }
+
+    pub fn min_reward(&self) -> f64 {
+        self.delivery_rewards
+            .iter()
+            .map(|&(_, reward)| reward)
+            .fold(self.costs.min_reward(), f64::min)
+    }
+
+    // Maps a step reward into [0, 1] by the world's reward range.  When
+    // every step has the same reward there is no range, so all map to 0.
+    pub fn normalize_reward(&self, reward: f64) -> f64 {
+        let range = self.max_reward() - self.min_reward();
+
+        if range > 0.0 {
+            (reward - self.min_reward()) / range
+        } else {
+            0.0
+        }
     }
 
     pub fn num_fixed_positions(&self) -> usize {
@@ -316,7 +1014,133 @@ impl World {
                     ActionAffect::Invalid
                 }
             }
+
+            Actions::Wait => ActionAffect::Wait,
+        }
+    }
+
+    // Every action except the moves blocked by a wall or the edge of the
+    // world, which would leave the taxi where it is.
+    pub fn available_actions(&self, position: Position) -> Vec<Actions> {
+        (0..Actions::NUM_ELEMENTS)
+            .filter_map(Actions::from_index)
+            .filter(|action| match action {
+                Actions::North | Actions::South | Actions::East | Actions::West => !matches!(
+                    self.determine_affect(position, *action),
+                    ActionAffect::Invalid
+                ),
+                _ => true,
+            })
+            .collect()
+    }
+
+    // The true, deterministic model of the world, for planners that need
+    // (s, a) -> (s', r).  This is the same step a learner takes with
+    // State::apply_action, just with the result in planning order.
+    pub fn model_step(&self, state: &State, action: Actions) -> (State, f64) {
+        let (reward, next_state) = state.apply_action(self, action);
+        (next_state, reward)
+    }
+
+    // Describes every difference between the two worlds, empty when they
+    // are equal.
+    pub fn diff(&self, other: &World) -> Vec<String> {
+        let mut result = Vec::new();
+
+        if self.width != other.width || self.height != other.height {
+            result.push(format!(
+                "dimensions ({},{}) != ({},{})",
+                self.width, self.height, other.width, other.height
+            ));
+        } else {
+            let mut fixed_ids_match = true;
+
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let position = Position::new(x, y);
+
+                    let wall = self.get_wall(position);
+                    let other_wall = other.get_wall(position);
+                    if wall != other_wall {
+                        result.push(format!("({},{}): {} != {}", x, y, wall, other_wall));
+                    }
+
+                    let id = self.get_fixed_id(position);
+                    let other_id = other.get_fixed_id(position);
+                    if id != other_id {
+                        fixed_ids_match = false;
+                        result.push(format!(
+                            "({},{}): fixed position {} != {}",
+                            x,
+                            y,
+                            describe_fixed_id(id),
+                            describe_fixed_id(other_id)
+                        ));
+                    }
+                }
+            }
+
+            if fixed_ids_match {
+                for (i, (fp, other_fp)) in self
+                    .fixed_positions
+                    .iter()
+                    .zip(other.fixed_positions.iter())
+                    .enumerate()
+                {
+                    if fp.id != other_fp.id {
+                        result.push(format!(
+                            "fixed position index {}: '{}' != '{}'",
+                            i, fp.id, other_fp.id
+                        ));
+                    }
+                }
+            }
+        }
+
+        if self.costs != other.costs {
+            result.push(format!("costs {:?} != {:?}", self.costs, other.costs));
+        }
+
+        if self.delivery_rewards != other.delivery_rewards {
+            result.push(format!(
+                "delivery rewards {:?} != {:?}",
+                self.delivery_rewards, other.delivery_rewards
+            ));
         }
+
+        if self.destination_churn != other.destination_churn {
+            result.push(format!(
+                "destination churn {} != {}",
+                self.destination_churn, other.destination_churn
+            ));
+        }
+
+        if self.passenger_wander != other.passenger_wander {
+            result.push(format!(
+                "passenger wander {} != {}",
+                self.passenger_wander, other.passenger_wander
+            ));
+        }
+
+        if self.pickup_refusal != other.pickup_refusal {
+            result.push(format!(
+                "pickup refusal {} != {}",
+                self.pickup_refusal, other.pickup_refusal
+            ));
+        }
+
+        if self.env_rng != other.env_rng {
+            result.push(format!("{:?} != {:?}", self.env_rng, other.env_rng));
+        }
+
+        if self.goto_actions != other.goto_actions {
+            result.push(format!(
+                "goto actions {} != {}",
+                self.goto_actions, other.goto_actions
+            ));
+        }
+
+        result
     }
 
     pub fn display(&self) -> String {
@@ -343,11 +1167,7 @@ impl World {
             let mut previous_wall = None;
 
             for (x, w) in row.iter().enumerate() {
-                let upper_wall = if let Some(previous_row) = previous_row {
-                    Some(&previous_row[x])
-                } else {
-                    None
-                };
+                let upper_wall = previous_row.map(|previous_row| &previous_row[x]);
 
                 upper_chars.push(calc_upper_left_char(*w, previous_wall, upper_wall));
                 upper_chars.push(if w.north { '─' } else { ' ' });
@@ -369,11 +1189,8 @@ impl World {
             }
 
             if let Some(w) = previous_wall {
-                let upper_wall = if let Some(previous_row) = previous_row {
-                    Some(&previous_row[(self.width - 1) as usize])
-                } else {
-                    None
-                };
+                let upper_wall =
+                    previous_row.map(|previous_row| &previous_row[(self.width - 1) as usize]);
 
                 upper_chars.push(calc_upper_right_char(*w, upper_wall));
                 chars.push(if w.east { '│' } else { ' ' });
@@ -404,6 +1221,31 @@ impl World {
 
         result
     }
+
+    // The same grid as display_strings, one character for one, drawn with
+    // '+', '-' and '|' for terminals that mangle box drawing characters.
+    pub fn display_strings_ascii(&self) -> Vec<String> {
+        self.display_strings()
+            .iter()
+            .map(|line| line.chars().map(ascii_wall_char).collect())
+            .collect()
+    }
+}
+
+fn ascii_wall_char(c: char) -> char {
+    match c {
+        '─' => '-',
+        '│' => '|',
+        c if is_box_drawing(c) => '+',
+        c => c,
+    }
+}
+
+fn describe_fixed_id(id: Option<char>) -> String {
+    match id {
+        Some(id) => format!("'{}'", id),
+        None => String::from("none"),
+    }
 }
 
 fn calc_upper_left_char(
@@ -516,6 +1358,10 @@ fn render_connection(
     }
 }
 
+fn is_box_drawing(c: char) -> bool {
+    ('\u{2500}'..='\u{257f}').contains(&c)
+}
+
 fn parse_wall_line(
     line: &str,
     line_count: usize,
@@ -523,21 +1369,32 @@ fn parse_wall_line(
     mut previous_row: Option<&mut [Wall]>,
     mut row: Option<&mut [Wall]>,
 ) -> Result<(), ParseError> {
-    let mut num_chars_read = 0;
     let expected_num_chars = 2 * width + 1;
 
-    for (i, c) in line.chars().enumerate() {
-        num_chars_read += 1;
+    // Trailing whitespace has been trimmed, so the line may be short.
+    let num_chars = line.chars().count();
+    if num_chars > expected_num_chars {
+        return Err(ParseError::LineTooLong {
+            line: line_count,
+            num_chars,
+            expected_num_chars,
+        });
+    }
 
-        if num_chars_read > expected_num_chars {
-            break;
+    for (i, c) in line.chars().enumerate() {
+        // Anything but '─' leaves a wall segment open, but another line
+        // drawing character there is almost certainly a misplaced wall.
+        if i % 2 == 1 && c != '─' && is_box_drawing(c) {
+            return Err(ParseError::UnknownCharacter {
+                character: c,
+                line: line_count,
+                column: i + 1,
+            });
         }
 
         if i % 2 == 1 {
             let x = i / 2;
 
-            if x > width {}
-
             if c == '─' {
                 if let Some(ref mut prev) = previous_row {
                     (*prev)[x].south = true;
@@ -550,46 +1407,53 @@ fn parse_wall_line(
         }
     }
 
-    if num_chars_read == expected_num_chars {
-        Ok(())
-    } else {
-        Err(ParseError::LineTooLong {
-            line: line_count,
-            num_chars: line.chars().count(),
-            expected_num_chars,
-        })
-    }
+    Ok(())
 }
 
 fn parse_content_line(
     line: &str,
     line_count: usize,
     width: usize,
-    wall_row: &mut Vec<Wall>,
+    wall_row: &mut [Wall],
     fixed_positions: &mut Vec<FixedPosition>,
 ) -> Result<(), ParseError> {
-    let mut num_chars_read = 0;
     let expected_num_chars = 2 * width + 1;
 
+    // Only the east border may be missing, as trailing whitespace has been
+    // trimmed.  Every cell must be there.
+    let num_chars = line.chars().count();
+    if num_chars > expected_num_chars || num_chars + 1 < expected_num_chars {
+        return Err(ParseError::LineTooLong {
+            line: line_count,
+            num_chars,
+            expected_num_chars,
+        });
+    }
+
     let y = (line_count - 1) / 2;
 
     for (i, c) in line.chars().enumerate() {
-        num_chars_read += 1;
-
-        if num_chars_read > expected_num_chars {
-            break;
-        }
-
         let x = i / 2;
 
         if i % 2 == 1 {
+            if c.is_whitespace() || c.is_control() || is_box_drawing(c) {
+                return Err(ParseError::UnknownCharacter {
+                    character: c,
+                    line: line_count,
+                    column: i + 1,
+                });
+            }
+
             // odd characters are points themselves
             if c != '.' {
                 // for now, ignore the taxi, passenger, and destination characters.
                 if c != 't' && c != 'T' && c != 'd' && c != 'D' && c != 'p' {
                     for fp in fixed_positions.iter() {
                         if fp.id == c {
-                            return Err(ParseError::DuplicateFixedPosition { id: c });
+                            return Err(ParseError::DuplicateFixedPosition {
+                                id: c,
+                                position: Position::new(x as i32, y as i32),
+                            });
                         }
                     }
                 }
@@ -599,6 +1463,12 @@ fn parse_content_line(
                     position: Position::new(x as i32, y as i32),
                 })
             }
+        } else if c != '│' && c != ' ' {
+            return Err(ParseError::UnknownCharacter {
+                character: c,
+                line: line_count,
+                column: i + 1,
+            });
         } else if c == '│' {
             // even characters can only be walls
             if x < width {
@@ -611,21 +1481,14 @@ fn parse_content_line(
         }
     }
 
-    if num_chars_read == expected_num_chars {
-        Ok(())
-    } else {
-        Err(ParseError::LineTooLong {
-            line: line_count,
-            num_chars: line.chars().count(),
-            expected_num_chars,
-        })
-    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test_world {
 
     use super::*;
+    use crate::state::StateIterator;
 
     #[test]
     fn build_correct_height() {
@@ -644,6 +1507,57 @@ mod test_world {
         assert_matches!(res, Ok(World { height: 4, .. }))
     }
 
+    #[test]
+    fn build_ignores_comments() {
+        let stripped = "\
+                        ┌───┬─────┐\n\
+                        │R .│. . G│\n\
+                        │   │     │\n\
+                        │. .│. . .│\n\
+                        │         │\n\
+                        │. . . . .│\n\
+                        │         │\n\
+                        │.│. .│. .│\n\
+                        │ │   │   │\n\
+                        │Y│. .│B .│\n\
+                        └─┴───┴───┘\n\
+                        ";
+        let commented = "\
+                         # The standard taxi world.\n\
+                         ┌───┬─────┐\n\
+                         │R .│. . G│   \n\
+                         │   │     │\n\
+                         # Open middle row.\n\
+                         │. .│. . .│\n\
+                         │         │\n\
+                         │. . . . .│\n\
+                         │         │\t\n\
+                         │.│. .│. .│\n\
+                           # Indented comment.\n\
+                         │ │   │   │\n\
+                         │Y│. .│B .│\n\
+                         └─┴───┴───┘\n\
+                         #\n\
+                         ";
+
+        let expected = World::build_from_str(stripped, Costs::default()).unwrap();
+        let w = World::build_from_str(commented, Costs::default()).unwrap();
+
+        assert_eq!(w, expected);
+        assert_eq!(w.display(), expected.display());
+
+        assert_matches!(
+            World::build_from_str("# Nothing but a comment.\n", Costs::default()),
+            Err(Error::EmptyString)
+        );
+
+        // A content line missing a cell is still an error.
+        assert_matches!(
+            World::build_from_str("┌───┐\n│R│\n└───┘\n", Costs::default()),
+            Err(Error::Parse { .. })
+        );
+    }
+
     #[test]
     fn build_correct_width() {
         let source = "\
@@ -792,6 +1706,12 @@ mod test_world {
             fixed_positions: vec![],
 
             costs: Costs::default(),
+            delivery_rewards: vec![],
+            destination_churn: 0.0,
+            passenger_wander: 0.0,
+            pickup_refusal: 0.0,
+            env_rng: EnvRng::default(),
+            goto_actions: false,
         }
     }
 
@@ -810,7 +1730,7 @@ mod test_world {
 
         let costs = Costs::default();
         match World::build_from_str(source, costs) {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(w) => {
                 assert_eq!(w, expected_w);
             }
@@ -843,7 +1763,7 @@ mod test_world {
 
         let costs = Costs::default();
         match World::build_from_str(source, costs) {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(w) => {
                 assert_eq!(w, expected_w);
             }
@@ -888,7 +1808,7 @@ mod test_world {
 
         let costs = Costs::default();
         match World::build_from_str(source, costs) {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(w) => {
                 assert_eq!(w, expected_w);
             }
@@ -954,7 +1874,7 @@ mod test_world {
 
         let costs = Costs::default();
         match World::build_from_str(source, costs) {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(w) => {
                 assert_eq!(w, expected_w);
             }
@@ -1001,10 +1921,114 @@ mod test_world {
 
         let costs = Costs::default();
         match World::build_from_str(source, costs) {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(w) => {
                 assert_eq!(w, expected_w);
             }
         }
     }
+
+    #[test]
+    fn model_step_matches_apply_action() {
+        let source = "\
+                      ┌───┬─┐\n\
+                      │R .│G│\n\
+                      │   │ │\n\
+                      │.│. .│\n\
+                      └─┴───┘\n\
+                      ";
+        let w = World::build_from_str(source, Costs::default()).unwrap();
+
+        for state in StateIterator::new(&w) {
+            for action_index in 0..Actions::NUM_ELEMENTS {
+                let action = Actions::from_index(action_index).unwrap();
+
+                let (reward, next_state) = state.apply_action(&w, action);
+                assert_eq!(w.model_step(&state, action), (next_state, reward));
+            }
+        }
+    }
+
+    #[test]
+    fn standard_worlds_build() {
+        let names = World::standard_names();
+        assert!(names.contains(&"dietterich5x5"));
+
+        for name in names {
+            let w = World::standard(name).unwrap();
+            assert!(w.num_fixed_positions() >= 4, "{}", name);
+        }
+
+        let w = World::standard("dietterich5x5").unwrap();
+        assert_eq!(w.width, 5);
+        assert_eq!(w.height, 5);
+        assert_eq!(w.get_fixed_position('Y'), Some(Position::new(0, 4)));
+    }
+
+    #[test]
+    fn unknown_standard_world() {
+        assert!(World::standard("no such world").is_none());
+    }
+
+    #[test]
+    fn optimal_values_by_hand() {
+        let mut w = World::standard("small3x3").unwrap();
+        w.set_delivery_reward('G', Some(20.0)).unwrap();
+        let values = w.optimal_values(0.5, 1e-9, 10_000).unwrap();
+        let state_indexer = StateIndexer::new(&w);
+        let value = |state: State| values[state_indexer.get_index(&w, &state).unwrap()];
+
+        let delivery = 20.0;
+        let movement = w.costs.movement;
+
+        // Carrying the passenger on G, or one step east of it.
+        let at_g = State::build(&w, (2, 0), None, 'G').unwrap();
+        assert!(approx_eq!(f64, value(at_g), delivery, ulps = 2));
+
+        let beside_g = State::build(&w, (1, 0), None, 'G').unwrap();
+        assert!(approx_eq!(
+            f64,
+            value(beside_g),
+            movement + 0.5 * delivery,
+            ulps = 2
+        ));
+
+        let delivered = State::build(&w, (2, 0), Some('G'), 'G').unwrap();
+        assert_eq!(value(delivered), 0.0);
+    }
+
+    #[test]
+    fn optimal_values_stranded_cell() {
+        let source = "\
+                      ┌─────┐\n\
+                      │R G│.│\n\
+                      └─────┘\n\
+                      ";
+        let w = World::build_from_str(source, Costs::default()).unwrap();
+
+        // A taxi on the walled off cell can never deliver, so with gamma 1 its
+        // value falls forever and the sweeps run out.
+        assert_eq!(w.optimal_values(1.0, 1e-9, 1000), None);
+
+        let values = w.optimal_values(0.5, 1e-9, 1000).unwrap();
+        let state_indexer = StateIndexer::new(&w);
+        let stranded = State::build(&w, (2, 0), Some('R'), 'G').unwrap();
+        let stranded_value = values[state_indexer.get_index(&w, &stranded).unwrap()];
+
+        // Every action leaves it where it is, so it repeats the best of them.
+        let best_reward = (0..Actions::NUM_ELEMENTS)
+            .map(|action_index| {
+                let action = Actions::from_index(action_index).unwrap();
+                let (reward, next_state) = stranded.apply_action(&w, action);
+                assert_eq!(next_state, stranded);
+                reward
+            })
+            .fold(f64::NEG_INFINITY, f64::max);
+        assert!(approx_eq!(
+            f64,
+            stranded_value,
+            best_reward / (1.0 - 0.5),
+            epsilon = 1e-6
+        ));
+    }
 }
@@ -0,0 +1,125 @@
+use crate::position::Position;
+use crate::world::{set_wall, BuildError, Costs, EnvRng, Error, FixedPosition, Wall, World};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    North,
+    South,
+    East,
+    West,
+}
+
+// Builds a world one wall and fixed position at a time.  The world starts
+// with walls all around its border, like the box drawn worlds.
+#[derive(Debug, Clone)]
+pub struct WorldBuilder {
+    width: i32,
+    height: i32,
+    walls: Vec<(Position, Side)>,
+    fixed_positions: Vec<(char, Position)>,
+    costs: Costs,
+}
+
+impl WorldBuilder {
+    pub fn new(width: i32, height: i32) -> WorldBuilder {
+        WorldBuilder {
+            width,
+            height,
+            walls: Vec::new(),
+            fixed_positions: Vec::new(),
+            costs: Costs::default(),
+        }
+    }
+
+    pub fn add_wall(&mut self, position: Position, side: Side) -> &mut WorldBuilder {
+        self.walls.push((position, side));
+        self
+    }
+
+    pub fn add_fixed_position(&mut self, id: char, position: Position) -> &mut WorldBuilder {
+        self.fixed_positions.push((id, position));
+        self
+    }
+
+    pub fn costs(&mut self, costs: Costs) -> &mut WorldBuilder {
+        self.costs = costs;
+        self
+    }
+
+    pub fn build(&self) -> Result<World, Error> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err(Error::Build(BuildError::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+            }));
+        }
+
+        let mut walls = vec![vec![Wall::new(); self.width as usize]; self.height as usize];
+
+        for x in 0..self.width {
+            walls[0][x as usize].north = true;
+            walls[(self.height - 1) as usize][x as usize].south = true;
+        }
+
+        for row in walls.iter_mut() {
+            row[0].west = true;
+            row[(self.width - 1) as usize].east = true;
+        }
+
+        for &(position, side) in &self.walls {
+            self.check_bounds(position)?;
+
+            set_wall(&mut walls, position, side, true);
+        }
+
+        let mut fixed_positions: Vec<FixedPosition> = Vec::new();
+
+        for &(id, position) in &self.fixed_positions {
+            self.check_bounds(position)?;
+
+            if fixed_positions
+                .iter()
+                .any(|fp| fp.id == id || fp.position == position)
+            {
+                return Err(Error::Build(BuildError::DuplicateFixedPosition {
+                    id,
+                    position,
+                }));
+            }
+
+            fixed_positions.push(FixedPosition { id, position });
+        }
+
+        let world = World {
+            width: self.width,
+            height: self.height,
+            walls,
+            fixed_positions,
+
+            costs: self.costs,
+            delivery_rewards: Vec::new(),
+            destination_churn: 0.0,
+            passenger_wander: 0.0,
+            pickup_refusal: 0.0,
+            env_rng: EnvRng::default(),
+            goto_actions: false,
+        };
+
+        world.check_connectivity()?;
+
+        Ok(world)
+    }
+
+    fn check_bounds(&self, position: Position) -> Result<(), Error> {
+        if position.x < 0 || position.x >= self.width || position.y < 0 || position.y >= self.height
+        {
+            Err(Error::Build(BuildError::OutOfBounds {
+                position,
+                width: self.width,
+                height: self.height,
+            }))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::world::{Costs, World};
+
+// The named worlds every experiment can refer to, so benches, configs and
+// tests agree on the layouts.  The first is the world from Dietterich's
+// MaxQ paper, then a small one for quick runs and two harder layouts.
+const STANDARD_WORLDS: &[(&str, &str)] = &[
+    (
+        "dietterich5x5",
+        "\
+         ┌───┬─────┐\n\
+         │R .│. . G│\n\
+         │   │     │\n\
+         │. .│. . .│\n\
+         │         │\n\
+         │. . . . .│\n\
+         │         │\n\
+         │.│. .│. .│\n\
+         │ │   │   │\n\
+         │Y│. .│B .│\n\
+         └─┴───┴───┘\n\
+         ",
+    ),
+    (
+        "small3x3",
+        "\
+         ┌─┬───┐\n\
+         │R│. G│\n\
+         │ │   │\n\
+         │. . .│\n\
+         │     │\n\
+         │Y B .│\n\
+         └─────┘\n\
+         ",
+    ),
+    (
+        "rooms10x10",
+        "\
+         ┌─────┬─────────┬───┐\n\
+         │R . .│. . . . .│. G│\n\
+         │     │         │   │\n\
+         │. . .│. . W . .│. .│\n\
+         │     │         │   │\n\
+         │. . . . . . . . . .│\n\
+         │                   │\n\
+         │. . . . . . . . . .│\n\
+         │                   │\n\
+         │. . . . . . . . . .│\n\
+         │          ─────    │\n\
+         │. . .│. . . .│. . .│\n\
+         │     │       │     │\n\
+         │C . .│. . . .│. . M│\n\
+         │     │       │     │\n\
+         │. . .│. . . .│. . .│\n\
+         │     │       │     │\n\
+         │. . .│. P . .│. . .│\n\
+         │     │       │     │\n\
+         │Y . .│. . . .│B . .│\n\
+         └─────┴───────┴─────┘\n\
+         ",
+    ),
+    (
+        "maze6x6",
+        "\
+         ┌───────────┐\n\
+         │R . . . . G│\n\
+         ├─────────  │\n\
+         │. . . . .│.│\n\
+         │  ─────  │ │\n\
+         │. Y . .│.│.│\n\
+         │       │ │ │\n\
+         │. . B .│. .│\n\
+         │ ┌─────┘   │\n\
+         │. . . . . .│\n\
+         │  ───────  │\n\
+         │. . . . . .│\n\
+         └───────────┘\n\
+         ",
+    ),
+];
+
+impl World {
+    // One of the standard worlds with the default costs, None when no
+    // world has that name.
+    pub fn standard(name: &str) -> Option<World> {
+        STANDARD_WORLDS
+            .iter()
+            .find(|(standard_name, _)| *standard_name == name)
+            .map(|(_, source)| {
+                World::build_from_str(source, Costs::default()).expect("Standard worlds are valid.")
+            })
+    }
+
+    pub fn standard_names() -> Vec<&'static str> {
+        STANDARD_WORLDS.iter().map(|(name, _)| *name).collect()
+    }
+}
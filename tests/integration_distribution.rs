@@ -139,3 +139,54 @@ fn measures_combine() {
         assert!(result_std_dev.approx_eq_ulps(&base_line_std_dev, 3));
     }
 }
+
+#[test]
+fn moments_of_large_values() {
+    // Repeating 0..10 on top of a large offset, where a naive sum of
+    // squares loses every digit of the spread.
+    let offset = 1.0e9;
+    let count = 1_000_000;
+
+    let mut measurement = MeasureDistribution::default();
+
+    for i in 0..count {
+        measurement.add_value(offset + (i % 10) as f64);
+    }
+
+    // The population variance of 0..10 is (10^2 - 1) / 12.
+    let expected_variance = 99.0 / 12.0;
+    let n = count as f64;
+
+    assert!((measurement.mean() - (offset + 4.5)).abs() < 1.0e-6);
+
+    assert!(measurement.variance() >= 0.0);
+    assert!((measurement.variance() - expected_variance).abs() < 1.0e-6);
+
+    let expected_sample_variance = expected_variance * n / (n - 1.0);
+    assert!((measurement.sample_variance() - expected_sample_variance).abs() < 1.0e-6);
+
+    let (mean, std_dev) = measurement.get_distribution();
+    assert!(mean.approx_eq_ulps(&measurement.mean(), 1));
+    assert!(std_dev.approx_eq_ulps(&measurement.sample_variance().sqrt(), 1));
+}
+
+#[test]
+fn moments_of_few_values() {
+    let mut measurement = MeasureDistribution::default();
+
+    assert!(measurement.mean().is_nan());
+    assert!(measurement.variance().is_nan());
+    assert!(measurement.sample_variance().is_nan());
+
+    measurement.add_value(3.0);
+
+    assert!(measurement.mean().approx_eq_ulps(&3.0, 1));
+    assert!(measurement.variance().approx_eq_ulps(&0.0, 1));
+    assert!(measurement.sample_variance().is_nan());
+
+    measurement.add_value(5.0);
+
+    assert!(measurement.mean().approx_eq_ulps(&4.0, 1));
+    assert!(measurement.variance().approx_eq_ulps(&1.0, 1));
+    assert!(measurement.sample_variance().approx_eq_ulps(&2.0, 1));
+}
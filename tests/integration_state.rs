@@ -3,7 +3,6 @@ use taxi::state::*;
 use taxi::world::{Costs, World};
 
 #[test]
-#[should_panic(expected = "'C'")]
 fn build_fails_unknown_passenger() {
     let source_world = "\
                         ┌───┬─────┐\n\
@@ -21,11 +20,11 @@ fn build_fails_unknown_passenger() {
 
     let costs = Costs::default();
     let w = World::build_from_str(source_world, costs).unwrap();
-    State::build(&w, (1, 3), Some('C'), 'B').unwrap();
+    let res = State::build(&w, (1, 3), Some('C'), 'B');
+    assert!(matches!(res, Err(Error::InvalidPassenger { id: 'C', .. })));
 }
 
 #[test]
-#[should_panic(expected = "'Q'")]
 fn build_fails_unknown_destination() {
     let source_world = "\
                         ┌───┬─────┐\n\
@@ -43,11 +42,14 @@ fn build_fails_unknown_destination() {
 
     let costs = Costs::default();
     let w = World::build_from_str(source_world, costs).unwrap();
-    State::build(&w, (1, 3), Some('Y'), 'Q').unwrap();
+    let res = State::build(&w, (1, 3), Some('Y'), 'Q');
+    assert!(matches!(
+        res,
+        Err(Error::InvalidDestination { id: 'Q', .. })
+    ));
 }
 
 #[test]
-#[should_panic(expected = "(1,6)")]
 fn build_fails_invalid_taxi() {
     let source_world = "\
                         ┌───┬─────┐\n\
@@ -65,7 +67,17 @@ fn build_fails_invalid_taxi() {
 
     let costs = Costs::default();
     let w = World::build_from_str(source_world, costs).unwrap();
-    State::build(&w, (1, 6), Some('R'), 'B').unwrap();
+    let res = State::build(&w, (1, 6), Some('R'), 'B');
+    assert!(matches!(
+        res,
+        Err(Error::InvalidTaxi {
+            taxi_pos: (1, 6),
+            world_dims: (5, 5)
+        })
+    ));
+
+    let res = State::build(&w, (-1, 0), Some('R'), 'B');
+    assert!(matches!(res, Err(Error::InvalidTaxi { .. })));
 }
 
 #[test]
@@ -224,9 +236,9 @@ fn move_top_north() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 0), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_north) = state.apply_action(&w, Actions::North);
                 assert_eq!(expected_north, state_north.display(&w));
@@ -267,9 +279,9 @@ fn move_wall_north() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 3), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_north) = state.apply_action(&w, Actions::North);
                 assert_eq!(expected_north, state_north.display(&w));
@@ -310,9 +322,9 @@ fn move_allowed_south() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (3, 1), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_south) = state.apply_action(&w, Actions::South);
                 assert_eq!(expected_south, state_south.display(&w));
@@ -353,9 +365,9 @@ fn move_bottom_south() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (0, 4), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_south) = state.apply_action(&w, Actions::South);
                 assert_eq!(expected_south, state_south.display(&w));
@@ -396,9 +408,9 @@ fn move_wall_south() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 2), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_south) = state.apply_action(&w, Actions::South);
                 assert_eq!(expected_south, state_south.display(&w));
@@ -439,9 +451,9 @@ fn move_allowed_east() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 2), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_east) = state.apply_action(&w, Actions::East);
                 assert_eq!(expected_east, state_east.display(&w));
@@ -482,9 +494,9 @@ fn move_right_east() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (3, 1), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_east) = state.apply_action(&w, Actions::East);
                 assert_eq!(expected_east, state_east.display(&w));
@@ -525,9 +537,9 @@ fn move_wall_east() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 1), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_east) = state.apply_action(&w, Actions::East);
                 assert_eq!(expected_east, state_east.display(&w));
@@ -568,9 +580,9 @@ fn move_allowed_west() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 1), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_west) = state.apply_action(&w, Actions::West);
                 assert_eq!(expected_west, state_west.display(&w));
@@ -611,9 +623,9 @@ fn move_left_west() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (1, 2), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_west) = state.apply_action(&w, Actions::West);
                 assert_eq!(expected_west, state_west.display(&w));
@@ -654,9 +666,9 @@ fn move_wall_west() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (3, 4), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 let (_, state_west) = state.apply_action(&w, Actions::West);
                 assert_eq!(expected_west, state_west.display(&w));
@@ -695,32 +707,32 @@ fn reaches_destination() {
 
     let costs = Costs::default();
     match World::build_from_str(source_world, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(w) => match State::build(&w, (2, 1), Some('R'), 'G') {
-            Err(msg) => panic!(msg),
+            Err(msg) => panic!("{:?}", msg),
             Ok(state) => {
                 println!();
                 println!("{}", state.display(&w));
 
                 let (_, result0) = state.apply_action(&w, Actions::East);
                 println!("0:\n{}", result0.display(&w));
-                assert_eq!(result0.at_destination(), false);
+                assert!(!result0.at_destination());
 
                 let (_, result1) = result0.apply_action(&w, Actions::PickUp);
                 println!("1:\n{}", result1.display(&w));
-                assert_eq!(result1.at_destination(), false);
+                assert!(!result1.at_destination());
 
                 let (_, result2) = result1.apply_action(&w, Actions::South);
                 println!("2:\n{}", result2.display(&w));
-                assert_eq!(result2.at_destination(), false);
+                assert!(!result2.at_destination());
 
                 let (_, result3) = result2.apply_action(&w, Actions::South);
                 println!("3:\n{}", result3.display(&w));
-                assert_eq!(result3.at_destination(), false);
+                assert!(!result3.at_destination());
 
                 let (_, result4) = result3.apply_action(&w, Actions::DropOff);
                 println!("4:\n{}", result4.display(&w));
-                assert_eq!(result4.at_destination(), true);
+                assert!(result4.at_destination());
             }
         },
     }
@@ -767,6 +779,54 @@ fn movement_reward() {
     assert!((-1.0 - west_reward).abs() < 1.0e-6);
 }
 
+#[test]
+fn apply_action_returns_successor() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let costs = Costs::default();
+    let world = World::build_from_str(source_world, costs).unwrap();
+    let initial_state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+
+    let expected = [
+        (Actions::North, -1.0, initial_state),
+        (
+            Actions::South,
+            -1.0,
+            State::build(&world, (0, 1), Some('R'), 'G').unwrap(),
+        ),
+        (
+            Actions::East,
+            -1.0,
+            State::build(&world, (1, 0), Some('R'), 'G').unwrap(),
+        ),
+        (Actions::West, -1.0, initial_state),
+        (
+            Actions::PickUp,
+            0.0,
+            State::build(&world, (0, 0), None, 'G').unwrap(),
+        ),
+        (Actions::DropOff, -12.0, initial_state),
+        (Actions::Wait, -1.0, initial_state),
+    ];
+
+    for (action, reward, next_state) in &expected {
+        assert_eq!(
+            initial_state.apply_action(&world, *action),
+            (*reward, *next_state),
+            "{}",
+            action
+        );
+    }
+}
+
 #[test]
 fn correct_pickup_reward() {
     let source_world = "\
@@ -799,6 +859,87 @@ fn correct_pickup_reward() {
     assert!(pickup_reward.abs() < 1.0e-6);
 }
 
+#[test]
+fn correct_dropoff_reward() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let default_world = World::build_from_str(source_world, Costs::default()).unwrap();
+    let state = State::build(&default_world, (2, 0), None, 'G').unwrap();
+    let (reward, delivered) = state.apply_action(&default_world, Actions::DropOff);
+    assert!(reward.abs() < 1.0e-6);
+    assert!(delivered.at_destination());
+    assert!(default_world.max_reward().abs() < 1.0e-6);
+
+    let costs = Costs {
+        delivery_reward: 20.0,
+        ..Costs::default()
+    };
+    let world = World::build_from_str(source_world, costs).unwrap();
+    let state = State::build(&world, (2, 0), None, 'G').unwrap();
+    let (reward, delivered) = state.apply_action(&world, Actions::DropOff);
+    assert!((20.0 - reward).abs() < 1.0e-6);
+    assert!(delivered.at_destination());
+    assert!((20.0 - world.max_reward()).abs() < 1.0e-6);
+}
+
+#[test]
+fn reward_range() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let costs = Costs {
+        delivery_reward: 20.0,
+        ..Costs::default()
+    };
+    let world = World::build_from_str(source_world, costs).unwrap();
+    assert!((20.0 - world.max_reward()).abs() < 1.0e-6);
+    assert!((-12.0 - world.min_reward()).abs() < 1.0e-6);
+
+    // Every reward a step can produce lands in [0, 1].
+    for y in 0..world.height {
+        for x in 0..world.width {
+            for passenger in &[Some('R'), Some('Y'), None] {
+                let state = State::build(&world, (x, y), *passenger, 'G').unwrap();
+                for action in &[
+                    Actions::North,
+                    Actions::South,
+                    Actions::East,
+                    Actions::West,
+                    Actions::PickUp,
+                    Actions::DropOff,
+                    Actions::Wait,
+                ] {
+                    let (reward, _) = state.apply_action(&world, *action);
+                    let normalized = world.normalize_reward(reward);
+                    assert!((0.0..=1.0).contains(&normalized));
+                }
+            }
+        }
+    }
+    assert!(world.normalize_reward(-12.0).abs() < 1.0e-6);
+    assert!((1.0 - world.normalize_reward(20.0)).abs() < 1.0e-6);
+
+    let degenerate = World::build_from_str(source_world, Costs::new(0.0, 0.0, 0.0, 0.0)).unwrap();
+    assert!(degenerate.min_reward().abs() < 1.0e-6);
+    assert!(degenerate.max_reward().abs() < 1.0e-6);
+    assert!(degenerate.normalize_reward(0.0).abs() < 1.0e-6);
+}
+
 #[test]
 fn incorrect_pickup_reward() {
     let source_world = "\
@@ -1043,3 +1184,149 @@ fn state_iterator_walks_all() {
 
     assert_eq!(observed_states.len(), num_states);
 }
+
+#[test]
+fn wait_leaves_state_unchanged() {
+    let source_world = "\
+                        ┌───┐\n\
+                        │R .│\n\
+                        │   │\n\
+                        │. G│\n\
+                        └───┘\n\
+                        ";
+
+    let default_world = World::build_from_str(source_world, Costs::default()).unwrap();
+    let state = State::build(&default_world, (1, 0), Some('R'), 'G').unwrap();
+    let (reward, next_state) = state.apply_action(&default_world, Actions::Wait);
+    assert_eq!(state, next_state);
+    assert!((default_world.costs.movement - reward).abs() < 1.0e-6);
+
+    let costs = Costs {
+        wait: -0.25,
+        ..Costs::default()
+    };
+    let world = World::build_from_str(source_world, costs).unwrap();
+    let state = State::build(&world, (0, 0), None, 'G').unwrap();
+    let (reward, next_state) = state.apply_action(&world, Actions::Wait);
+    assert_eq!(state, next_state);
+    assert!((-0.25 - reward).abs() < 1.0e-6);
+}
+
+#[test]
+fn per_action_costs() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        └─────┘\n\
+                        ";
+
+    let default_world = World::build_from_str(source_world, Costs::default()).unwrap();
+    assert_eq!(default_world.costs.blocked_movement, -1.0);
+    assert_eq!(default_world.costs.pickup, 0.0);
+
+    let costs = Costs {
+        movement: -2.0,
+        blocked_movement: -3.0,
+        pickup: -4.0,
+        miss_pickup: -5.0,
+        miss_dropoff: -6.0,
+        empty_dropoff: -7.0,
+        delivery_reward: 8.0,
+        wait: -9.0,
+    };
+    let world = World::build_from_str(source_world, costs).unwrap();
+
+    let waiting = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+    assert_eq!(waiting.apply_action(&world, Actions::East).0, -2.0);
+    assert_eq!(waiting.apply_action(&world, Actions::North).0, -3.0);
+    assert_eq!(waiting.apply_action(&world, Actions::Wait).0, -9.0);
+    assert_eq!(waiting.apply_action(&world, Actions::DropOff).0, -7.0);
+
+    let (reward, carrying) = waiting.apply_action(&world, Actions::PickUp);
+    assert_eq!(reward, -4.0);
+    assert!(carrying.passenger_in_taxi());
+
+    let off_passenger = State::build(&world, (1, 0), Some('R'), 'G').unwrap();
+    assert_eq!(off_passenger.apply_action(&world, Actions::PickUp).0, -5.0);
+
+    assert_eq!(carrying.apply_action(&world, Actions::DropOff).0, -6.0);
+    let at_destination = State::build(&world, (2, 0), None, 'G').unwrap();
+    assert_eq!(at_destination.apply_action(&world, Actions::DropOff).0, 8.0);
+
+    assert_eq!(world.max_reward(), 8.0);
+    assert_eq!(world.min_reward(), -9.0);
+}
+
+#[test]
+fn goto_moves_along_shortest_path() {
+    let source_world = "\
+                        ┌───┬───┐\n\
+                        │R .│. G│\n\
+                        │   │   │\n\
+                        │. . . .│\n\
+                        └───────┘\n\
+                        ";
+
+    let mut world = World::build_from_str(source_world, Costs::default()).unwrap();
+    let state = State::build(&world, (0, 0), Some('R'), 'G').unwrap();
+
+    // Off by default.
+    let (reward, next) = state.apply_goto(&world, 'G');
+    assert_eq!(reward, world.costs.blocked_movement);
+    assert_eq!(next, state);
+
+    world.goto_actions = true;
+
+    // Around the wall takes 5 moves rather than 3.
+    let (reward, next) = state.apply_goto(&world, 'G');
+    assert_eq!(reward, 5.0 * world.costs.movement);
+    assert_eq!(next.get_taxi(), world.get_fixed_position('G').unwrap());
+    assert_eq!(next.passenger_state(), PassengerState::Waiting('R'));
+
+    let (reward, next) = state.apply_goto(&world, 'R');
+    assert_eq!(reward, world.costs.wait);
+    assert_eq!(next, state);
+
+    let (reward, next) = state.apply_goto(&world, 'Q');
+    assert_eq!(reward, world.costs.blocked_movement);
+    assert_eq!(next, state);
+}
+
+#[test]
+fn per_destination_delivery_rewards() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let mut world = World::build_from_str(source_world, Costs::default()).unwrap();
+    world.set_delivery_reward('G', Some(30.0)).unwrap();
+    world.set_delivery_reward('R', Some(10.0)).unwrap();
+    assert!(world.set_delivery_reward('B', Some(5.0)).is_err());
+
+    assert_eq!(world.delivery_reward('G'), 30.0);
+    assert_eq!(world.delivery_reward('R'), 10.0);
+    assert_eq!(world.delivery_reward('Y'), world.costs.delivery_reward);
+    assert_eq!(world.max_reward(), 30.0);
+    assert_eq!(world.min_reward(), -12.0);
+
+    let deliveries = [('G', (2, 0), 30.0), ('R', (0, 0), 10.0), ('Y', (1, 2), 0.0)];
+    for (id, position, expected) in &deliveries {
+        let state = State::build(&world, *position, None, *id).unwrap();
+        let (reward, delivered) = state.apply_action(&world, Actions::DropOff);
+        assert_eq!(reward, *expected);
+        assert!(delivered.at_destination());
+    }
+
+    // Clearing a destination's reward goes back to the costs.
+    world.set_delivery_reward('G', None).unwrap();
+    assert_eq!(world.delivery_reward('G'), world.costs.delivery_reward);
+    assert_eq!(world.max_reward(), 10.0);
+}
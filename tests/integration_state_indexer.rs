@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use taxi::state::State;
 use taxi::state_indexer::StateIndexer;
 use taxi::world::{Costs, World};
@@ -60,3 +62,169 @@ fn indices_unique() {
         assert!(v);
     }
 }
+
+fn check_round_trip(world: &World, state_indexer: &StateIndexer) {
+    let mut visited_states = vec![false; state_indexer.num_states()];
+
+    for state_index in 0..state_indexer.num_states() {
+        let state = state_indexer.get_state(world, state_index).unwrap();
+        let index = state_indexer.get_index(world, &state).unwrap();
+
+        assert_eq!(index, state_index);
+        assert!(!visited_states[index]);
+        visited_states[index] = true;
+    }
+
+    assert!(state_indexer
+        .get_state(world, state_indexer.num_states())
+        .is_none());
+}
+
+#[test]
+fn wide_world_num_states() {
+    let source_world = "\
+                        ┌─────────────┐\n\
+                        │R . . . . . G│\n\
+                        │             │\n\
+                        │. . . . . . .│\n\
+                        │             │\n\
+                        │. . . . . . .│\n\
+                        └─────────────┘\n\
+                        ";
+
+    let costs = Costs::default();
+    let world = World::build_from_str(source_world, costs).unwrap();
+    assert_eq!(world.width, 7);
+    assert_eq!(world.height, 3);
+
+    let state_indexer = StateIndexer::new(&world);
+
+    // 21 taxi positions * 3 passenger states * 2 destinations
+    assert_eq!(state_indexer.num_states(), 126);
+    check_round_trip(&world, &state_indexer);
+}
+
+#[test]
+fn tall_world_num_states() {
+    let source_world = "\
+                        ┌───┐\n\
+                        │R .│\n\
+                        │   │\n\
+                        │. .│\n\
+                        │   │\n\
+                        │. G│\n\
+                        │   │\n\
+                        │. .│\n\
+                        │   │\n\
+                        │Y .│\n\
+                        │   │\n\
+                        │. B│\n\
+                        │   │\n\
+                        │. .│\n\
+                        └───┘\n\
+                        ";
+
+    let costs = Costs::default();
+    let world = World::build_from_str(source_world, costs).unwrap();
+    assert_eq!(world.width, 2);
+    assert_eq!(world.height, 7);
+
+    let state_indexer = StateIndexer::new(&world);
+
+    // 14 taxi positions * 5 passenger states * 4 destinations
+    assert_eq!(state_indexer.num_states(), 280);
+    check_round_trip(&world, &state_indexer);
+}
+
+#[test]
+fn passenger_cells_num_states() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let costs = Costs::default();
+    let world = World::build_from_str(source_world, costs).unwrap();
+
+    let state_indexer = StateIndexer::with_passenger_cells(&world);
+
+    // 9 taxi positions * 10 passenger states * 3 destinations
+    assert_eq!(state_indexer.num_states(), 270);
+    check_round_trip(&world, &state_indexer);
+
+    let mid_grid = State::build_with_passenger_position(&world, (0, 0), Some((1, 1)), 'G').unwrap();
+    let fixed = State::build(&world, (0, 0), Some('Y'), 'G').unwrap();
+
+    assert!(state_indexer.get_index(&world, &mid_grid).is_some());
+    assert!(state_indexer.get_index(&world, &fixed).is_some());
+
+    // The fixed position indexer has no room for the mid grid passenger.
+    assert!(StateIndexer::new(&world)
+        .get_index(&world, &mid_grid)
+        .is_none());
+}
+
+#[test]
+fn hidden_destination_observations() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │. Y .│\n\
+                        └─────┘\n\
+                        ";
+
+    let costs = Costs::default();
+    let world = World::build_from_str(source_world, costs).unwrap();
+
+    let state_indexer = StateIndexer::with_hidden_destination(&world);
+
+    // 108 states plus 9 taxi positions * 4 passenger states without a
+    // destination.
+    assert_eq!(state_indexer.num_states(), 108);
+    assert_eq!(state_indexer.num_observations(), 144);
+    check_round_trip(&world, &state_indexer);
+
+    let mut waiting_states = HashSet::new();
+    let mut waiting_observations = HashSet::new();
+
+    for state in state_indexer.iter_states(&world) {
+        let state_index = state_indexer.get_index(&world, &state).unwrap();
+        let observation_index = state_indexer.get_observation_index(&world, &state).unwrap();
+        assert!(observation_index < state_indexer.num_observations());
+
+        if state.passenger_in_taxi() || state.at_destination() {
+            assert_eq!(observation_index, state_index);
+        } else {
+            waiting_states.insert(state_index);
+            waiting_observations.insert(observation_index);
+        }
+    }
+
+    // 9 taxi positions * 3 passengers, each waiting for one of the two
+    // other destinations.
+    assert_eq!(waiting_states.len(), 54);
+    assert_eq!(waiting_observations.len(), 27);
+
+    // Only the destination differs, and it is not observed.
+    let to_green = State::build(&world, (1, 1), Some('R'), 'G').unwrap();
+    let to_yellow = State::build(&world, (1, 1), Some('R'), 'Y').unwrap();
+    assert_eq!(
+        state_indexer.get_observation_index(&world, &to_green),
+        state_indexer.get_observation_index(&world, &to_yellow)
+    );
+
+    let full_indexer = StateIndexer::new(&world);
+    assert_eq!(full_indexer.num_observations(), full_indexer.num_states());
+    assert_ne!(
+        full_indexer.get_observation_index(&world, &to_green),
+        full_indexer.get_observation_index(&world, &to_yellow)
+    );
+}
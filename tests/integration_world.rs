@@ -1,5 +1,9 @@
+#[macro_use]
+extern crate assert_matches;
+
 use taxi::actions::Actions;
 use taxi::position::Position;
+use taxi::state::State;
 use taxi::world::*;
 
 #[test]
@@ -27,7 +31,7 @@ fn build_world() {
 
     let costs = Costs::default();
     match World::build_from_str(source, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(world) => {
             assert_eq!(world.width, 5);
             assert_eq!(world.height, 5);
@@ -35,6 +39,24 @@ fn build_world() {
     }
 }
 
+#[test]
+fn build_world_default_costs() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  │   │\n\
+                  │. G│\n\
+                  └───┘\n\
+                  ";
+
+    let world = World::build_from_str_default(source).unwrap();
+    assert_eq!(world.costs, Costs::default());
+    assert_eq!(
+        world,
+        World::build_from_str(source, Costs::default()).unwrap()
+    );
+}
+
 #[test]
 fn wall_move_validity() {
     let source = "\
@@ -53,7 +75,7 @@ fn wall_move_validity() {
 
     let costs = Costs::default();
     match World::build_from_str(source, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(world) => {
             assert_eq!(
                 world.determine_affect(Position::new(2, 2), Actions::North),
@@ -100,7 +122,7 @@ fn edge_action_validity() {
 
     let costs = Costs::default();
     match World::build_from_str(source, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(world) => {
             assert_eq!(
                 world.determine_affect(Position::new(1, 0), Actions::North),
@@ -143,7 +165,7 @@ fn read_fixed_position() {
 
     let costs = Costs::default();
     match World::build_from_str(source, costs) {
-        Err(msg) => panic!(msg),
+        Err(msg) => panic!("{:?}", msg),
         Ok(world) => {
             assert_eq!(world.num_fixed_positions(), 4);
 
@@ -214,6 +236,141 @@ fn no_duplicate_fixed_position() {
     World::build_from_str(source, costs).unwrap();
 }
 
+#[test]
+fn duplicate_fixed_position_reports_location() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  │   │\n\
+                  │. R│\n\
+                  └───┘\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::DuplicateFixedPosition { id: 'R', position },
+            ..
+        }) if position == Position::new(1, 1)
+    );
+}
+
+#[test]
+fn unknown_character_in_cell() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  │   │\n\
+                  │.─G│\n\
+                  └───┘\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::UnknownCharacter {
+                character: '─',
+                line: 4,
+                column: 3
+            },
+            ..
+        })
+    );
+}
+
+#[test]
+fn unknown_character_between_cells() {
+    let source = "\
+                  ┌───┐\n\
+                  │R#.│\n\
+                  │   │\n\
+                  │. G│\n\
+                  └───┘\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::UnknownCharacter {
+                character: '#',
+                line: 2,
+                column: 3
+            },
+            ..
+        })
+    );
+}
+
+#[test]
+fn unknown_character_in_wall_line() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  ││  │\n\
+                  │. G│\n\
+                  └───┘\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::UnknownCharacter {
+                character: '│',
+                line: 3,
+                column: 2
+            },
+            ..
+        })
+    );
+}
+
+#[test]
+fn inconsistent_width() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  │   │\n\
+                  │.│\n\
+                  └───┘\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::LineTooLong {
+                line: 4,
+                num_chars: 3,
+                expected_num_chars: 5
+            },
+            ..
+        })
+    );
+}
+
+#[test]
+fn missing_bottom_border() {
+    let source = "\
+                  ┌───┐\n\
+                  │R .│\n\
+                  │   │\n\
+                  │. G│\n\
+                  ";
+
+    let res = World::build_from_str(source, Costs::default());
+    assert_matches!(
+        res,
+        Err(Error::Parse {
+            error: ParseError::MissingBorder { line: 4 },
+            ..
+        })
+    );
+}
+
 #[test]
 fn pickup_dropoff_validity() {
     let source = "\
@@ -285,3 +442,529 @@ fn output_world() {
 
     assert_eq!(result, source);
 }
+
+#[test]
+fn builder_matches_parsed_world() {
+    let source = "\
+                  ┌───┬─────┐\n\
+                  │R .│. . G│\n\
+                  │   │     │\n\
+                  │. .│. . .│\n\
+                  │         │\n\
+                  │. . . . .│\n\
+                  │         │\n\
+                  │.│. .│. .│\n\
+                  │ │   │   │\n\
+                  │Y│. .│B .│\n\
+                  └─┴───┴───┘\n\
+                  ";
+
+    let parsed = World::build_from_str(source, Costs::default()).unwrap();
+
+    let built = WorldBuilder::new(5, 5)
+        .add_wall(Position::new(1, 0), Side::East)
+        .add_wall(Position::new(1, 1), Side::East)
+        .add_wall(Position::new(0, 3), Side::East)
+        .add_wall(Position::new(3, 3), Side::West)
+        .add_wall(Position::new(0, 4), Side::East)
+        .add_wall(Position::new(2, 4), Side::East)
+        .add_fixed_position('R', Position::new(0, 0))
+        .add_fixed_position('G', Position::new(4, 0))
+        .add_fixed_position('Y', Position::new(0, 4))
+        .add_fixed_position('B', Position::new(3, 4))
+        .build()
+        .unwrap();
+
+    assert_eq!(built.display_strings(), parsed.display_strings());
+    assert_eq!(built, parsed);
+}
+
+#[test]
+fn builder_rejects_invalid_input() {
+    assert_matches!(
+        WorldBuilder::new(2, 2)
+            .add_wall(Position::new(2, 0), Side::North)
+            .build(),
+        Err(Error::Build(BuildError::OutOfBounds { .. }))
+    );
+
+    assert_matches!(
+        WorldBuilder::new(2, 2)
+            .add_fixed_position('R', Position::new(0, 0))
+            .add_fixed_position('R', Position::new(1, 1))
+            .build(),
+        Err(Error::Build(BuildError::DuplicateFixedPosition {
+            id: 'R',
+            ..
+        }))
+    );
+
+    assert_matches!(
+        WorldBuilder::new(0, 2).build(),
+        Err(Error::Build(BuildError::InvalidDimensions { .. }))
+    );
+}
+
+#[test]
+fn build_fails_unreachable_fixed_position() {
+    let source = "\
+                  ┌───┬─────┐\n\
+                  │R .│. . .│\n\
+                  │   │   ┌─┤\n\
+                  │. . . .│G│\n\
+                  │       └─┤\n\
+                  │. . . . .│\n\
+                  └─────────┘\n\
+                  ";
+
+    assert_matches!(
+        World::build_from_str(source, Costs::default()),
+        Err(Error::Unreachable('G'))
+    );
+
+    let open_source = source.replace("│. . . .│G│", "│. . . . G│");
+    assert!(World::build_from_str(&open_source, Costs::default()).is_ok());
+
+    let sealed = WorldBuilder::new(3, 1)
+        .add_fixed_position('R', Position::new(0, 0))
+        .add_fixed_position('G', Position::new(2, 0))
+        .add_wall(Position::new(1, 0), Side::East)
+        .build();
+    assert_matches!(sealed, Err(Error::Unreachable('G')));
+
+    // G is listed first but is still the one named.
+    let sealed_first = "\
+                        ┌─┬───┐\n\
+                        │G│R .│\n\
+                        ├─┘   │\n\
+                        │. . B│\n\
+                        └─────┘\n\
+                        ";
+    assert_matches!(
+        World::build_from_str(sealed_first, Costs::default()),
+        Err(Error::Unreachable('G'))
+    );
+
+    let sealed = WorldBuilder::new(3, 1)
+        .add_fixed_position('G', Position::new(0, 0))
+        .add_fixed_position('R', Position::new(1, 0))
+        .add_fixed_position('B', Position::new(2, 0))
+        .add_wall(Position::new(0, 0), Side::East)
+        .build();
+    assert_matches!(sealed, Err(Error::Unreachable('G')));
+}
+
+#[test]
+fn stranded_cells_are_listed() {
+    let source_world = "\
+                        ┌───┬─┐\n\
+                        │R G│.│\n\
+                        │   │ │\n\
+                        │. .│.│\n\
+                        └───┴─┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+    assert_eq!(
+        w.stranded_cells(),
+        vec![Position::new(2, 0), Position::new(2, 1)]
+    );
+
+    let open = World::build_from_str(&source_world.replace("│R G│.│", "│R G .│"), Costs::default())
+        .unwrap();
+    assert_eq!(open.stranded_cells(), vec![]);
+}
+
+#[test]
+fn diff_describes_mismatches() {
+    let source = "\
+                  ┌───┬───┐\n\
+                  │R .│. G│\n\
+                  │   │   │\n\
+                  │. . . .│\n\
+                  └───────┘\n\
+                  ";
+
+    let world = World::build_from_str(source, Costs::default()).unwrap();
+    let same = World::build_from_str(source, Costs::default()).unwrap();
+    assert_eq!(world, same);
+    assert!(world.diff(&same).is_empty());
+
+    let moved_wall = source.replace("│R .│. G│", "│R . .│G│");
+    let moved_wall = World::build_from_str(&moved_wall, Costs::default()).unwrap();
+    assert_ne!(world, moved_wall);
+    assert_eq!(
+        world.diff(&moved_wall),
+        vec![
+            "(1,0): Wall('ne') != Wall('n')",
+            "(2,0): Wall('nw') != Wall('ne')",
+            "(3,0): Wall('ne') != Wall('new')",
+        ]
+    );
+
+    let renamed = source.replace('G', "B");
+    let renamed = World::build_from_str(&renamed, Costs::default()).unwrap();
+    assert_eq!(
+        world.diff(&renamed),
+        vec!["(3,0): fixed position 'G' != 'B'"]
+    );
+
+    let smaller = "\
+                   ┌───┐\n\
+                   │R G│\n\
+                   └───┘\n\
+                   ";
+    let smaller = World::build_from_str(smaller, Costs::default()).unwrap();
+    assert_eq!(world.diff(&smaller), vec!["dimensions (4,2) != (2,1)"]);
+}
+
+#[test]
+fn diff_reports_every_inequality() {
+    let source = "\
+                  ┌───┬───┐\n\
+                  │R .│. G│\n\
+                  │   │   │\n\
+                  │. . . .│\n\
+                  └───────┘\n\
+                  ";
+
+    let world = World::build_from_str(source, Costs::default()).unwrap();
+
+    let mut variants = Vec::new();
+
+    let mut rewarded = world.clone();
+    rewarded.set_delivery_reward('G', Some(5.0)).unwrap();
+    variants.push(rewarded);
+
+    let mut churning = world.clone();
+    churning.destination_churn = 0.1;
+    variants.push(churning);
+
+    let mut wandering = world.clone();
+    wandering.passenger_wander = 0.1;
+    variants.push(wandering);
+
+    let mut refusing = world.clone();
+    refusing.pickup_refusal = 0.1;
+    variants.push(refusing);
+
+    let mut seeded = world.clone();
+    seeded.env_rng = EnvRng::new(7);
+    variants.push(seeded);
+
+    let mut teleporting = world.clone();
+    teleporting.goto_actions = true;
+    variants.push(teleporting);
+
+    let mut costly = world.clone();
+    costly.costs.movement = -2.0;
+    variants.push(costly);
+
+    for variant in &variants {
+        assert_ne!(&world, variant);
+        assert_eq!(world.diff(variant).len(), 1, "{:?}", world.diff(variant));
+        assert_eq!(variant.diff(&world).len(), 1);
+    }
+
+    assert_eq!(world.diff(&variants[2]), vec!["passenger wander 0 != 0.1"]);
+}
+
+#[test]
+fn distance_follows_walls() {
+    let source_world = "\
+                        ┌───┬───┐\n\
+                        │R .│. G│\n\
+                        │   │   │\n\
+                        │. . . .│\n\
+                        └───────┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    assert_eq!(w.distance(Position::new(0, 0), Position::new(0, 0)), Some(0));
+    assert_eq!(w.distance(Position::new(1, 0), Position::new(2, 0)), Some(3));
+    assert_eq!(w.distance(Position::new(0, 0), Position::new(3, 1)), Some(4));
+    assert_eq!(w.distance(Position::new(0, 0), Position::new(4, 0)), None);
+}
+
+#[test]
+fn optimal_steps_of_bench_probes() {
+    let source_world = "\
+                        ┌─┬───┐\n\
+                        │R│. G│\n\
+                        │ │   │\n\
+                        │. . .│\n\
+                        │     │\n\
+                        │Y B .│\n\
+                        └─────┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    let optimal = |passenger, destination| {
+        let state = State::build(&w, (1, 1), Some(passenger), destination).unwrap();
+        w.optimal_steps(&state)
+    };
+
+    // The limits in benches/training_session.rs count only the moves, each
+    // of these also needs a PickUp and a DropOff.
+    assert_eq!(optimal('Y', 'R'), Some(4 + 2));
+    assert_eq!(optimal('Y', 'G'), Some(6 + 2));
+    assert_eq!(optimal('Y', 'B'), Some(3 + 2));
+    assert_eq!(optimal('R', 'B'), Some(5 + 2));
+    assert_eq!(optimal('G', 'R'), Some(6 + 2));
+    assert_eq!(optimal('B', 'G'), Some(4 + 2));
+
+    let in_taxi = State::build(&w, (0, 0), None, 'R').unwrap();
+    assert_eq!(w.optimal_steps(&in_taxi), Some(1));
+
+    let delivered = State::build(&w, (1, 1), Some('R'), 'R').unwrap();
+    assert_eq!(w.optimal_steps(&delivered), Some(0));
+}
+
+#[test]
+fn optimal_steps_none_when_unsolvable() {
+    let source_world = "\
+                        ┌───┬─┐\n\
+                        │R G│.│\n\
+                        └───┴─┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    let state = State::build(&w, (0, 0), Some('R'), 'G').unwrap();
+    assert_eq!(w.optimal_steps(&state), Some(3));
+
+    // The walls keep this taxi away from every fixed position.
+    let walled_in = State::build(&w, (2, 0), None, 'G').unwrap();
+    assert_eq!(w.optimal_steps(&walled_in), None);
+}
+
+#[test]
+fn toggle_wall_round_trips() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        └─────┘\n\
+                        ";
+    let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+    let original = w.display_strings();
+
+    w.toggle_wall(Position::new(1, 0), Side::East).unwrap();
+
+    // Both cells see the new wall.
+    assert!(w.get_wall(Position::new(1, 0)).east);
+    assert!(w.get_wall(Position::new(2, 0)).west);
+    assert_ne!(w.display_strings(), original);
+    assert_eq!(
+        w.distance(Position::new(1, 0), Position::new(2, 0)),
+        Some(3)
+    );
+
+    // Toggled from the other side, so the west wall of the neighbour.
+    w.toggle_wall(Position::new(2, 0), Side::West).unwrap();
+
+    assert!(!w.get_wall(Position::new(1, 0)).east);
+    assert_eq!(w.display_strings(), original);
+}
+
+#[test]
+fn toggle_wall_refuses_bad_edits() {
+    let source_world = "\
+                        ┌─────┐\n\
+                        │R . G│\n\
+                        │     │\n\
+                        │. . .│\n\
+                        └─────┘\n\
+                        ";
+    let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+    let original = w.clone();
+
+    assert_matches!(
+        w.toggle_wall(Position::new(0, 0), Side::North),
+        Err(Error::Edit(EditError::BorderWall { .. }))
+    );
+    assert_matches!(
+        w.toggle_wall(Position::new(3, 0), Side::West),
+        Err(Error::Edit(EditError::OutOfBounds { .. }))
+    );
+
+    // With walls north and west of (1,1), one to the east would shut it in.
+    w.toggle_wall(Position::new(1, 1), Side::North).unwrap();
+    w.toggle_wall(Position::new(1, 1), Side::West).unwrap();
+    assert_matches!(
+        w.toggle_wall(Position::new(1, 1), Side::East),
+        Err(Error::Edit(EditError::Disconnects { .. }))
+    );
+    assert!(!w.get_wall(Position::new(1, 1)).east);
+    assert!(!w.get_wall(Position::new(2, 1)).west);
+
+    w.toggle_wall(Position::new(1, 1), Side::North).unwrap();
+    w.toggle_wall(Position::new(1, 1), Side::West).unwrap();
+    assert_eq!(w, original);
+}
+
+#[test]
+fn move_fixed_position_checks() {
+    let source_world = "\
+                        ┌───┬─┐\n\
+                        │R G│.│\n\
+                        │   │ │\n\
+                        │. .│.│\n\
+                        └───┴─┘\n\
+                        ";
+    let mut w = World::build_from_str(source_world, Costs::default()).unwrap();
+    let original = w.clone();
+
+    w.move_fixed_position('R', Position::new(0, 1)).unwrap();
+    assert_eq!(w.get_fixed_position('R'), Some(Position::new(0, 1)));
+    assert_eq!(w.get_fixed_id(Position::new(0, 0)), None);
+
+    assert_matches!(
+        w.move_fixed_position('R', Position::new(1, 0)),
+        Err(Error::Edit(EditError::Occupied { id: 'G', .. }))
+    );
+    assert_matches!(
+        w.move_fixed_position('Y', Position::new(1, 1)),
+        Err(Error::Edit(EditError::UnknownFixedPosition { id: 'Y' }))
+    );
+    assert_matches!(
+        w.move_fixed_position('R', Position::new(5, 1)),
+        Err(Error::Edit(EditError::OutOfBounds { .. }))
+    );
+
+    // The right column is walled off from the rest.
+    assert_matches!(
+        w.move_fixed_position('R', Position::new(2, 1)),
+        Err(Error::Unreachable(_))
+    );
+    assert_eq!(w.get_fixed_position('R'), Some(Position::new(0, 1)));
+
+    w.move_fixed_position('R', Position::new(0, 0)).unwrap();
+    assert_eq!(w, original);
+}
+
+#[test]
+fn crop_to_bbox_keeps_walls() {
+    let source_world = "\
+                        ┌───┬─────┐\n\
+                        │R .│. . G│\n\
+                        │   │     │\n\
+                        │. .│. . .│\n\
+                        │         │\n\
+                        │. . . . .│\n\
+                        │         │\n\
+                        │.│. .│. .│\n\
+                        │ │   │   │\n\
+                        │Y│. .│B .│\n\
+                        └─┴───┴───┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    let kept = [Position::new(0, 2), Position::new(1, 3), Position::new(2, 4)];
+    let (cropped, translate) = w.crop_to_bbox(&kept).unwrap();
+
+    // The wall beside Y is kept, and the open cells to the east and north
+    // are walled off.
+    let expected_world = "\
+                          ┌─────┐\n\
+                          │. . .│\n\
+                          │     │\n\
+                          │.│. .│\n\
+                          │ │   │\n\
+                          │Y│. .│\n\
+                          └─┴───┘\n\
+                          ";
+    let expected = World::build_from_str(expected_world, Costs::default()).unwrap();
+    assert_eq!(cropped, expected);
+
+    assert_eq!(translate(Position::new(1, 3)), Position::new(1, 1));
+    assert_eq!(
+        translate(w.get_fixed_position('Y').unwrap()),
+        cropped.get_fixed_position('Y').unwrap()
+    );
+    assert_eq!(cropped.get_fixed_position('B'), None);
+
+    let taxi = translate(Position::new(2, 2));
+    let state = State::build(&cropped, (taxi.x, taxi.y), None, 'Y').unwrap();
+    assert_eq!(cropped.optimal_steps(&state), Some(4 + 1));
+}
+
+#[test]
+fn crop_to_bbox_checks_positions() {
+    let source_world = "\
+                        ┌───┬─────┐\n\
+                        │R .│. . G│\n\
+                        │   │     │\n\
+                        │. .│. . .│\n\
+                        │         │\n\
+                        │. . . . .│\n\
+                        │         │\n\
+                        │.│. .│. .│\n\
+                        │ │   │   │\n\
+                        │Y│. .│B .│\n\
+                        └─┴───┴───┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    assert_matches!(
+        w.crop_to_bbox(&[]).map(|(cropped, _)| cropped),
+        Err(Error::Build(BuildError::InvalidDimensions { .. }))
+    );
+    assert_matches!(
+        w.crop_to_bbox(&[Position::new(0, 0), Position::new(5, 0)]).map(|(cropped, _)| cropped),
+        Err(Error::Build(BuildError::OutOfBounds { .. }))
+    );
+
+    // Without the open row above, Y is cut off from B.
+    assert_matches!(
+        w.crop_to_bbox(&[Position::new(0, 3), Position::new(3, 4)]).map(|(cropped, _)| cropped),
+        Err(Error::Unreachable(_))
+    );
+}
+
+#[test]
+fn ascii_display_matches_layout() {
+    let source_world = "\
+                        ┌───┬─────┐\n\
+                        │R .│. . G│\n\
+                        │   │     │\n\
+                        │. .│. . .│\n\
+                        │  ─┘     │\n\
+                        │. . . . .│\n\
+                        │         │\n\
+                        │.│. .│. .│\n\
+                        │ │   │   │\n\
+                        │Y│. .│B .│\n\
+                        └─┴───┴───┘\n\
+                        ";
+    let w = World::build_from_str(source_world, Costs::default()).unwrap();
+
+    let unicode = w.display_strings();
+    let ascii = w.display_strings_ascii();
+
+    assert_eq!(
+        ascii,
+        vec![
+            "+---+-----+",
+            "|R .|. . G|",
+            "|   |     |",
+            "|. .|. . .|",
+            "|  -+     |",
+            "|. . . . .|",
+            "|         |",
+            "|.|. .|. .|",
+            "| |   |   |",
+            "|Y|. .|B .|",
+            "+-+---+---+",
+        ]
+    );
+
+    // Every character lines up, and only the walls change.
+    assert_eq!(ascii.len(), unicode.len());
+    for (ascii_line, unicode_line) in ascii.iter().zip(&unicode) {
+        assert_eq!(ascii_line.chars().count(), unicode_line.chars().count());
+
+        for (a, u) in ascii_line.chars().zip(unicode_line.chars()) {
+            assert!(a == u || ('\u{2500}'..='\u{257f}').contains(&u) && "+-|".contains(a));
+        }
+    }
+}